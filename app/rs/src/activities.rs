@@ -0,0 +1,64 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::error;
+
+use crate::guest;
+
+// dumpsys takes a while, automation polling for a screen shouldn't run it on every call
+const CACHE_TTL: Duration = Duration::from_secs(1);
+
+static CACHE: Mutex<Option<(Instant, Vec<String>)>> = Mutex::new(None);
+
+/// The guest activity stack as `t<task> <component>`, topmost first, empty if the guest isn't ready.
+pub fn activity_stack() -> Vec<String> {
+    let mut cache = CACHE.lock().unwrap();
+    if let Some((at, stack)) = cache.as_ref() {
+        if at.elapsed() < CACHE_TTL {
+            return stack.clone();
+        }
+    }
+
+    let output = match guest::shell("dumpsys activity activities") {
+        Some(output) if output.success => output.stdout,
+        Some(output) => {
+            error!("dump guest activities failed: {}", output.stderr.trim());
+            return Vec::new();
+        }
+        None => return Vec::new(),
+    };
+
+    let stack = parse_stack(&output);
+    *cache = Some((Instant::now(), stack.clone()));
+    stack
+}
+
+/// The topmost guest activity as `t<task> <component>`, `None` if the guest isn't ready.
+pub fn top_activity() -> Option<String> {
+    activity_stack().into_iter().next()
+}
+
+// Tasks are dumped top down and each task's history from its top:
+// * Hist #0: ActivityRecord{8c1d2e5 u0 com.android.launcher3/.Launcher t12}
+fn parse_stack(dump: &str) -> Vec<String> {
+    dump.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if !line.starts_with("* Hist #") {
+                return None;
+            }
+            let record = line.split_once("ActivityRecord{")?.1;
+            let record = &record[..record.find('}')?];
+            let component = record.split_whitespace().find(|token| token.contains('/'))?;
+            let task = record
+                .split_whitespace()
+                .find(|token| token.len() > 1 && token.starts_with('t') && token[1..].chars().all(|c| c.is_ascii_digit()))
+                .unwrap_or("t?");
+            Some(format!("{} {}", task, component))
+        })
+        .collect()
+}
@@ -0,0 +1,83 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use once_cell::sync::Lazy;
+use unix_socket::{UnixListener, UnixStream};
+
+use log::{error, info};
+
+const BIOMETRIC_PATH: &'static str = "/data/data/io.twoyi/rootfs/dev/socket/biometric";
+
+// protocol with the guest biometric HAL stub, one byte per message.
+const MSG_AUTH_REQUEST: u8 = 1;
+const MSG_AUTH_CANCEL: u8 = 2;
+const MSG_AUTH_SUCCESS: u8 = 0x10;
+const MSG_AUTH_FAILURE: u8 = 0x11;
+
+static HAL_STREAM: Lazy<Mutex<Option<UnixStream>>> = Lazy::new(|| Mutex::new(None));
+static AUTH_PENDING: AtomicBool = AtomicBool::new(false);
+
+pub fn start_biometric_server() {
+    thread::spawn(|| {
+        biometric_server();
+    });
+}
+
+/// Delivers the host authentication result to the guest HAL if it is waiting for one.
+pub fn send_result(success: bool) {
+    if !AUTH_PENDING.swap(false, Ordering::AcqRel) {
+        info!("biometric result {} ignored, guest didn't request auth", success);
+        return;
+    }
+
+    let msg = if success { MSG_AUTH_SUCCESS } else { MSG_AUTH_FAILURE };
+    if let Some(ref mut stream) = *HAL_STREAM.lock().unwrap() {
+        if let Err(e) = stream.write_all(&[msg]) {
+            error!("send biometric result failed: {}", e);
+        }
+    }
+}
+
+fn biometric_server() {
+    let _ = std::fs::remove_file(BIOMETRIC_PATH);
+    let listener = match UnixListener::bind(BIOMETRIC_PATH) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("bind biometric socket failed: {}", e);
+            return;
+        }
+    };
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(s) => s,
+            Err(_) => break,
+        };
+
+        info!("biometric hal connected!");
+        AUTH_PENDING.store(false, Ordering::Release);
+        *HAL_STREAM.lock().unwrap() = stream.try_clone().ok();
+
+        let mut buf = [0u8; 1];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(1) => match buf[0] {
+                    MSG_AUTH_REQUEST => AUTH_PENDING.store(true, Ordering::Release),
+                    MSG_AUTH_CANCEL => AUTH_PENDING.store(false, Ordering::Release),
+                    other => error!("unknown biometric message: {}", other),
+                },
+                _ => break,
+            }
+        }
+
+        info!("biometric hal disconnected");
+        AUTH_PENDING.store(false, Ordering::Release);
+        *HAL_STREAM.lock().unwrap() = None;
+    }
+}
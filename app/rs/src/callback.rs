@@ -0,0 +1,93 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use jni::objects::{GlobalRef, JClass, JValue, JValueOwned};
+use jni::{JNIEnv, JavaVM};
+use once_cell::sync::OnceCell;
+
+use log::error;
+
+static JVM: OnceCell<JavaVM> = OnceCell::new();
+
+// threads attached from native can't find app classes, keep the class from JNI_OnLoad.
+static RENDERER_CLASS: OnceCell<GlobalRef> = OnceCell::new();
+
+pub fn init(env: &mut JNIEnv, clazz: &JClass) {
+    if let Ok(vm) = env.get_java_vm() {
+        let _ = JVM.set(vm);
+    }
+    if let Ok(class) = env.new_global_ref(clazz) {
+        let _ = RENDERER_CLASS.set(class);
+    }
+}
+
+/// Calls the static method `name` of `io.twoyi.Renderer`, from any thread.
+pub fn call_static(name: &str, sig: &str, args: &[JValue]) -> bool {
+    let mut called = false;
+    with_env(|env| {
+        called = call_static_in(env, name, sig, args);
+    });
+    called
+}
+
+/// Runs `f` with an env attached to the current thread, for callbacks that
+/// need to build their arguments (arrays, strings) first.
+pub fn with_env<F: FnOnce(&mut JNIEnv)>(f: F) {
+    let vm = match JVM.get() {
+        Some(vm) => vm,
+        None => {
+            error!("callback before JNI_OnLoad");
+            return;
+        }
+    };
+
+    match vm.attach_current_thread_permanently() {
+        Ok(mut env) => f(&mut env),
+        Err(e) => error!("attach thread failed: {:?}", e),
+    }
+}
+
+pub fn call_static_in(env: &mut JNIEnv, name: &str, sig: &str, args: &[JValue]) -> bool {
+    call_static_value(env, name, sig, args).is_some()
+}
+
+/// Like `call_static_in`, for methods whose return value is needed.
+pub fn call_static_value<'local>(
+    env: &mut JNIEnv<'local>,
+    name: &str,
+    sig: &str,
+    args: &[JValue],
+) -> Option<JValueOwned<'local>> {
+    let class = RENDERER_CLASS.get()?;
+
+    let clazz: &JClass = class.as_obj().into();
+    match env.call_static_method(clazz, name, sig, args) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            error!("callback {} failed: {:?}", name, e);
+            let _ = env.exception_clear();
+            None
+        }
+    }
+}
+
+/// Calls a static `()F` method of `io.twoyi.Renderer`, `None` if it failed.
+pub fn call_static_float(name: &str) -> Option<f32> {
+    let mut value = None;
+    with_env(|env| {
+        let class = match RENDERER_CLASS.get() {
+            Some(class) => class,
+            None => return,
+        };
+        let clazz: &JClass = class.as_obj().into();
+        match env.call_static_method(clazz, name, "()F", &[]).and_then(|v| v.f()) {
+            Ok(v) => value = Some(v),
+            Err(e) => {
+                error!("callback {} failed: {:?}", name, e);
+                let _ = env.exception_clear();
+            }
+        }
+    });
+    value
+}
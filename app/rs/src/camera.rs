@@ -0,0 +1,219 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use jni::objects::JValue;
+use once_cell::sync::Lazy;
+use unix_socket::{UnixListener, UnixStream};
+
+use log::{error, info};
+
+use crate::callback;
+
+const CAMERA_PATH: &'static str = "/data/data/io.twoyi/rootfs/dev/socket/camera";
+
+// android.graphics.ImageFormat.NV21, what the guest camera HAL consumes
+const FORMAT_NV21: u32 = 0x11;
+
+// a slow guest must not stall the host thread pushing frames, drop the frame instead
+const FRAME_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+// until the guest asks for a size
+const DEFAULT_FRAME_SIZE: (usize, usize) = (640, 480);
+// frames are scaled to the guest size, which the guest must not make arbitrarily large
+const MAX_FRAME_SIDE: usize = 4096;
+
+static CAMERA_STREAM: Lazy<Mutex<Option<UnixStream>>> = Lazy::new(|| Mutex::new(None));
+
+// size the guest HAL configured its stream with, frames are scaled to it
+static REQUESTED_SIZE: Mutex<(usize, usize)> = Mutex::new(DEFAULT_FRAME_SIZE);
+
+// host camera currently forwarded to the guest
+static FORWARDED_CAMERA: Mutex<Option<i32>> = Mutex::new(None);
+
+pub fn start_camera_server() {
+    thread::spawn(|| {
+        camera_server();
+    });
+}
+
+fn camera_server() {
+    let _ = std::fs::remove_file(CAMERA_PATH);
+    let listener = match UnixListener::bind(CAMERA_PATH) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("bind camera socket failed: {}", e);
+            return;
+        }
+    };
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(s) => s,
+            Err(_) => break,
+        };
+
+        info!("camera hal connected!");
+        let _ = stream.set_write_timeout(Some(FRAME_WRITE_TIMEOUT));
+        *CAMERA_STREAM.lock().unwrap() = stream.try_clone().ok();
+
+        // the HAL configures its stream with [u32 width][u32 height]
+        let mut buf = [0u8; 8];
+        while stream.read_exact(&mut buf).is_ok() {
+            let width = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+            let height = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+            on_guest_configure(width, height);
+        }
+
+        info!("camera hal disconnected");
+        *CAMERA_STREAM.lock().unwrap() = None;
+    }
+}
+
+fn on_guest_configure(width: usize, height: usize) {
+    if width == 0 || height == 0 || width > MAX_FRAME_SIDE || height > MAX_FRAME_SIDE {
+        error!("guest camera asked for invalid size {}x{}", width, height);
+        return;
+    }
+
+    info!("guest camera configured {}x{}", width, height);
+    *REQUESTED_SIZE.lock().unwrap() = (width, height);
+
+    // let the host reopen its camera with the closest size it supports
+    let forwarded = *FORWARDED_CAMERA.lock().unwrap();
+    if let Some(id) = forwarded {
+        notify_start(id);
+    }
+}
+
+/// Asks the host to open camera `id` and push its frames with `push_frame`.
+///
+/// The host gets the size the guest wants and picks the closest one it can
+/// provide, frames are scaled to the exact guest size here. The host side is
+/// responsible for the CAMERA permission and must not open the camera without it.
+pub fn start_forward(id: i32) {
+    let previous = FORWARDED_CAMERA.lock().unwrap().replace(id);
+    if let Some(previous) = previous {
+        if previous != id {
+            callback::call_static("onStopCameraForward", "(I)V", &[JValue::Int(previous)]);
+        }
+    }
+
+    info!("start camera forward: {}", id);
+    notify_start(id);
+}
+
+pub fn stop_forward(id: i32) {
+    let mut forwarded = FORWARDED_CAMERA.lock().unwrap();
+    if *forwarded != Some(id) {
+        info!("camera {} isn't forwarded, ignore", id);
+        return;
+    }
+    *forwarded = None;
+    drop(forwarded);
+
+    info!("stop camera forward: {}", id);
+    callback::call_static("onStopCameraForward", "(I)V", &[JValue::Int(id)]);
+}
+
+fn notify_start(id: i32) {
+    let (width, height) = *REQUESTED_SIZE.lock().unwrap();
+    callback::call_static(
+        "onStartCameraForward",
+        "(III)V",
+        &[JValue::Int(id), JValue::Int(width as i32), JValue::Int(height as i32)],
+    );
+}
+
+/// Sends an RGBA frame to the guest camera HAL as NV21.
+///
+/// Each frame is `[u32 width][u32 height][u32 format][u32 length]` followed by
+/// `length` bytes, little endian. Rows of `rgba` may be padded, the stride is
+/// derived from the buffer length; odd sizes are cropped to even for the chroma planes.
+pub fn push_frame(rgba: &[u8], width: i32, height: i32) -> bool {
+    if width <= 0 || height <= 0 {
+        error!("invalid frame size: {}x{}", width, height);
+        return false;
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    let stride = rgba.len() / height;
+    if stride < width * 4 {
+        error!("frame too small: {} bytes for {}x{}", rgba.len(), width, height);
+        return false;
+    }
+
+    let mut guard = CAMERA_STREAM.lock().unwrap();
+    let stream = match guard.as_mut() {
+        Some(s) => s,
+        None => return false,
+    };
+
+    let (req_width, req_height) = *REQUESTED_SIZE.lock().unwrap();
+    let scaled;
+    let (rgba, stride, width, height) = if (width, height) != (req_width, req_height) {
+        scaled = scale_rgba(rgba, stride, width, height, req_width, req_height);
+        (&scaled[..], req_width * 4, req_width, req_height)
+    } else {
+        (rgba, stride, width, height)
+    };
+
+    let (w, h) = (width & !1, height & !1);
+    let frame = rgba_to_nv21(rgba, stride, w, h);
+
+    let mut header = Vec::with_capacity(16);
+    header.extend_from_slice(&(w as u32).to_le_bytes());
+    header.extend_from_slice(&(h as u32).to_le_bytes());
+    header.extend_from_slice(&FORMAT_NV21.to_le_bytes());
+    header.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+
+    if let Err(e) = stream.write_all(&header).and_then(|_| stream.write_all(&frame)) {
+        // a partial frame desyncs the stream, let the HAL reconnect
+        error!("push frame failed: {}", e);
+        *guard = None;
+        return false;
+    }
+    true
+}
+
+// Nearest neighbour, the output rows are tightly packed.
+fn scale_rgba(src: &[u8], stride: usize, width: usize, height: usize, dst_width: usize, dst_height: usize) -> Vec<u8> {
+    let mut out = vec![0u8; dst_width * dst_height * 4];
+    for row in 0..dst_height {
+        let src_row = row * height / dst_height;
+        for col in 0..dst_width {
+            let src_col = col * width / dst_width;
+            let i = src_row * stride + src_col * 4;
+            let o = (row * dst_width + col) * 4;
+            out[o..o + 4].copy_from_slice(&src[i..i + 4]);
+        }
+    }
+    out
+}
+
+// BT.601 limited range, chroma sampled at the top left pixel of each 2x2 block.
+fn rgba_to_nv21(rgba: &[u8], stride: usize, width: usize, height: usize) -> Vec<u8> {
+    let mut out = vec![0u8; width * height * 3 / 2];
+    let (y_plane, vu_plane) = out.split_at_mut(width * height);
+
+    for row in 0..height {
+        for col in 0..width {
+            let i = row * stride + col * 4;
+            let (r, g, b) = (rgba[i] as i32, rgba[i + 1] as i32, rgba[i + 2] as i32);
+
+            y_plane[row * width + col] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+
+            if row % 2 == 0 && col % 2 == 0 {
+                let vu = (row / 2) * width + col;
+                vu_plane[vu] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+                vu_plane[vu + 1] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,58 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use log::error;
+
+const CONFIG_DIR: &'static str = "/data/data/io.twoyi";
+
+pub const RENDERER_CONF: &'static str = "renderer.conf";
+pub const INPUT_CONF: &'static str = "input.conf";
+pub const CONTAINER_CONF: &'static str = "container.conf";
+
+// config files are tiny `key=value` files, serialize writers so updates don't get lost.
+static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+
+fn config_path(file: &str) -> PathBuf {
+    PathBuf::from(CONFIG_DIR).join(file)
+}
+
+pub fn get(file: &str, key: &str) -> Option<String> {
+    let content = fs::read_to_string(config_path(file)).ok()?;
+    content.lines().find_map(|line| match line.split_once('=') {
+        Some((k, v)) if k.trim() == key => Some(v.trim().to_string()),
+        _ => None,
+    })
+}
+
+pub fn get_or<T: FromStr>(file: &str, key: &str, default: T) -> T {
+    get(file, key).and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+pub fn set(file: &str, key: &str, value: &str) -> bool {
+    let _lock = CONFIG_LOCK.lock().unwrap();
+
+    let path = config_path(file);
+    let content = fs::read_to_string(&path).unwrap_or_default();
+    let mut lines: Vec<String> = content
+        .lines()
+        .filter(|line| match line.split_once('=') {
+            Some((k, _)) => k.trim() != key,
+            None => !line.trim().is_empty(),
+        })
+        .map(|line| line.to_string())
+        .collect();
+    lines.push(format!("{}={}", key, value));
+    lines.push(String::new());
+
+    if let Err(e) = fs::write(&path, lines.join("\n")) {
+        error!("write {} failed: {}", path.display(), e);
+        return false;
+    }
+    true
+}
@@ -0,0 +1,760 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+
+use log::{error, info, warn};
+
+use crate::callback;
+use crate::config;
+use crate::guest;
+use crate::power;
+use crate::storage;
+
+const WORKING_DIR: &'static str = "/data/data/io.twoyi/rootfs";
+const LOG_PATH: &'static str = "/data/data/io.twoyi/log.txt";
+const GUEST_DATA_PATH: &'static str = "/data/data/io.twoyi/rootfs/data";
+
+// pid of the running guest init, it outlives the app process when that gets killed
+const PID_PATH: &'static str = "/data/data/io.twoyi/guest.pid";
+const STALE_KILL_TIMEOUT: Duration = Duration::from_secs(3);
+
+const DEFAULT_INIT: &'static str = "./init";
+const INIT_ENV: &'static str = "TWOYI_INIT";
+
+// Known failure signatures in log.txt, most specific first: the first line containing all parts
+// of one is the diagnosis. Denials and crashes of single apps show up in every boot, so only
+// what stops init itself or the kernel killing the guest counts.
+const BOOT_ERROR_SIGNATURES: [(&[&'static str], &'static str); 6] = [
+    (&["Exec format error"], "guest init is not built for this device's ABI"),
+    (&["Out of memory: Kill"], "the host killed the guest, out of memory"),
+    // "Unable to load SELinux policy", not "Loading SELinux policy"
+    (&["init: ", "load SELinux policy"], "blocked by SELinux, guest init couldn't load its policy"),
+    (&["init: ", "exec", "Permission denied"], "guest init couldn't start a program, check the rootfs permissions"),
+    (&["init: ", "received signal 11"], "a guest service crashed (segfault)"),
+    (&["init: ", "InitFatalReboot"], "guest init hit a fatal error"),
+];
+
+// surfaceflinger reads it before starting bootanim, so it has to be in the prop files at boot
+const NO_BOOT_ANIM_PROP: &'static str = "debug.sf.nobootanimation";
+
+// the running guest writes to log.txt
+static GUEST_LOGGING: AtomicBool = AtomicBool::new(false);
+
+static GUEST_INIT: Lazy<Mutex<Option<Child>>> = Lazy::new(|| Mutex::new(None));
+
+// loader of the last start, a reboot starts the guest with it again
+static LOADER_PATH: Mutex<Option<String>> = Mutex::new(None);
+
+// extra arguments for the guest init, for guest builds that take flags
+static INIT_ARGS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+const MAX_INIT_ARGS: usize = 32;
+const MAX_INIT_ARG_LEN: usize = 256;
+
+// The guest entrypoint, from `TWOYI_INIT`, then `init` in container.conf, relative to the rootfs.
+pub fn init_binary() -> String {
+    std::env::var(INIT_ENV)
+        .ok()
+        .or_else(|| config::get(config::CONTAINER_CONF, "init"))
+        .filter(|init| !init.is_empty())
+        .unwrap_or_else(|| DEFAULT_INIT.to_string())
+}
+
+/// Sets the arguments passed to the guest init on the next start, an empty list clears them.
+/// The whole list is rejected if any argument is unsafe.
+pub fn set_init_args(args: Vec<String>) -> bool {
+    if args.len() > MAX_INIT_ARGS {
+        error!("too many guest init arguments: {}", args.len());
+        return false;
+    }
+    if let Some(arg) = args.iter().find(|arg| !is_safe_init_arg(arg)) {
+        error!("rejecting guest init argument {:?}", arg);
+        return false;
+    }
+    info!("guest init arguments: {:?}", args);
+    *INIT_ARGS.lock().unwrap() = args;
+    true
+}
+
+// init is exec'd directly without a shell, so only empty, huge or control character args are refused.
+fn is_safe_init_arg(arg: &str) -> bool {
+    !arg.is_empty() && arg.len() <= MAX_INIT_ARG_LEN && !arg.chars().any(|c| c.is_control())
+}
+
+// Names the guest after the host model when `auto_hostname` is set in container.conf.
+// Only the prop can be set before boot.
+fn apply_auto_hostname() {
+    if !config::get_or(config::CONTAINER_CONF, "auto_hostname", false) {
+        return;
+    }
+    match guest::host_hostname() {
+        Some(name) => {
+            if guest::write_props(&[("net.hostname", &name)]) {
+                info!("guest hostname: {}", name);
+            }
+        }
+        None => error!("host model isn't a valid hostname"),
+    }
+}
+
+const BOOT_SETTINGS_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Settings only the booted guest accepts, from the container.conf flags:
+// `auto_font_scale` gives the guest the host font scale, `performance_mode` turns off animations.
+fn apply_boot_settings() {
+    let font_scale = config::get_or(config::CONTAINER_CONF, "auto_font_scale", false);
+    let performance = config::get_or(config::CONTAINER_CONF, "performance_mode", false);
+    if !font_scale && !performance {
+        return;
+    }
+    if !guest::wait_for_boot(BOOT_SETTINGS_TIMEOUT) {
+        error!("guest didn't boot, boot settings not applied");
+        return;
+    }
+
+    if font_scale {
+        if let Some(scale) = callback::call_static_float("hostFontScale") {
+            guest::set_font_scale(scale.clamp(guest::MIN_FONT_SCALE, guest::MAX_FONT_SCALE));
+        }
+    }
+    if performance {
+        guest::set_animation_scale(0.0);
+    }
+}
+
+// Syncs `skip_boot_anim` from container.conf into the guest props before init reads them.
+fn apply_boot_anim_option() {
+    let skip = config::get_or(config::CONTAINER_CONF, "skip_boot_anim", false);
+    let value = if skip { "1" } else { "0" };
+    let current = guest::read_prop(NO_BOOT_ANIM_PROP);
+    if current.as_deref() == Some(value) || (!skip && current.is_none()) {
+        info!("skip boot animation: {}", skip);
+        return;
+    }
+
+    if guest::write_props(&[(NO_BOOT_ANIM_PROP, value)]) {
+        info!("skip boot animation: {}", skip);
+    } else {
+        error!("skip boot animation: {} not applied, {} couldn't be written", skip, NO_BOOT_ANIM_PROP);
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(meta) => meta.is_file() && meta.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StartStatus {
+    Started,
+    // started, but log.txt couldn't be created so the guest output is discarded
+    StartedWithoutLog,
+    AlreadyRunning,
+    Failed,
+}
+
+impl StartStatus {
+    /// The `START_*` code reported to Java.
+    pub fn code(&self) -> i32 {
+        match self {
+            StartStatus::Started => 0,
+            StartStatus::StartedWithoutLog => 1,
+            StartStatus::AlreadyRunning => 2,
+            StartStatus::Failed => 3,
+        }
+    }
+}
+
+fn is_pid_alive(pid: i32) -> bool {
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+// A recorded pid only belongs to a guest if the process still runs in the rootfs,
+// otherwise it was reused by something else.
+fn is_guest_process(pid: i32) -> bool {
+    fs::read_link(format!("/proc/{}/cwd", pid))
+        .map(|cwd| cwd == Path::new(WORKING_DIR))
+        .unwrap_or(false)
+}
+
+// Makes sure no guest from an earlier app process is still running, false if one survives.
+fn reap_stale_instance() -> bool {
+    let pid: i32 = match fs::read_to_string(PID_PATH).ok().and_then(|s| s.trim().parse().ok()) {
+        Some(pid) => pid,
+        None => return true,
+    };
+
+    if pid > 0 && is_pid_alive(pid) && is_guest_process(pid) {
+        info!("killing stale guest instance {}", pid);
+        unsafe { libc::kill(-pid, libc::SIGKILL) };
+
+        // not our child, so no wait(); poll until it is gone
+        let start = Instant::now();
+        while is_pid_alive(pid) {
+            if start.elapsed() > STALE_KILL_TIMEOUT {
+                error!("stale guest instance {} doesn't die", pid);
+                return false;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    info!("removing stale guest lock of {}", pid);
+    let _ = fs::remove_file(PID_PATH);
+    true
+}
+
+// Soft limits for the guest, from container.conf or the hard limit: an Android guest
+// running many apps outgrows the default open files and processes.
+fn guest_rlimits() -> Vec<(libc::c_int, libc::rlimit)> {
+    let resources = [
+        (libc::RLIMIT_NOFILE, "nofile_limit"),
+        (libc::RLIMIT_NPROC, "nproc_limit"),
+    ];
+
+    let mut limits = Vec::with_capacity(resources.len());
+    for (resource, key) in resources {
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+            error!("getrlimit {} failed: {}", key, io::Error::last_os_error());
+            continue;
+        }
+        let requested: u64 = config::get_or(config::CONTAINER_CONF, key, 0);
+        let soft = if requested == 0 {
+            limit.rlim_max
+        } else {
+            (requested as libc::rlim_t).min(limit.rlim_max)
+        };
+        info!("guest {}: {} (was {}, hard {})", key, soft, limit.rlim_cur, limit.rlim_max);
+        limit.rlim_cur = soft;
+        limits.push((resource, limit));
+    }
+    limits
+}
+
+/// Spawns the guest init, its output goes to `log.txt` or nowhere if that can't be created.
+///
+/// Only one guest may run at a time: one started by this process is reported as
+/// running, one left behind by an earlier app process is killed first.
+pub fn container_start(loader_path: &str) -> StartStatus {
+    // held until the child is stored, two starts must not both spawn a guest
+    let mut guest_init = GUEST_INIT.lock().unwrap();
+    if child_alive(&mut guest_init) {
+        error!("guest is already running");
+        return StartStatus::AlreadyRunning;
+    }
+    if !reap_stale_instance() {
+        return StartStatus::AlreadyRunning;
+    }
+
+    let init = init_binary();
+    if !is_executable(&Path::new(WORKING_DIR).join(&init)) {
+        error!("guest init {} doesn't exist or isn't executable in {}", init, WORKING_DIR);
+        return StartStatus::Failed;
+    }
+
+    apply_boot_anim_option();
+    apply_auto_hostname();
+
+    // the guest booting matters more than its log
+    let log = File::create(LOG_PATH).and_then(|outputs| Ok((outputs.try_clone()?, outputs)));
+    let (outputs, errors, logging) = match log {
+        Ok((outputs, errors)) => (Stdio::from(outputs), Stdio::from(errors), true),
+        Err(e) => {
+            error!("create {} failed: {}, guest logging is disabled", LOG_PATH, e);
+            (Stdio::null(), Stdio::null(), false)
+        }
+    };
+
+    *LOADER_PATH.lock().unwrap() = Some(loader_path.to_string());
+    let mut args = INIT_ARGS.lock().unwrap().clone();
+    // only this boot, the one after it is a normal one again
+    if let Some(mode) = NEXT_BOOT_MODE.lock().unwrap().take() {
+        args.push(format!("{}={}", BOOT_MODE_ARG, mode));
+    }
+    info!("guest command line: nice -n 5 {} {}", init, args.join(" "));
+
+    let limits = guest_rlimits();
+
+    // Gunakan 'nice' untuk menjalankan container
+    let mut command = Command::new("nice");
+    command
+        .arg("-n")
+        .arg("5")
+        .arg(&init)
+        .args(&args)
+        .current_dir(WORKING_DIR)
+        .env("TYLOADER", loader_path)
+        .stdout(outputs)
+        .stderr(errors)
+        // its own group, not the app's: a group kill of the guest never hits the app itself.
+        // Guest daemons and apps start their own sessions, the group only holds what didn't.
+        .process_group(0);
+    unsafe {
+        command.pre_exec(move || {
+            // the soft limit never exceeds the hard one, this doesn't fail; if it does the default stays
+            for (resource, limit) in &limits {
+                libc::setrlimit(*resource, limit);
+            }
+            Ok(())
+        });
+    }
+    let child = command.spawn();
+
+    match child {
+        Ok(child) => {
+            info!("guest init {} started, pid: {}", init, child.id());
+            if let Err(e) = fs::write(PID_PATH, child.id().to_string()) {
+                error!("write {} failed: {}", PID_PATH, e);
+            }
+            *guest_init = Some(child);
+            drop(guest_init);
+            EXIT_HANDLED.store(false, Ordering::Release);
+            thread::spawn(apply_boot_settings);
+            power::on_guest_start();
+            GUEST_LOGGING.store(logging, Ordering::Release);
+            if logging {
+                StartStatus::Started
+            } else {
+                StartStatus::StartedWithoutLog
+            }
+        }
+        Err(e) => {
+            error!("start guest init failed: {}", e);
+            StartStatus::Failed
+        }
+    }
+}
+
+pub const REBOOT_NORMAL: i32 = 0;
+pub const REBOOT_RECOVERY: i32 = 1;
+pub const REBOOT_BOOTLOADER: i32 = 2;
+
+// the host /proc/cmdline isn't the guest's, init takes androidboot.* from its own arguments
+const BOOT_MODE_ARG: &'static str = "androidboot.mode";
+// boot mode of the next guest start only, taken by container_start
+static NEXT_BOOT_MODE: Mutex<Option<&'static str>> = Mutex::new(None);
+const GUEST_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Restarts the guest into `mode`. Recovery and bootloader let the guest shut itself
+/// down first, whatever is still running after that is stopped like a normal reboot.
+pub fn container_reboot(mode: i32) -> StartStatus {
+    let target = match mode {
+        REBOOT_NORMAL => "normal",
+        REBOOT_RECOVERY => "recovery",
+        REBOOT_BOOTLOADER => "bootloader",
+        _ => {
+            error!("invalid reboot mode: {}", mode);
+            return StartStatus::Failed;
+        }
+    };
+    info!("guest reboot requested: {}", target);
+
+    let loader_path = match LOADER_PATH.lock().unwrap().clone() {
+        Some(path) => path,
+        None => {
+            error!("guest was never started, can't reboot it");
+            return StartStatus::Failed;
+        }
+    };
+    // an exit we asked for isn't a crash
+    EXIT_HANDLED.store(true, Ordering::Release);
+    if mode != REBOOT_NORMAL && guest::shell(&format!("reboot {}", target)).is_some() {
+        let start = Instant::now();
+        while is_alive() && start.elapsed() < GUEST_SHUTDOWN_TIMEOUT {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    container_stop();
+    if mode != REBOOT_NORMAL {
+        *NEXT_BOOT_MODE.lock().unwrap() = Some(target);
+    }
+    let status = container_start(&loader_path);
+    // a start that failed before taking the mode must not leave it to the next one
+    NEXT_BOOT_MODE.lock().unwrap().take();
+    status
+}
+
+const MAX_GRACEFUL_STOP_MS: i32 = 30_000;
+
+/// Stops the guest without losing buffered writes: syncs its storage, asks init to exit
+/// with SIGTERM and only kills the guest if it is still running after `timeout_ms`.
+/// True if the guest exited on its own in time.
+pub fn container_stop_graceful(timeout_ms: i32) -> bool {
+    let pid = match guest_pid() {
+        Some(pid) if is_alive() => pid,
+        _ => {
+            container_stop();
+            return true;
+        }
+    };
+    let timeout = Duration::from_millis(timeout_ms.clamp(0, MAX_GRACEFUL_STOP_MS) as u64);
+    let start = Instant::now();
+
+    info!("graceful stop: syncing guest storage");
+    match guest::shell("sync") {
+        Some(output) if output.success => info!("graceful stop: guest synced"),
+        // guest and host share the kernel, a host sync flushes the guest files as well
+        _ => {
+            warn!("graceful stop: guest sync failed, syncing from the host");
+            unsafe { libc::sync() };
+        }
+    }
+
+    // an exit we asked for isn't a crash
+    EXIT_HANDLED.store(true, Ordering::Release);
+    info!("graceful stop: sending SIGTERM to guest init {}", pid);
+    unsafe { libc::kill(-pid, libc::SIGTERM) };
+    while is_alive() && start.elapsed() < timeout {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let graceful = !is_alive();
+    if graceful {
+        info!("graceful stop: guest exited after {} ms", start.elapsed().as_millis());
+    } else {
+        warn!("graceful stop: guest still running after {} ms, killing it", timeout.as_millis());
+    }
+    container_stop();
+    graceful
+}
+
+pub fn container_stop() {
+    if let Some(mut child) = GUEST_INIT.lock().unwrap().take() {
+        let pid = child.id() as i32;
+        // reaches init and whatever stayed in its group, not the guest processes with their own session
+        unsafe { libc::kill(-pid, libc::SIGKILL) };
+        let _ = child.kill();
+        let _ = child.wait();
+        let _ = fs::remove_file(PID_PATH);
+        info!("guest init {} stopped", pid);
+        finish_log();
+    }
+}
+
+const STOPPED_MARKER: &'static str = "=== guest stopped ===\n";
+
+// The guest held the only handles to log.txt, they are closed once it was reaped.
+// Ends a line cut off by the kill and marks the stop, so the log reads complete.
+fn finish_log() {
+    // a log.txt left from an earlier boot isn't ours to finish
+    if !GUEST_LOGGING.swap(false, Ordering::AcqRel) {
+        return;
+    }
+    let ret = (|| -> io::Result<()> {
+        let mut log = fs::OpenOptions::new().read(true).append(true).open(LOG_PATH)?;
+        let mut last = [0u8; 1];
+        let len = log.metadata()?.len();
+        if len > 0 {
+            log.seek(SeekFrom::Start(len - 1))?;
+            log.read_exact(&mut last)?;
+        }
+        if len > 0 && last[0] != b'\n' {
+            log.write_all(b"\n")?;
+        }
+        log.write_all(STOPPED_MARKER.as_bytes())?;
+        log.sync_all()
+    })();
+    if let Err(e) = ret {
+        error!("finish {} failed: {}", LOG_PATH, e);
+    }
+}
+
+/// Pid of the guest init, also the id of its process group.
+pub fn guest_pid() -> Option<i32> {
+    GUEST_INIT.lock().unwrap().as_ref().map(|child| child.id() as i32)
+}
+
+pub fn is_alive() -> bool {
+    child_alive(&mut GUEST_INIT.lock().unwrap())
+}
+
+fn child_alive(guest_init: &mut Option<Child>) -> bool {
+    match guest_init.as_mut() {
+        Some(child) => match child.try_wait() {
+            Ok(None) => true,
+            Ok(Some(status)) => {
+                // try_wait keeps reporting the exit, save the log only the first time
+                if !status.success() && !EXIT_HANDLED.swap(true, Ordering::AcqRel) {
+                    save_crash_log(&status.to_string());
+                }
+                false
+            }
+            Err(_) => false,
+        },
+        None => false,
+    }
+}
+
+const CRASH_LOG_DIR: &'static str = "/data/data/io.twoyi";
+const CRASH_LOG_PREFIX: &'static str = "crash-";
+const DEFAULT_CRASH_LOGS_KEPT: usize = 5;
+
+// set once the exit of the current guest init was looked at
+static EXIT_HANDLED: AtomicBool = AtomicBool::new(false);
+
+// Copies log.txt of a guest init that died on its own to crash-<unix time>.txt, the next
+// start truncates log.txt. Keeps the newest `crash_logs_kept` from container.conf, 0 keeps none.
+fn save_crash_log(status: &str) {
+    error!("guest init exited: {}", status);
+    let kept = config::get_or(config::CONTAINER_CONF, "crash_logs_kept", DEFAULT_CRASH_LOGS_KEPT);
+    if kept == 0 || !GUEST_LOGGING.load(Ordering::Acquire) {
+        return;
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path = Path::new(CRASH_LOG_DIR).join(format!("{}{}.txt", CRASH_LOG_PREFIX, now));
+    let ret = fs::copy(LOG_PATH, &path).and_then(|_| {
+        let mut crash_log = fs::OpenOptions::new().append(true).open(&path)?;
+        writeln!(crash_log, "\n=== guest init exited: {} ===", status)
+    });
+    match ret {
+        Ok(_) => info!("crash log saved to {}", path.display()),
+        Err(e) => error!("save crash log failed: {}", e),
+    }
+
+    for old in list_crash_logs().iter().skip(kept) {
+        let _ = fs::remove_file(old);
+    }
+}
+
+/// Saved crash logs, newest first.
+pub fn list_crash_logs() -> Vec<String> {
+    let mut logs: Vec<String> = match fs::read_dir(CRASH_LOG_DIR) {
+        Ok(entries) => entries
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.starts_with(CRASH_LOG_PREFIX) && name.ends_with(".txt")
+            })
+            .map(|entry| entry.path().to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    // the unix times have the same number of digits for centuries to come
+    logs.sort_unstable_by(|a, b| b.cmp(a));
+    logs
+}
+
+/// One line diagnosis of the last boot from `log.txt`, empty if nothing known was found.
+pub fn last_boot_error() -> String {
+    let init = init_binary();
+    if !is_executable(&Path::new(WORKING_DIR).join(&init)) {
+        return format!("guest init {} is missing or not executable", init);
+    }
+
+    let log = match fs::read(LOG_PATH) {
+        Ok(log) => log,
+        Err(_) => return "the guest was never started, no log.txt".to_string(),
+    };
+    let log = String::from_utf8_lossy(&log);
+
+    for (signature, cause) in BOOT_ERROR_SIGNATURES.iter() {
+        let mut lines = log.lines().filter(|line| signature.iter().all(|part| line.contains(part)));
+        if let Some(first) = lines.next() {
+            let count = lines.count() + 1;
+            return format!("{} ({} times, first: {})", cause, count, first.trim());
+        }
+    }
+    String::new()
+}
+
+/// Stops the guest and removes its data partition, the system image is kept.
+pub fn wipe_guest_data() -> bool {
+    container_stop();
+    if is_alive() {
+        error!("guest is still alive, refuse to wipe");
+        return false;
+    }
+
+    info!("wiping guest data");
+    remove_dir_contents(Path::new(GUEST_DATA_PATH))
+}
+
+/// Stops the guest and removes the whole rootfs, it is extracted again
+/// from the bundled archive on the next boot.
+pub fn wipe_guest_full() -> bool {
+    container_stop();
+    if is_alive() {
+        error!("guest is still alive, refuse to wipe");
+        return false;
+    }
+
+    info!("wiping guest rootfs");
+    remove_dir_contents(Path::new(WORKING_DIR))
+}
+
+fn remove_dir_contents(dir: &Path) -> bool {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("read {} failed: {}", dir.display(), e);
+            return false;
+        }
+    };
+
+    let mut success = true;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        // never follow symlinks out of the rootfs
+        let is_dir = fs::symlink_metadata(&path).map(|m| m.is_dir()).unwrap_or(false);
+        let ret = if is_dir { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+        match ret {
+            Ok(_) => info!("wipe: removed {}", path.display()),
+            Err(e) => {
+                error!("wipe: remove {} failed: {}", path.display(), e);
+                success = false;
+            }
+        }
+    }
+    success
+}
+
+pub const MOUNT_SUCCESS: i32 = 0;
+pub const MOUNT_GUEST_NOT_RUNNING: i32 = 1;
+pub const MOUNT_INVALID_PATH: i32 = 2;
+pub const MOUNT_NAMESPACE_DENIED: i32 = 3;
+pub const MOUNT_DENIED: i32 = 4;
+pub const MOUNT_FAILED: i32 = 5;
+
+#[derive(Debug)]
+pub enum MountError {
+    GuestNotRunning,
+    InvalidPath,
+    // entering the guest mount namespace isn't permitted
+    NamespaceDenied,
+    // the bind mount itself isn't permitted, needs CAP_SYS_ADMIN in the namespace
+    MountDenied,
+    Failed(io::Error),
+}
+
+impl MountError {
+    /// The `MOUNT_*` code reported to Java.
+    pub fn code(&self) -> i32 {
+        match self {
+            MountError::GuestNotRunning => MOUNT_GUEST_NOT_RUNNING,
+            MountError::InvalidPath => MOUNT_INVALID_PATH,
+            MountError::NamespaceDenied => MOUNT_NAMESPACE_DENIED,
+            MountError::MountDenied => MOUNT_DENIED,
+            MountError::Failed(_) => MOUNT_FAILED,
+        }
+    }
+}
+
+/// Bind mounts the host directory `host_path` at `guest_path` in the running guest.
+///
+/// Mounting needs CAP_SYS_ADMIN, which the app uid doesn't have: without root on the
+/// host this fails with `NamespaceDenied` or `MountDenied`.
+pub fn mount_into_guest(host_path: &str, guest_path: &str) -> Result<(), MountError> {
+    let source = Path::new(host_path);
+    if !source.is_absolute() || !source.is_dir() {
+        return Err(MountError::InvalidPath);
+    }
+    let target = storage::guest_path(guest_path).ok_or(MountError::InvalidPath)?;
+    if !target.is_dir() {
+        return Err(MountError::InvalidPath);
+    }
+
+    let source = CString::new(host_path).map_err(|_| MountError::InvalidPath)?;
+    let target = CString::new(target.as_os_str().as_bytes()).map_err(|_| MountError::InvalidPath)?;
+    let ret = in_guest_mount_ns(move || {
+        let flags = libc::MS_BIND | libc::MS_REC;
+        let ret = unsafe {
+            libc::mount(source.as_ptr(), target.as_ptr(), std::ptr::null(), flags, std::ptr::null())
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    });
+
+    match ret {
+        Ok(_) => info!("mounted {} at {} in the guest", host_path, guest_path),
+        Err(ref e) => error!("mount {} at {} failed: {:?}", host_path, guest_path, e),
+    }
+    ret
+}
+
+pub fn unmount_from_guest(guest_path: &str) -> Result<(), MountError> {
+    let target = storage::guest_path(guest_path).ok_or(MountError::InvalidPath)?;
+    let target = CString::new(target.as_os_str().as_bytes()).map_err(|_| MountError::InvalidPath)?;
+    let ret = in_guest_mount_ns(move || {
+        if unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    });
+
+    match ret {
+        Ok(_) => info!("unmounted {} in the guest", guest_path),
+        Err(ref e) => error!("unmount {} failed: {:?}", guest_path, e),
+    }
+    ret
+}
+
+// setns(CLONE_NEWNS) is refused in a multi-threaded process, so `op` runs in a
+// forked child that joins the namespace of the guest init first.
+fn in_guest_mount_ns<F>(op: F) -> Result<(), MountError>
+where
+    F: FnMut() -> io::Result<()> + Send + Sync + 'static,
+{
+    let pid = guest_pid().ok_or(MountError::GuestNotRunning)?;
+    let guest_ns = format!("/proc/{}/ns/mnt", pid);
+    let same_ns = match (fs::read_link(&guest_ns), fs::read_link("/proc/self/ns/mnt")) {
+        (Ok(guest), Ok(own)) => guest == own,
+        _ => return Err(MountError::NamespaceDenied),
+    };
+
+    let ns = if same_ns {
+        None
+    } else {
+        let ns = File::open(&guest_ns).map_err(|_| MountError::NamespaceDenied)?;
+        // joining alone first, so a refusal isn't mistaken for a mount error
+        run_in_child(Some(ns.as_raw_fd()), || Ok(())).map_err(|_| MountError::NamespaceDenied)?;
+        Some(ns)
+    };
+
+    run_in_child(ns.as_ref().map(|ns| ns.as_raw_fd()), op).map_err(|e| match e.raw_os_error() {
+        Some(libc::EPERM) | Some(libc::EACCES) => MountError::MountDenied,
+        _ => MountError::Failed(e),
+    })
+}
+
+fn run_in_child<F>(ns: Option<RawFd>, mut op: F) -> io::Result<()>
+where
+    F: FnMut() -> io::Result<()> + Send + Sync + 'static,
+{
+    let mut cmd = Command::new("/system/bin/true");
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(fd) = ns {
+                if libc::setns(fd, libc::CLONE_NEWNS) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            // an error here is reported by spawn() in the parent
+            op()
+        });
+    }
+    cmd.status().map(|_| ())
+}
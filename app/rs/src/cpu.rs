@@ -0,0 +1,158 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use log::{error, info};
+
+use crate::container;
+
+const PROC_STAT: &'static str = "/proc/stat";
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+// last per-core usage of the guest in percent, refreshed by the sampler thread.
+static GUEST_CPU_USAGE: Lazy<Mutex<Vec<f32>>> = Lazy::new(|| {
+    thread::spawn(sampler);
+    Mutex::new(Vec::new())
+});
+
+/// CPU usage of the guest processes per core, never blocks on sampling. The kernel doesn't
+/// account process time per core, so this is an estimate, see `sampler`.
+pub fn guest_cpu_usage() -> Vec<f32> {
+    GUEST_CPU_USAGE.lock().unwrap().clone()
+}
+
+#[derive(Clone, Copy, Default)]
+struct CoreTimes {
+    busy: u64,
+    total: u64,
+}
+
+// The kernel only accounts cpu time per core system wide, so each core's busy
+// share is scaled by how much of all busy time the guest processes used.
+fn sampler() {
+    let mut last_cores = read_cores();
+    let mut last_guest = read_guest_ticks();
+    if last_cores.is_empty() {
+        error!("{} not readable, guest cpu usage unavailable", PROC_STAT);
+        return;
+    }
+    info!("guest cpu sampler started, {} cores", last_cores.len());
+
+    loop {
+        thread::sleep(SAMPLE_INTERVAL);
+
+        let cores = read_cores();
+        let guest = read_guest_ticks();
+        if cores.len() != last_cores.len() {
+            // cpu hotplug, start over
+            last_cores = cores;
+            last_guest = guest;
+            continue;
+        }
+
+        let busy_all: u64 = cores.iter().zip(&last_cores).map(|(c, l)| c.busy.saturating_sub(l.busy)).sum();
+        let guest_share = if busy_all == 0 {
+            0.0
+        } else {
+            (guest.saturating_sub(last_guest) as f32 / busy_all as f32).min(1.0)
+        };
+
+        let usage = cores
+            .iter()
+            .zip(&last_cores)
+            .map(|(c, l)| {
+                let total = c.total.saturating_sub(l.total);
+                if total == 0 {
+                    0.0
+                } else {
+                    c.busy.saturating_sub(l.busy) as f32 * 100.0 / total as f32 * guest_share
+                }
+            })
+            .collect();
+
+        *GUEST_CPU_USAGE.lock().unwrap() = usage;
+        last_cores = cores;
+        last_guest = guest;
+    }
+}
+
+fn read_cores() -> Vec<CoreTimes> {
+    let content = match fs::read_to_string(PROC_STAT) {
+        Ok(c) => c,
+        Err(_) => return Vec::new(),
+    };
+
+    content
+        .lines()
+        // "cpu" is the sum line, "cpuN" are the cores
+        .filter(|line| line.starts_with("cpu") && !line.starts_with("cpu "))
+        .map(|line| {
+            let values: Vec<u64> = line.split_whitespace().skip(1).filter_map(|v| v.parse().ok()).collect();
+            let total = values.iter().sum();
+            // idle + iowait
+            let idle = values.get(3).copied().unwrap_or(0) + values.get(4).copied().unwrap_or(0);
+            CoreTimes { busy: total - idle, total }
+        })
+        .collect()
+}
+
+// utime + stime of the guest init and all its descendants. Guest daemons and apps start their
+// own sessions and process groups, only the parent chain leads back to init.
+fn read_guest_ticks() -> u64 {
+    let init = match container::guest_pid() {
+        Some(pid) => pid,
+        None => return 0,
+    };
+
+    let entries = match fs::read_dir("/proc") {
+        Ok(e) => e,
+        Err(_) => return 0,
+    };
+
+    // ppid -> (pid, ticks) of its children
+    let mut children: HashMap<i32, Vec<(i32, u64)>> = HashMap::new();
+    let mut init_ticks = 0;
+    for entry in entries.flatten() {
+        let pid: i32 = match entry.file_name().to_str().and_then(|name| name.parse().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        // comm may contain spaces, fields are counted from the closing paren
+        let fields: Vec<&str> = match stat.rfind(')') {
+            Some(pos) => stat[pos + 1..].split_whitespace().collect(),
+            None => continue,
+        };
+        let ppid: i32 = match fields.get(1).and_then(|p| p.parse().ok()) {
+            Some(ppid) => ppid,
+            None => continue,
+        };
+        let utime: u64 = fields.get(11).and_then(|v| v.parse().ok()).unwrap_or(0);
+        let stime: u64 = fields.get(12).and_then(|v| v.parse().ok()).unwrap_or(0);
+        if pid == init {
+            init_ticks = utime + stime;
+        }
+        children.entry(ppid).or_default().push((pid, utime + stime));
+    }
+
+    let mut ticks = init_ticks;
+    let mut pending = vec![init];
+    while let Some(parent) = pending.pop() {
+        for (pid, child_ticks) in children.remove(&parent).unwrap_or_default() {
+            ticks += child_ticks;
+            pending.push(pid);
+        }
+    }
+    ticks
+}
@@ -0,0 +1,203 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use log::{error, info};
+
+use crate::{guest, memory, storage};
+
+const CFS_PERIOD_US: i64 = 100_000;
+const RESPAWN_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+pub const CPU_LIMIT_SUCCESS: i32 = 0;
+pub const CPU_LIMIT_INVALID_ARGS: i32 = 1;
+pub const CPU_LIMIT_GUEST_NOT_RUNNING: i32 = 2;
+pub const CPU_LIMIT_APP_NOT_RUNNING: i32 = 3;
+pub const CPU_LIMIT_CGROUP_UNAVAILABLE: i32 = 4;
+pub const CPU_LIMIT_FAILED: i32 = 5;
+
+#[derive(Debug)]
+pub enum CpuLimitError {
+    InvalidArgs,
+    // the guest shell isn't up
+    GuestNotRunning,
+    AppNotRunning,
+    // the guest has neither a cgroup v2 cpu controller nor a v1 cpuctl it may create groups in
+    CgroupUnavailable,
+    Failed(String),
+}
+
+impl CpuLimitError {
+    /// The `CPU_LIMIT_*` code reported to Java.
+    pub fn code(&self) -> i32 {
+        match self {
+            CpuLimitError::InvalidArgs => CPU_LIMIT_INVALID_ARGS,
+            CpuLimitError::GuestNotRunning => CPU_LIMIT_GUEST_NOT_RUNNING,
+            CpuLimitError::AppNotRunning => CPU_LIMIT_APP_NOT_RUNNING,
+            CpuLimitError::CgroupUnavailable => CPU_LIMIT_CGROUP_UNAVAILABLE,
+            CpuLimitError::Failed(_) => CPU_LIMIT_FAILED,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Cgroup {
+    V2,
+    V1,
+}
+
+struct Limit {
+    percent: i32,
+    // processes already in the group
+    pids: Vec<i32>,
+}
+
+#[derive(Default)]
+struct Limits {
+    apps: HashMap<String, Limit>,
+    // the monitor runs while any app is limited
+    monitoring: bool,
+}
+
+// limited packages, the monitor moves the processes they respawn into their group
+static LIMITS: Lazy<Mutex<Limits>> = Lazy::new(|| Mutex::new(Limits::default()));
+
+/// Caps the processes of guest app `package` at `percent` of one core, 0 lifts the cap.
+///
+/// The guest's cpu cgroups are the host's hierarchy, which the app uid can't create groups in.
+/// Without root on the host this fails with `CgroupUnavailable`.
+pub fn set_app_cpu_limit(package: &str, percent: i32) -> Result<(), CpuLimitError> {
+    let ret = limit(package, percent);
+    match ret {
+        Ok(_) if percent == 0 => info!("cpu limit of {} lifted", package),
+        Ok(_) => info!("cpu of {} limited to {}%", package, percent),
+        Err(ref e) => error!("limit cpu of {} failed: {:?}", package, e),
+    }
+    ret
+}
+
+fn limit(package: &str, percent: i32) -> Result<(), CpuLimitError> {
+    if !storage::is_valid_package(package) || !(0..=100).contains(&percent) {
+        return Err(CpuLimitError::InvalidArgs);
+    }
+    let cgroup = detect()?;
+
+    if percent == 0 {
+        LIMITS.lock().unwrap().apps.remove(package);
+        return apply(cgroup, package, 0, &[]);
+    }
+
+    let pids = memory::package_pids(package);
+    if pids.is_empty() {
+        return Err(CpuLimitError::AppNotRunning);
+    }
+    apply(cgroup, package, percent, &pids)?;
+    let mut limits = LIMITS.lock().unwrap();
+    limits.apps.insert(package.to_string(), Limit { percent, pids });
+    if !limits.monitoring {
+        limits.monitoring = true;
+        thread::spawn(monitor);
+    }
+    Ok(())
+}
+
+fn detect() -> Result<Cgroup, CpuLimitError> {
+    let cmd = "if grep -qw cpu /sys/fs/cgroup/cgroup.controllers 2>/dev/null && [ -w /sys/fs/cgroup ]; then echo v2; \
+               elif [ -e /dev/cpuctl/cpu.cfs_quota_us ] && [ -w /dev/cpuctl ]; then echo v1; fi";
+    let output = guest::shell(cmd).ok_or(CpuLimitError::GuestNotRunning)?;
+    match output.stdout.trim() {
+        "v2" => Ok(Cgroup::V2),
+        "v1" => Ok(Cgroup::V1),
+        _ => Err(CpuLimitError::CgroupUnavailable),
+    }
+}
+
+// Sets the quota of the package's group, creating it if needed, and moves `pids` into it.
+fn apply(cgroup: Cgroup, package: &str, percent: i32, pids: &[i32]) -> Result<(), CpuLimitError> {
+    let quota = CFS_PERIOD_US * percent as i64 / 100;
+    let (dir, quota_cmd) = match cgroup {
+        Cgroup::V2 => {
+            let dir = format!("/sys/fs/cgroup/twoyi-{}", package);
+            let quota = if percent == 0 { "max".to_string() } else { quota.to_string() };
+            let cmd = format!(
+                "echo +cpu > /sys/fs/cgroup/cgroup.subtree_control 2>/dev/null; echo '{} {}' > {}/cpu.max",
+                quota, CFS_PERIOD_US, dir
+            );
+            (dir, cmd)
+        }
+        Cgroup::V1 => {
+            let dir = format!("/dev/cpuctl/twoyi-{}", package);
+            let quota = if percent == 0 { -1 } else { quota };
+            let cmd = format!(
+                "echo {} > {}/cpu.cfs_period_us && echo {} > {}/cpu.cfs_quota_us",
+                CFS_PERIOD_US, dir, quota, dir
+            );
+            (dir, cmd)
+        }
+    };
+
+    match guest::shell(&format!("mkdir -p {}", dir)) {
+        Some(output) if output.success => (),
+        Some(_) => return Err(CpuLimitError::CgroupUnavailable),
+        None => return Err(CpuLimitError::GuestNotRunning),
+    }
+    match guest::shell(&quota_cmd) {
+        Some(output) if output.success => (),
+        Some(output) => return Err(CpuLimitError::Failed(output.stderr.trim().to_string())),
+        None => return Err(CpuLimitError::GuestNotRunning),
+    }
+
+    if pids.is_empty() {
+        return Ok(());
+    }
+    // a process may exit meanwhile, the others still get moved
+    let pids: Vec<String> = pids.iter().map(|pid| pid.to_string()).collect();
+    let cmd = format!("for p in {}; do echo $p > {}/cgroup.procs; done 2>/dev/null", pids.join(" "), dir);
+    guest::shell(&cmd).ok_or(CpuLimitError::GuestNotRunning)?;
+    Ok(())
+}
+
+// Apps get restarted by the guest, their new processes start outside the group.
+// Runs until no app is limited anymore.
+fn monitor() {
+    loop {
+        thread::sleep(RESPAWN_CHECK_INTERVAL);
+
+        let mut state = LIMITS.lock().unwrap();
+        if state.apps.is_empty() {
+            state.monitoring = false;
+            return;
+        }
+        let limits: Vec<(String, i32, Vec<i32>)> = state
+            .apps
+            .iter()
+            .map(|(package, limit)| (package.clone(), limit.percent, limit.pids.clone()))
+            .collect();
+        drop(state);
+        for (package, percent, known) in limits {
+            let pids = memory::package_pids(&package);
+            let respawned: Vec<i32> = pids.iter().copied().filter(|pid| !known.contains(pid)).collect();
+            if respawned.is_empty() {
+                continue;
+            }
+            let ret = detect().and_then(|cgroup| apply(cgroup, &package, percent, &respawned));
+            match ret {
+                Ok(_) => info!("cpu limit of {} applied to respawned {:?}", package, respawned),
+                Err(e) => {
+                    error!("reapply cpu limit of {} failed: {:?}", package, e);
+                    continue;
+                }
+            }
+            if let Some(limit) = LIMITS.lock().unwrap().apps.get_mut(&package) {
+                limit.pids = pids;
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+use log::{error, info};
+
+use crate::{gpu, guest, input};
+
+const LOG_PATH: &'static str = "/data/data/io.twoyi/log.txt";
+const CRASH_PATH: &'static str = "/data/data/io.twoyi/crash.txt";
+
+/// Writes a zip with logs, guest info and stats to `path`. A piece that can't be
+/// collected is skipped and listed in `errors.txt` inside the zip.
+pub fn export(path: &str) -> bool {
+    let pieces: Vec<(&str, Result<Vec<u8>, String>)> = vec![
+        ("log.txt", fs::read(LOG_PATH).map_err(|e| e.to_string())),
+        ("crash.txt", fs::read(CRASH_PATH).map_err(|e| e.to_string())),
+        ("build_info.json", Ok(guest::build_info().into_bytes())),
+        ("gl_info.txt", gpu::gl_info().map(|info| info.describe().into_bytes()).ok_or_else(|| "no GL context".to_string())),
+        ("render_stats.txt", Ok(crate::render_stats().into_bytes())),
+        ("input_stats.txt", Ok(input::queue_stats().into_bytes())),
+        ("processes.txt", guest_processes()),
+    ];
+
+    match write_zip(path, pieces) {
+        Ok(_) => {
+            info!("diagnostics exported to {}", path);
+            true
+        }
+        Err(e) => {
+            error!("export diagnostics to {} failed: {}", path, e);
+            false
+        }
+    }
+}
+
+fn guest_processes() -> Result<Vec<u8>, String> {
+    match guest::shell("ps -A") {
+        Some(output) if output.success => Ok(output.stdout.into_bytes()),
+        Some(output) => Err(output.stderr.trim().to_string()),
+        None => Err("guest not ready".to_string()),
+    }
+}
+
+fn write_zip(path: &str, pieces: Vec<(&str, Result<Vec<u8>, String>)>) -> io::Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let mut errors = String::new();
+    for (name, data) in pieces {
+        match data {
+            Ok(data) => zip.add(name, &data)?,
+            Err(e) => errors.push_str(&format!("{}: {}\n", name, e)),
+        }
+    }
+    if !errors.is_empty() {
+        zip.add("errors.txt", errors.as_bytes())?;
+    }
+    zip.finish()
+}
+
+// Just enough of the zip format for a handful of stored (uncompressed) files.
+struct ZipWriter {
+    out: BufWriter<File>,
+    // name, crc, size, offset of the local header
+    entries: Vec<(String, u32, u32, u32)>,
+    offset: u32,
+}
+
+// 1980-01-01 00:00, the zip epoch
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = 0x21;
+
+impl ZipWriter {
+    fn new(file: File) -> ZipWriter {
+        ZipWriter { out: BufWriter::new(file), entries: Vec::new(), offset: 0 }
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let crc = crc32(data);
+        let size = data.len() as u32;
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        header.extend_from_slice(&0u16.to_le_bytes()); // flags
+        header.extend_from_slice(&0u16.to_le_bytes()); // stored
+        header.extend_from_slice(&DOS_TIME.to_le_bytes());
+        header.extend_from_slice(&DOS_DATE.to_le_bytes());
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // extra
+        header.extend_from_slice(name.as_bytes());
+
+        self.out.write_all(&header)?;
+        self.out.write_all(data)?;
+        self.entries.push((name.to_string(), crc, size, self.offset));
+        self.offset += header.len() as u32 + size;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let mut directory = Vec::new();
+        for (name, crc, size, offset) in &self.entries {
+            directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            directory.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            directory.extend_from_slice(&0u16.to_le_bytes()); // flags
+            directory.extend_from_slice(&0u16.to_le_bytes()); // stored
+            directory.extend_from_slice(&DOS_TIME.to_le_bytes());
+            directory.extend_from_slice(&DOS_DATE.to_le_bytes());
+            directory.extend_from_slice(&crc.to_le_bytes());
+            directory.extend_from_slice(&size.to_le_bytes());
+            directory.extend_from_slice(&size.to_le_bytes());
+            directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            // extra, comment, disk, internal and external attributes
+            directory.extend_from_slice(&[0u8; 12]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let count = self.entries.len() as u16;
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        end.extend_from_slice(&[0u8; 4]); // disk numbers
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        end.extend_from_slice(&self.offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes()); // comment
+
+        self.out.write_all(&directory)?;
+        self.out.write_all(&end)?;
+        self.out.flush()
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn u16_at(data: &[u8], at: usize) -> usize {
+        u16::from_le_bytes([data[at], data[at + 1]]) as usize
+    }
+
+    fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+    }
+
+    // (name, data) of every entry, read the way java.util.zip.ZipFile does: the end record
+    // leads to the central directory, which leads to the local headers.
+    fn read_zip(zip: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(zip, end), 0x06054b50);
+        let count = u16_at(zip, end + 10);
+        let mut at = u32_at(zip, end + 16) as usize;
+        assert_eq!(at + u32_at(zip, end + 12) as usize, end);
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(zip, at), 0x02014b50);
+            let crc = u32_at(zip, at + 16);
+            let size = u32_at(zip, at + 20) as usize;
+            let name_len = u16_at(zip, at + 28);
+            let local = u32_at(zip, at + 42) as usize;
+            let name = String::from_utf8(zip[at + 46..at + 46 + name_len].to_vec()).unwrap();
+
+            assert_eq!(u32_at(zip, local), 0x04034b50);
+            assert_eq!(u32_at(zip, local + 14), crc);
+            assert_eq!(&zip[local + 30..local + 30 + name_len], name.as_bytes());
+            let start = local + 30 + name_len + u16_at(zip, local + 28);
+            let data = zip[start..start + size].to_vec();
+            assert_eq!(crc32(&data), crc, "{}", name);
+
+            entries.push((name, data));
+            at += 46 + name_len;
+        }
+        entries
+    }
+
+    #[test]
+    fn crc32_known_answers() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414fa339);
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let path = std::env::temp_dir().join(format!("twoyi-diagnostics-{}.zip", std::process::id()));
+        let pieces = vec![
+            ("log.txt", Ok(b"first line\nsecond line\n".to_vec())),
+            ("empty.txt", Ok(Vec::new())),
+            ("processes.txt", Err("guest not ready".to_string())),
+        ];
+        write_zip(path.to_str().unwrap(), pieces).unwrap();
+        let zip = fs::read(&path).unwrap();
+
+        let entries = read_zip(&zip);
+        assert_eq!(
+            entries,
+            [
+                ("log.txt".to_string(), b"first line\nsecond line\n".to_vec()),
+                ("empty.txt".to_string(), Vec::new()),
+                ("errors.txt".to_string(), b"processes.txt: guest not ready\n".to_vec()),
+            ]
+        );
+
+        // the real thing, where the host has it
+        if let Ok(output) = Command::new("unzip").arg("-t").arg(&path).output() {
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+        }
+        let _ = fs::remove_file(&path);
+    }
+}
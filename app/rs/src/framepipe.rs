@@ -0,0 +1,267 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Streams rendered frames to a unix socket for external tools.
+//!
+//! Every frame is a 24 byte little endian header followed by the pixels:
+//!
+//! ```text
+//! u32 magic      "TWYF" (0x46595754)
+//! u32 width
+//! u32 height
+//! u32 stride     always width * 4
+//! u64 timestamp  CLOCK_MONOTONIC in microseconds
+//! u8  rgba[stride * height]
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::os::raw::c_int;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use unix_socket::{UnixListener, UnixStream};
+
+use log::{error, info, warn};
+
+use crate::renderer_bindings;
+
+const FRAME_MAGIC: u32 = 0x46595754;
+const HEADER_SIZE: usize = 24;
+const MAX_PIPE_FPS: i32 = 60;
+// longest the writer waits on its client or the renderer before it checks for close
+const POLL_MS: c_int = 100;
+
+struct FramePipe {
+    path: String,
+    // inode of the socket open bound, close only unlinks that one
+    inode: u64,
+    // stops the writer, also while it waits for a client that stopped reading
+    closed: Arc<AtomicBool>,
+    interval: Duration,
+    last_frame: Option<Instant>,
+    // one frame in flight, the render thread drops frames instead of waiting
+    sender: SyncSender<Arc<Vec<u8>>>,
+    dropped: u64,
+}
+
+static PIPES: Lazy<Mutex<HashMap<i32, FramePipe>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_HANDLE: AtomicI32 = AtomicI32::new(1);
+
+/// Listens on a unix socket at `path` and streams frames at up to `fps` to the
+/// client, returns a handle for `close`, -1 on failure.
+pub fn open(path: &str, fps: i32) -> i32 {
+    if fps <= 0 {
+        error!("invalid frame pipe fps: {}", fps);
+        return -1;
+    }
+    let fps = fps.min(MAX_PIPE_FPS);
+
+    if !remove_stale_socket(path) {
+        return -1;
+    }
+    let listener = match UnixListener::bind(path) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("bind frame pipe {} failed: {}", path, e);
+            return -1;
+        }
+    };
+    let inode = match fs::symlink_metadata(path) {
+        Ok(meta) => meta.ino(),
+        Err(e) => {
+            error!("stat frame pipe {} failed: {}", path, e);
+            return -1;
+        }
+    };
+    if let Err(e) = listener.set_nonblocking(true) {
+        error!("set frame pipe {} non-blocking failed: {}", path, e);
+        let _ = fs::remove_file(path);
+        return -1;
+    }
+
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let closed = Arc::new(AtomicBool::new(false));
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    let pipe = FramePipe {
+        path: path.to_string(),
+        inode,
+        closed: closed.clone(),
+        interval: Duration::from_secs(1) / fps as u32,
+        last_frame: None,
+        sender,
+        dropped: 0,
+    };
+
+    let mut pipes = PIPES.lock().unwrap();
+    let owned_path = path.to_string();
+    thread::spawn(move || writer(listener, receiver, owned_path, closed));
+    pipes.insert(handle, pipe);
+    let first = pipes.len() == 1;
+    // the renderer may be waiting for the lock in on_frame
+    drop(pipes);
+    if first {
+        unsafe { renderer_bindings::setFrameCallback(Some(on_frame)) };
+    }
+
+    info!("frame pipe {} opened at {}, {} fps", handle, path, fps);
+    handle
+}
+
+pub fn close(handle: i32) {
+    let mut pipes = PIPES.lock().unwrap();
+    let pipe = match pipes.remove(&handle) {
+        Some(pipe) => pipe,
+        None => {
+            warn!("no frame pipe {}", handle);
+            return;
+        }
+    };
+    let last = pipes.is_empty();
+    drop(pipes);
+    if last {
+        unsafe { renderer_bindings::setFrameCallback(None) };
+    }
+
+    info!("frame pipe {} closed, {} frames dropped", handle, pipe.dropped);
+    pipe.closed.store(true, Ordering::Release);
+    // whatever was put at the path since open isn't ours to remove
+    let ours = fs::symlink_metadata(&pipe.path).is_ok_and(|meta| meta.file_type().is_socket() && meta.ino() == pipe.inode);
+    if ours {
+        let _ = fs::remove_file(&pipe.path);
+    }
+}
+
+// Removes a socket an earlier run left at `path`. Any other file there is kept, and open fails.
+fn remove_stale_socket(path: &str) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => match fs::remove_file(path) {
+            Ok(_) => true,
+            Err(e) => {
+                error!("remove stale frame pipe {} failed: {}", path, e);
+                false
+            }
+        },
+        Ok(_) => {
+            error!("{} exists and isn't a socket, not using it as frame pipe", path);
+            false
+        }
+        Err(_) => true,
+    }
+}
+
+// Waits up to POLL_MS for `events` on `fd`.
+fn poll(fd: RawFd, events: i16) -> bool {
+    let mut pfd = libc::pollfd { fd, events, revents: 0 };
+    unsafe { libc::poll(&mut pfd, 1, POLL_MS) > 0 }
+}
+
+fn writer(listener: UnixListener, receiver: Receiver<Arc<Vec<u8>>>, path: String, closed: Arc<AtomicBool>) {
+    while !closed.load(Ordering::Acquire) {
+        if !poll(listener.as_raw_fd(), libc::POLLIN) {
+            continue;
+        }
+        let mut stream = match listener.accept() {
+            Ok((s, _)) => s,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return,
+        };
+        // accepted sockets don't inherit O_NONBLOCK
+        if stream.set_nonblocking(true).is_err() {
+            continue;
+        }
+
+        info!("frame pipe {} client connected", path);
+        loop {
+            let frame = match receiver.recv_timeout(Duration::from_millis(POLL_MS as u64)) {
+                Ok(frame) => frame,
+                Err(RecvTimeoutError::Timeout) if !closed.load(Ordering::Acquire) => continue,
+                Err(_) => return,
+            };
+            if write_frame(&mut stream, &frame, &closed).is_err() {
+                if closed.load(Ordering::Acquire) {
+                    return;
+                }
+                info!("frame pipe {} client disconnected", path);
+                break;
+            }
+        }
+    }
+}
+
+// Writes all of `frame` to the non-blocking `stream`, a client that stopped reading
+// can't keep the writer from noticing close.
+fn write_frame(stream: &mut UnixStream, frame: &[u8], closed: &AtomicBool) -> io::Result<()> {
+    let mut written = 0;
+    while written < frame.len() {
+        if closed.load(Ordering::Acquire) {
+            return Err(io::Error::new(io::ErrorKind::Other, "frame pipe closed"));
+        }
+        match stream.write(&frame[written..]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                poll(stream.as_raw_fd(), libc::POLLOUT);
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Called by the renderer on its own thread after each presented frame.
+pub extern "C" fn on_frame(rgba: *const u8, width: c_int, height: c_int, stride: c_int) {
+    if rgba.is_null() || width <= 0 || height <= 0 || stride < width * 4 {
+        return;
+    }
+
+    let mut pipes = PIPES.lock().unwrap();
+    let now = Instant::now();
+    let mut frame: Option<Arc<Vec<u8>>> = None;
+    for pipe in pipes.values_mut() {
+        if pipe.last_frame.is_some_and(|last| now - last < pipe.interval) {
+            continue;
+        }
+        pipe.last_frame = Some(now);
+
+        // copied once, shared by all pipes that are due
+        let data = frame
+            .get_or_insert_with(|| {
+                let pixels = unsafe { std::slice::from_raw_parts(rgba, (stride * height) as usize) };
+                Arc::new(encode(pixels, width as usize, height as usize, stride as usize))
+            })
+            .clone();
+        if let Err(TrySendError::Full(_)) = pipe.sender.try_send(data) {
+            pipe.dropped += 1;
+        }
+    }
+}
+
+fn encode(pixels: &[u8], width: usize, height: usize, stride: usize) -> Vec<u8> {
+    let row = width * 4;
+    let mut data = Vec::with_capacity(HEADER_SIZE + row * height);
+    data.extend_from_slice(&FRAME_MAGIC.to_le_bytes());
+    data.extend_from_slice(&(width as u32).to_le_bytes());
+    data.extend_from_slice(&(height as u32).to_le_bytes());
+    data.extend_from_slice(&(row as u32).to_le_bytes());
+    data.extend_from_slice(&monotonic_micros().to_le_bytes());
+    for y in 0..height {
+        data.extend_from_slice(&pixels[y * stride..y * stride + row]);
+    }
+    data
+}
+
+fn monotonic_micros() -> u64 {
+    let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000
+}
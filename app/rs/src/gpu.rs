@@ -0,0 +1,175 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info, warn};
+
+use crate::config;
+use crate::renderer_bindings;
+
+pub struct GlInfo {
+    pub vendor: String,
+    pub renderer: String,
+    pub version: String,
+    pub extensions: String,
+}
+
+/// Reads the GL strings of the render context, `None` if it isn't created yet.
+pub fn gl_info() -> Option<GlInfo> {
+    match read_gl_info() {
+        Ok(info) => Some(info),
+        Err(ret) => {
+            error!("getGlStrings failed: {}", ret);
+            None
+        }
+    }
+}
+
+fn read_gl_info() -> Result<GlInfo, i32> {
+    let mut vendor: *const c_char = std::ptr::null();
+    let mut renderer: *const c_char = std::ptr::null();
+    let mut version: *const c_char = std::ptr::null();
+    let mut extensions: *const c_char = std::ptr::null();
+
+    let ret = unsafe {
+        renderer_bindings::getGlStrings(&mut vendor, &mut renderer, &mut version, &mut extensions)
+    };
+    if ret != 0 {
+        return Err(ret);
+    }
+
+    Ok(GlInfo {
+        vendor: to_string(vendor),
+        renderer: to_string(renderer),
+        version: to_string(version),
+        extensions: to_string(extensions),
+    })
+}
+
+// GL_RENDERER of the CPU rasterizers a driver may fall back to
+const SOFTWARE_RENDERERS: [&'static str; 3] = ["llvmpipe", "softpipe", "swiftshader"];
+
+impl GlInfo {
+    pub fn is_software(&self) -> bool {
+        // the translator wraps the host renderer as "Android Emulator OpenGL ES Translator (Mali-G78)"
+        let renderer = match (self.renderer.find('('), self.renderer.rfind(')')) {
+            (Some(start), Some(end)) if start < end => &self.renderer[start + 1..end],
+            _ => &self.renderer,
+        };
+        let renderer = renderer.to_lowercase();
+        SOFTWARE_RENDERERS.iter().any(|sw| renderer.contains(sw))
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "GL_VENDOR: {}\nGL_RENDERER: {}\nGL_VERSION: {}\nGL_EXTENSIONS: {}",
+            self.vendor, self.renderer, self.version, self.extensions
+        )
+    }
+}
+
+fn to_string(s: *const c_char) -> String {
+    if s.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+}
+
+/// What the device can render with as JSON, probed with a throwaway context:
+/// `{"gles":"3.2","vulkan":true,"vulkan_version":"1.1.0","max_texture_size":16384,"native_fence_sync":true}`.
+/// `None` if no context could be created.
+pub fn capabilities_json() -> Option<String> {
+    let mut caps = renderer_bindings::RendererCapabilities::default();
+    let ret = unsafe { renderer_bindings::probeCapabilities(&mut caps) };
+    if ret != 0 {
+        error!("probeCapabilities failed: {}", ret);
+        return None;
+    }
+
+    let vulkan = caps.vulkan_available != 0;
+    let vulkan_version = if vulkan {
+        let v = caps.vulkan_api_version;
+        format!("{}.{}.{}", v >> 22, (v >> 12) & 0x3ff, v & 0xfff)
+    } else {
+        String::new()
+    };
+    let json = format!(
+        "{{\"gles\":\"{}.{}\",\"vulkan\":{},\"vulkan_version\":\"{}\",\"max_texture_size\":{},\"native_fence_sync\":{}}}",
+        caps.gles_major,
+        caps.gles_minor,
+        vulkan,
+        vulkan_version,
+        caps.max_texture_size,
+        caps.native_fence_sync != 0
+    );
+    info!("renderer capabilities: {}", json);
+    Some(json)
+}
+
+/// True if the render context runs on the GPU, false for a software rasterizer or no context yet.
+pub fn is_hardware_accelerated() -> bool {
+    gl_info().is_some_and(|info| !info.is_software())
+}
+
+pub const COLOR_MODE_SRGB: i32 = 0;
+pub const COLOR_MODE_WIDE_GAMUT: i32 = 1;
+pub const COLOR_MODE_HDR: i32 = 2;
+
+/// Switches the color space of the render surface, returns the mode in effect: sRGB
+/// if the display or driver lacks the requested one.
+pub fn apply_color_mode(mode: i32) -> i32 {
+    let applied = unsafe { renderer_bindings::setColorMode(mode) };
+    if applied != mode {
+        warn!("color mode {} not supported, falling back to {}", mode, applied);
+    } else {
+        info!("color mode: {}", mode);
+    }
+    applied
+}
+
+fn color_space_name(space: i32) -> &'static str {
+    match space {
+        COLOR_MODE_SRGB => "sRGB",
+        COLOR_MODE_WIDE_GAMUT => "Display P3",
+        COLOR_MODE_HDR => "BT.2020 PQ",
+        _ => "unknown",
+    }
+}
+
+// An HDR surface shows SDR guest content washed out unless it's tonemapped.
+fn log_surface_color_space() {
+    let space = unsafe { renderer_bindings::getSurfaceColorSpace() };
+    if space < 0 {
+        return;
+    }
+    info!("surface color space: {}", color_space_name(space));
+    if space == COLOR_MODE_HDR {
+        let tonemap = config::get_or(config::RENDERER_CONF, "sdr_tonemap", false);
+        info!("hdr surface, sdr content is {}", if tonemap { "tonemapped" } else { "shown on an sRGB surface" });
+    }
+}
+
+const CONTEXT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const CONTEXT_POLL_ATTEMPTS: u32 = 20;
+
+/// Warns once the render context exists if it turned out to be a software renderer.
+pub fn check_acceleration() {
+    for _ in 0..CONTEXT_POLL_ATTEMPTS {
+        thread::sleep(CONTEXT_POLL_INTERVAL);
+        // not logged, the context takes a moment
+        if let Ok(info) = read_gl_info() {
+            if info.is_software() {
+                warn!("software rendering with {}, expect poor performance", info.renderer);
+            } else {
+                info!("hardware rendering with {}", info.renderer);
+            }
+            log_surface_color_space();
+            return;
+        }
+    }
+}
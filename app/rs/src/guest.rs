@@ -0,0 +1,642 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+
+use log::{error, info, warn};
+
+use crate::gpu;
+use crate::json;
+
+// guest init loads these in order, read-only props keep the first value.
+const PROP_FILES: [&'static str; 4] = [
+    "/data/data/io.twoyi/rootfs/default.prop",
+    "/data/data/io.twoyi/rootfs/system/build.prop",
+    "/data/data/io.twoyi/rootfs/vendor/default.prop",
+    "/data/data/io.twoyi/rootfs/vendor/build.prop",
+];
+
+// our own prop overrides, RomManager.initRootfs writes the locale here too.
+const OVERRIDE_PROP_FILE: &'static str = "/data/data/io.twoyi/rootfs/vendor/default.prop";
+
+// PROP_VALUE_MAX includes the trailing null.
+const PROP_VALUE_MAX: usize = 92;
+
+const PROC_VERSION: &'static str = "/proc/version";
+
+// commands reach the guest through its adbd, the same way Installer does.
+// RomManager links loader64 to the loader in nativeLibraryDir, libadb.so sits next to it.
+const LOADER_LINK: &'static str = "/data/data/io.twoyi/loader64";
+const ADB_NAME: &'static str = "libadb.so";
+const ADB_PORT: &'static str = "9563";
+const ADB_TARGET: &'static str = "localhost:22122";
+const ADB_HOME: &'static str = "/data/data/io.twoyi/cache";
+
+pub const SETTINGS_NAMESPACES: [&'static str; 3] = ["system", "secure", "global"];
+
+static BUILD_INFO: OnceCell<String> = OnceCell::new();
+
+/// Reads `key` from the guest prop files without the guest running.
+pub fn read_prop(key: &str) -> Option<String> {
+    for file in PROP_FILES.iter() {
+        let content = match fs::read_to_string(file) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some((k, v)) = line.split_once('=') {
+                if k.trim() == key {
+                    return Some(v.trim().to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Writes `props` into the guest override prop file, replacing existing keys.
+///
+/// The file is only read by guest init at boot, so this must happen before the
+/// guest is spawned in `renderer_init`; otherwise it takes effect on the next boot.
+pub fn write_props(props: &[(&str, &str)]) -> bool {
+    for (k, v) in props {
+        if !is_valid_prop(k, v) {
+            error!("invalid prop: {}={}", k, v);
+            return false;
+        }
+    }
+
+    let content = fs::read_to_string(OVERRIDE_PROP_FILE).unwrap_or_default();
+    let mut lines: Vec<String> = content
+        .lines()
+        .filter(|line| match line.split_once('=') {
+            Some((k, _)) => !props.iter().any(|(key, _)| k.trim() == *key),
+            None => true,
+        })
+        .map(|line| line.to_string())
+        .collect();
+
+    for (k, v) in props {
+        lines.push(format!("{}={}", k, v));
+    }
+    lines.push(String::new());
+
+    match fs::write(OVERRIDE_PROP_FILE, lines.join("\n")) {
+        Ok(_) => {
+            info!("write props: {:?}", props);
+            true
+        }
+        Err(e) => {
+            error!("write props failed: {}", e);
+            false
+        }
+    }
+}
+
+fn is_valid_prop(key: &str, value: &str) -> bool {
+    let valid_key = !key.is_empty()
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-');
+    let valid_value = value.len() < PROP_VALUE_MAX && !value.contains(|c: char| c == '\n' || c == '\r');
+    valid_key && valid_value
+}
+
+/// Writes read-only `props`. Guest init keeps the first value it loads for those, so each
+/// one replaces the line in the first prop file that has it, or goes into the first file
+/// loaded. `read_prop` returns the new values right away, `getprop` from the next boot.
+pub fn write_ro_props(props: &[(&str, &str)]) -> bool {
+    for (k, v) in props {
+        if !is_valid_prop(k, v) {
+            error!("invalid prop: {}={}", k, v);
+            return false;
+        }
+    }
+
+    let mut remaining = props.to_vec();
+    for file in PROP_FILES.iter() {
+        if remaining.is_empty() {
+            break;
+        }
+        let content = match fs::read_to_string(file) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        let mut changed = false;
+        let mut lines = Vec::new();
+        for line in content.lines() {
+            let key = match line.split_once('=') {
+                Some((k, _)) if !line.trim_start().starts_with('#') => k.trim(),
+                _ => "",
+            };
+            match remaining.iter().position(|(k, _)| *k == key) {
+                Some(index) => {
+                    let (k, v) = remaining.remove(index);
+                    lines.push(format!("{}={}", k, v));
+                    changed = true;
+                }
+                None => lines.push(line.to_string()),
+            }
+        }
+        if !changed {
+            continue;
+        }
+        lines.push(String::new());
+        if let Err(e) = fs::write(file, lines.join("\n")) {
+            error!("write {} failed: {}", file, e);
+            return false;
+        }
+    }
+
+    if !remaining.is_empty() {
+        let mut content = fs::read_to_string(PROP_FILES[0]).unwrap_or_default();
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        for (k, v) in &remaining {
+            content.push_str(&format!("{}={}\n", k, v));
+        }
+        if let Err(e) = fs::write(PROP_FILES[0], content) {
+            error!("write {} failed: {}", PROP_FILES[0], e);
+            return false;
+        }
+    }
+    info!("write read-only props: {:?}", props);
+    true
+}
+
+/// Makes the guest report `model` and `manufacturer`, effective from the next guest boot.
+pub fn set_model(model: &str, manufacturer: &str) -> bool {
+    let props = [
+        ("ro.product.model", model),
+        ("ro.product.system.model", model),
+        ("ro.product.vendor.model", model),
+        ("ro.product.manufacturer", manufacturer),
+        ("ro.product.system.manufacturer", manufacturer),
+        ("ro.product.vendor.manufacturer", manufacturer),
+    ];
+    if !write_ro_props(&props) {
+        return false;
+    }
+    // read back the way init loads them, the first value of each wins
+    match props.iter().find(|(k, v)| read_prop(k).as_deref() != Some(*v)) {
+        Some((k, _)) => {
+            error!("{} still has another value after writing it", k);
+            false
+        }
+        None => true,
+    }
+}
+
+/// The guest build fingerprint, sdk, kernel and abi. It never changes, so it is read once
+/// the rootfs has it; fields missing before that are empty and read again next time.
+pub fn build_info() -> String {
+    if let Some(info) = BUILD_INFO.get() {
+        return info.clone();
+    }
+
+    let kernel = fs::read_to_string(PROC_VERSION).unwrap_or_default();
+    let fingerprint = read_prop("ro.build.fingerprint");
+    let sdk = read_prop("ro.build.version.sdk");
+    let complete = fingerprint.is_some() && sdk.is_some();
+    let fields = [
+        ("fingerprint", fingerprint.unwrap_or_default()),
+        ("sdk", sdk.unwrap_or_default()),
+        ("kernel", kernel.trim().to_string()),
+        ("abi", read_prop("ro.product.cpu.abi").unwrap_or_default()),
+    ];
+
+    let info = json::to_json(&fields);
+    if complete {
+        info!("guest build info: {}", info);
+        let _ = BUILD_INFO.set(info.clone());
+    }
+    info
+}
+
+pub struct ShellOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+fn adb(args: &[&str]) -> Option<std::process::Output> {
+    let loader = fs::read_link(LOADER_LINK).ok()?;
+    let adb: PathBuf = loader.parent()?.join(ADB_NAME);
+
+    match Command::new(adb)
+        .arg("-P")
+        .arg(ADB_PORT)
+        .args(args)
+        .env("TMPDIR", ADB_HOME)
+        .env("HOME", ADB_HOME)
+        .output()
+    {
+        Ok(output) => Some(output),
+        Err(e) => {
+            error!("run adb failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Runs `cmd` with the guest shell, `None` if the guest isn't reachable (not booted yet).
+pub fn shell(cmd: &str) -> Option<ShellOutput> {
+    let connect = adb(&["connect", ADB_TARGET])?;
+    // connected to localhost:22122 / already connected to localhost:22122
+    if !String::from_utf8_lossy(&connect.stdout).contains("connected to") {
+        error!("guest not ready, can't run: {}", cmd);
+        return None;
+    }
+
+    let output = adb(&["-s", ADB_TARGET, "shell", cmd])?;
+    Some(ShellOutput {
+        success: output.status.success(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    })
+}
+
+/// Quotes `s` as a single argument for the guest shell.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+fn is_valid_setting(namespace: &str, key: &str) -> bool {
+    SETTINGS_NAMESPACES.contains(&namespace)
+        && !key.is_empty()
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+/// Reads a guest setting, `None` if it isn't set or the guest isn't ready.
+pub fn get_setting(namespace: &str, key: &str) -> Option<String> {
+    if !is_valid_setting(namespace, key) {
+        error!("invalid setting: {} {}", namespace, key);
+        return None;
+    }
+
+    let output = shell(&format!("settings get {} {}", namespace, key))?;
+    let value = output.stdout.trim();
+    if !output.success || value == "null" {
+        return None;
+    }
+    Some(value.to_string())
+}
+
+pub fn put_setting(namespace: &str, key: &str, value: &str) -> bool {
+    if !is_valid_setting(namespace, key) {
+        error!("invalid setting: {} {}", namespace, key);
+        return false;
+    }
+
+    let cmd = format!("settings put {} {} {}", namespace, key, shell_quote(value));
+    match shell(&cmd) {
+        Some(output) if output.success => {
+            info!("setting {} {} = {}", namespace, key, value);
+            true
+        }
+        Some(output) => {
+            error!("put setting {} {} failed: {}", namespace, key, output.stderr.trim());
+            false
+        }
+        None => false,
+    }
+}
+
+// the guest treats Integer.MAX_VALUE as never
+const SCREEN_TIMEOUT_NEVER: i32 = i32::MAX;
+const MIN_SCREEN_TIMEOUT_MS: i32 = 5000;
+
+/// Sets the guest screen-off timeout in ms, 0 keeps the screen on.
+pub fn set_screen_timeout(ms: i32) -> bool {
+    let timeout = match ms {
+        0 => SCREEN_TIMEOUT_NEVER,
+        ms if ms >= MIN_SCREEN_TIMEOUT_MS => ms,
+        _ => {
+            error!("invalid screen timeout: {}", ms);
+            return false;
+        }
+    };
+
+    info!("set guest screen timeout: {}", timeout);
+    put_setting("system", "screen_off_timeout", &timeout.to_string())
+}
+
+/// Forces the guest density like `wm density`, 0 goes back to the one from the host.
+pub fn set_density(dpi: i32) -> bool {
+    let cmd = if dpi == 0 { "wm density reset".to_string() } else { format!("wm density {}", dpi) };
+    match shell(&cmd) {
+        Some(output) if output.success => true,
+        Some(output) => {
+            error!("set guest density {} failed: {}", dpi, output.stderr.trim());
+            false
+        }
+        None => false,
+    }
+}
+
+// RFC 1123 host names, which covers IPv4 addresses too
+fn is_valid_hostname(host: &str) -> bool {
+    host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Points the guest global HTTP proxy at `host:port`, port 0 clears it.
+///
+/// The `http.proxy*` props are only read at boot by apps that look at them
+/// instead of the settings, so those follow on the next boot.
+pub fn set_proxy(host: &str, port: i32) -> bool {
+    let clear = port == 0;
+    if !clear && (!is_valid_hostname(host) || !(1..=65535).contains(&port)) {
+        error!("invalid proxy: {}:{}", host, port);
+        return false;
+    }
+
+    // ":0" is what the guest settings use for no proxy
+    let (proxy, host, port) = if clear {
+        (":0".to_string(), "", String::new())
+    } else {
+        (format!("{}:{}", host, port), host, port.to_string())
+    };
+    if !put_setting("global", "http_proxy", &proxy) {
+        return false;
+    }
+    let applied = write_props(&[("http.proxyHost", host), ("http.proxyPort", &port)]);
+    info!("guest proxy: {}", if clear { "none" } else { &proxy });
+    applied
+}
+
+/// Makes the guest report airplane mode on or off, host connectivity is untouched.
+///
+/// Returns true once the guest setting holds the new state, even if the guest didn't let
+/// the shell tell its apps.
+pub fn set_airplane_mode(enabled: bool) -> bool {
+    let value = if enabled { "1" } else { "0" };
+    // newer guests have a privileged toggle that writes the setting and broadcasts it
+    let cmd = format!("cmd connectivity airplane-mode {}", if enabled { "enable" } else { "disable" });
+    if let Some(output) = shell(&cmd) {
+        if output.success && get_setting("global", "airplane_mode_on").as_deref() == Some(value) {
+            info!("guest airplane mode: {}", enabled);
+            return true;
+        }
+    }
+
+    if !put_setting("global", "airplane_mode_on", value) {
+        return false;
+    }
+    // apps only notice the setting through the broadcast, but AIRPLANE_MODE is protected:
+    // unless adbd runs as root the guest refuses it from the shell uid
+    let cmd = format!("am broadcast -a android.intent.action.AIRPLANE_MODE --ez state {}", enabled);
+    match shell(&cmd) {
+        Some(output) if output.success => info!("guest airplane mode: {}", enabled),
+        Some(output) => warn!("guest airplane mode set, but apps weren't told: {}", output.stderr.trim()),
+        None => warn!("guest airplane mode set, but apps weren't told"),
+    }
+    true
+}
+
+/// Turns the guest wifi on or off, which is all the guest apps see of the bridged network.
+pub fn set_wifi_enabled(enabled: bool) -> bool {
+    let cmd = format!("svc wifi {}", if enabled { "enable" } else { "disable" });
+    match shell(&cmd) {
+        Some(output) if output.success => {
+            info!("guest wifi: {}", enabled);
+            true
+        }
+        Some(output) => {
+            // without a wifi service the setting is the only thing left to report
+            warn!("guest svc wifi failed: {}", output.stderr.trim());
+            put_setting("global", "wifi_on", if enabled { "1" } else { "0" })
+        }
+        None => false,
+    }
+}
+
+/// Names the guest on the network and for Bluetooth, the prop is kept for the next boot.
+///
+/// The kernel hostname is shared with the host, changing it needs privileges the
+/// guest usually doesn't have, so a refusal there is only logged.
+pub fn set_hostname(name: &str) -> bool {
+    if !is_valid_hostname(name) {
+        error!("invalid hostname: {}", name);
+        return false;
+    }
+    if !write_props(&[("net.hostname", name)]) {
+        return false;
+    }
+
+    let quoted = shell_quote(name);
+    match shell(&format!("setprop net.hostname {} && hostname {}", quoted, quoted)) {
+        Some(output) if output.success => info!("guest hostname: {}", name),
+        Some(output) => warn!("set guest kernel hostname failed: {}", output.stderr.trim()),
+        None => {
+            info!("guest hostname {} applied on boot", name);
+            return true;
+        }
+    }
+    put_setting("global", "device_name", name);
+    put_setting("secure", "bluetooth_name", name);
+    true
+}
+
+/// The host model as a hostname, like `pixel-6`.
+pub fn host_hostname() -> Option<String> {
+    let mut value = [0u8; PROP_VALUE_MAX];
+    let key = b"ro.product.model\0";
+    let len = unsafe { libc::__system_property_get(key.as_ptr() as *const _, value.as_mut_ptr() as *mut _) };
+    let model = std::str::from_utf8(&value[..len.max(0) as usize]).ok()?;
+    let name: String = model
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    is_valid_hostname(name).then(|| name.to_string())
+}
+
+pub const MIN_FONT_SCALE: f32 = 0.5;
+pub const MAX_FONT_SCALE: f32 = 2.0;
+
+/// Scales guest text like the accessibility setting, the guest picks the new
+/// setting up as a configuration change.
+pub fn set_font_scale(scale: f32) -> bool {
+    if !(MIN_FONT_SCALE..=MAX_FONT_SCALE).contains(&scale) {
+        error!("font scale {} out of {}..{}", scale, MIN_FONT_SCALE, MAX_FONT_SCALE);
+        return false;
+    }
+    let applied = put_setting("system", "font_scale", &scale.to_string());
+    if applied {
+        info!("guest font scale: {}", scale);
+    }
+    applied
+}
+
+// the largest scale the guest developer options offer
+const MAX_ANIMATION_SCALE: f32 = 10.0;
+const ANIMATION_SCALES: [&'static str; 3] =
+    ["window_animation_scale", "transition_animation_scale", "animator_duration_scale"];
+
+/// Sets all three guest animation scales, 0 turns animations off.
+pub fn set_animation_scale(scale: f32) -> bool {
+    if !(0.0..=MAX_ANIMATION_SCALE).contains(&scale) {
+        error!("animation scale {} out of 0..{}", scale, MAX_ANIMATION_SCALE);
+        return false;
+    }
+    let value = scale.to_string();
+    let applied = ANIMATION_SCALES.iter().all(|key| put_setting("global", key, &value));
+    if applied {
+        info!("guest animation scale: {}", scale);
+    }
+    applied
+}
+
+const BOOT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Waits until the guest finished booting, false if it didn't within `timeout`.
+pub fn wait_for_boot(timeout: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if let Some(output) = shell("getprop sys.boot_completed") {
+            if output.stdout.trim() == "1" {
+                return true;
+            }
+        }
+        thread::sleep(BOOT_POLL_INTERVAL);
+    }
+    false
+}
+
+pub const COMPOSITION_GPU: i32 = 0;
+pub const COMPOSITION_CPU: i32 = 1;
+
+/// Picks how the guest SurfaceFlinger composes, effective from the next guest boot.
+///
+/// GPU bypasses the guest HWC and composes everything with GL, CPU falls back to
+/// software rendering; both work around a buggy HWC path.
+pub fn set_composition(mode: i32) -> bool {
+    let props: [(&str, &str); 2] = match mode {
+        COMPOSITION_GPU => [("debug.sf.hw", "1"), ("debug.sf.disable_hwc", "1")],
+        COMPOSITION_CPU => [("debug.sf.hw", "0"), ("debug.sf.disable_hwc", "1")],
+        _ => {
+            error!("invalid composition mode: {}", mode);
+            return false;
+        }
+    };
+
+    info!("set guest composition: {}", if mode == COMPOSITION_GPU { "gpu" } else { "cpu" });
+    write_props(&props)
+}
+
+// values of the guest display_color_mode setting
+const GUEST_COLOR_MODE_NATURAL: i32 = 0;
+const GUEST_COLOR_MODE_SATURATED: i32 = 2;
+
+/// Matches the guest color management to the host surface, the guest has no HDR
+/// mode of its own so that uses the saturated (native gamut) mode as well.
+pub fn set_color_mode(mode: i32) -> bool {
+    let guest_mode = match mode {
+        gpu::COLOR_MODE_SRGB => GUEST_COLOR_MODE_NATURAL,
+        gpu::COLOR_MODE_WIDE_GAMUT | gpu::COLOR_MODE_HDR => GUEST_COLOR_MODE_SATURATED,
+        _ => {
+            error!("invalid color mode: {}", mode);
+            return false;
+        }
+    };
+    put_setting("system", "display_color_mode", &guest_mode.to_string())
+}
+
+// hides status and navigation bars of every guest app
+const IMMERSIVE_POLICY: &'static str = "immersive.full=*";
+
+pub fn set_immersive(enabled: bool) -> bool {
+    info!("set guest immersive: {}", enabled);
+    let policy = if enabled { IMMERSIVE_POLICY } else { "null" };
+    put_setting("global", "policy_control", policy)
+}
+
+pub fn is_immersive() -> bool {
+    get_setting("global", "policy_control").as_deref() == Some(IMMERSIVE_POLICY)
+}
+
+// AudioManager.STREAM_MUSIC
+const STREAM_MUSIC: i32 = 3;
+
+// Current media volume index and its range, from "volume is 5 in range [0..15]".
+fn media_volume() -> Option<(i32, i32, i32)> {
+    let output = shell(&format!("media volume --stream {} --get", STREAM_MUSIC))?;
+    let line = output.stdout.lines().find(|line| line.contains("volume is"))?;
+    let (_, rest) = line.split_once("volume is ")?;
+    let (index, range) = rest.split_once(" in range [")?;
+    let (min, max) = range.trim_end_matches(']').split_once("..")?;
+    Some((index.trim().parse().ok()?, min.trim().parse().ok()?, max.trim().parse().ok()?))
+}
+
+/// Shows or hides the guest's "Pointer location" overlay.
+pub fn set_pointer_debug(enabled: bool) -> bool {
+    let value = if enabled { "1" } else { "0" };
+    let applied = put_setting("system", "pointer_location", value);
+    if applied {
+        info!("guest pointer location: {}", enabled);
+    } else {
+        error!("set guest pointer location to {} failed", enabled);
+    }
+    applied
+}
+
+/// Sets the guest media volume in percent, mapped onto the stream's index range.
+pub fn set_volume(percent: i32) -> bool {
+    let percent = percent.clamp(0, 100);
+    let (_, min, max) = match media_volume() {
+        Some(v) => v,
+        None => {
+            error!("guest media volume unavailable");
+            return false;
+        }
+    };
+
+    let index = min + ((max - min) * percent + 50) / 100;
+    info!("set guest volume: {}% (index {})", percent, index);
+    match shell(&format!("media volume --stream {} --set {}", STREAM_MUSIC, index)) {
+        Some(output) if output.success => true,
+        Some(output) => {
+            error!("set guest volume failed: {}", output.stderr.trim());
+            false
+        }
+        None => false,
+    }
+}
+
+/// The guest media volume in percent, -1 if the guest isn't ready.
+pub fn volume() -> i32 {
+    match media_volume() {
+        Some((index, min, max)) if max > min => ((index - min) * 100 + (max - min) / 2) / (max - min),
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
+/// Refresh rate the guest SurfaceFlinger runs its vsync at, `None` if the guest isn't ready.
+pub fn refresh_rate() -> Option<f32> {
+    let output = shell("dumpsys SurfaceFlinger")?;
+    // "refresh-rate              : 30.000000 fps"
+    let line = output.stdout.lines().find(|line| line.trim_start().starts_with("refresh-rate"))?;
+    let (_, value) = line.split_once(':')?;
+    value.trim().trim_end_matches("fps").trim().parse().ok()
+}
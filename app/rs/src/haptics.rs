@@ -0,0 +1,143 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use jni::objects::JValue;
+use unix_socket::UnixListener;
+
+use log::{error, info};
+
+use crate::callback;
+
+const VIBRATOR_PATH: &'static str = "/data/data/io.twoyi/rootfs/dev/socket/vibrator";
+
+// requests from the guest vibrator HAL stub, all integers are little endian.
+// ON:       [1][u32 duration ms][i16 amplitude]
+// OFF:      [2]
+// WAVEFORM: [3][u16 count] count * ([u32 duration ms][i16 amplitude])
+// amplitudes mean what they mean to VibrationEffect: 1..255, 0 is off and -1 the default.
+const MSG_ON: u8 = 1;
+const MSG_OFF: u8 = 2;
+const MSG_WAVEFORM: u8 = 3;
+
+// guard against a bogus count from the guest
+const MAX_WAVEFORM_SEGMENTS: usize = 64;
+
+// VibrationEffect.DEFAULT_AMPLITUDE
+const DEFAULT_AMPLITUDE: i32 = -1;
+const MAX_AMPLITUDE: i32 = 255;
+
+static HAPTICS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn start_haptics_server() {
+    thread::spawn(|| {
+        haptics_server();
+    });
+}
+
+pub fn set_haptics_enabled(enabled: bool) {
+    info!("haptics enabled: {}", enabled);
+    HAPTICS_ENABLED.store(enabled, Ordering::Release);
+}
+
+fn haptics_server() {
+    let _ = std::fs::remove_file(VIBRATOR_PATH);
+    let listener = match UnixListener::bind(VIBRATOR_PATH) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("bind vibrator socket failed: {}", e);
+            return;
+        }
+    };
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(s) => s,
+            Err(_) => break,
+        };
+
+        info!("vibrator hal connected!");
+        while handle_request(&mut stream).is_ok() {}
+        info!("vibrator hal disconnected");
+    }
+}
+
+fn handle_request<R: Read>(stream: &mut R) -> std::io::Result<()> {
+    let mut kind = [0u8; 1];
+    stream.read_exact(&mut kind)?;
+
+    match kind[0] {
+        MSG_ON => {
+            let (duration, amplitude) = read_segment(stream)?;
+            vibrate(&[(duration, amplitude)]);
+        }
+        MSG_OFF => vibrate(&[]),
+        MSG_WAVEFORM => {
+            let mut count = [0u8; 2];
+            stream.read_exact(&mut count)?;
+            let count = u16::from_le_bytes(count) as usize;
+
+            let mut segments = Vec::with_capacity(count.min(MAX_WAVEFORM_SEGMENTS));
+            for _ in 0..count {
+                segments.push(read_segment(stream)?);
+            }
+            segments.truncate(MAX_WAVEFORM_SEGMENTS);
+            vibrate(&segments);
+        }
+        other => {
+            error!("unknown vibrator request: {}", other);
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+    }
+    Ok(())
+}
+
+fn read_segment<R: Read>(stream: &mut R) -> std::io::Result<(i64, i32)> {
+    let mut buf = [0u8; 6];
+    stream.read_exact(&mut buf)?;
+    let duration = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let amplitude = i16::from_le_bytes([buf[4], buf[5]]) as i32;
+    Ok((duration as i64, amplitude.clamp(DEFAULT_AMPLITUDE, MAX_AMPLITUDE)))
+}
+
+// an empty waveform cancels the current vibration.
+fn vibrate(segments: &[(i64, i32)]) {
+    if !HAPTICS_ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+
+    match segments {
+        // a single segment at amplitude 0 is as much off as no segment at all
+        [] | [(_, 0)] => {
+            callback::call_static("onGuestVibrate", "(J)V", &[JValue::Long(0)]);
+        }
+        [(duration, DEFAULT_AMPLITUDE)] => {
+            callback::call_static("onGuestVibrate", "(J)V", &[JValue::Long(*duration)]);
+        }
+        _ => callback::with_env(|env| {
+            let timings: Vec<i64> = segments.iter().map(|(d, _)| *d).collect();
+            let amplitudes: Vec<i32> = segments.iter().map(|(_, a)| *a).collect();
+
+            let (jtimings, jamplitudes) = match (
+                env.new_long_array(timings.len() as i32),
+                env.new_int_array(amplitudes.len() as i32),
+            ) {
+                (Ok(t), Ok(a)) => (t, a),
+                _ => return,
+            };
+            let _ = env.set_long_array_region(&jtimings, 0, &timings);
+            let _ = env.set_int_array_region(&jamplitudes, 0, &amplitudes);
+
+            callback::call_static_in(
+                env,
+                "onGuestVibrateWaveform",
+                "([J[I)V",
+                &[JValue::Object(&jtimings), JValue::Object(&jamplitudes)],
+            );
+        }),
+    }
+}
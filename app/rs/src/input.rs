@@ -4,27 +4,37 @@
 
 use libc::*;
 use libc::{c_char, c_int};
-use ndk::event::{MotionAction, MotionEvent};
+use ndk::event::{MotionAction, MotionEvent, Source};
 use std::mem;
 use std::thread;
-use std::{io::Write};
+use std::time::{Duration, Instant};
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
 use uinput_sys::*;
 
-use std::sync::mpsc::{ SyncSender };
-use std::sync::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
 use once_cell::sync::Lazy;
 
-use log::{info, error};
+use crate::{callback, config, container, keymap};
+use jni::objects::JValue;
+
+use log::{info, error, warn};
 
 const FF_MAX: u16 = 0x7f;
 
-const TOUCH_PATH: &'static str = "/data/data/io.twoyi/rootfs/dev/input/touch";
+pub(crate) const TOUCH_PATH: &'static str = "/data/data/io.twoyi/rootfs/dev/input/touch";
 const TOUCH_DEVICE_NAME: &'static str = "vtouch";
 const TOUCH_DEVICE_UNIQUE_ID: &'static str = "<vtouch 0>";
 
 const KEY_DEVICE_NAME: &'static str = "vkey";
 const KEY_DEVICE_UNIQUE_ID: &'static str = "<keyboard 0>";
-const KEY_PATH: &'static str = "/data/data/io.twoyi/rootfs/dev/input/key0";
+pub(crate) const KEY_PATH: &'static str = "/data/data/io.twoyi/rootfs/dev/input/key0";
+
+const MOUSE_DEVICE_NAME: &'static str = "vmouse";
+const MOUSE_DEVICE_UNIQUE_ID: &'static str = "<mouse 0>";
+const MOUSE_PATH: &'static str = "/data/data/io.twoyi/rootfs/dev/input/mouse0";
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -49,6 +59,10 @@ unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
     ::std::slice::from_raw_parts((p as *const T) as *const u8, ::std::mem::size_of::<T>())
 }
 
+unsafe fn slice_as_u8_slice<T: Sized>(p: &[T]) -> &[u8] {
+    ::std::slice::from_raw_parts(p.as_ptr() as *const u8, ::std::mem::size_of::<T>() * p.len())
+}
+
 fn copy_to_cstr<const COUNT: usize>(data: &str, arr: &mut [u8; COUNT]) {
     let cstr = std::ffi::CString::new(data).expect("create cstring failed");
     let bytes = cstr.as_bytes_with_nul();
@@ -59,22 +73,335 @@ fn copy_to_cstr<const COUNT: usize>(data: &str, arr: &mut [u8; COUNT]) {
     arr[..len].copy_from_slice(bytes);
 }
 
-const MAX_POINTERS: usize = 5;
+// size of the slot table, the guest device gets `max_pointers` from input.conf
+const MAX_POINTERS: usize = 16;
+const DEFAULT_MT_SLOTS: usize = 10;
+
+const DEFAULT_TOUCH_QUEUE_CAPACITY: usize = 100;
+const DEFAULT_KEY_QUEUE_CAPACITY: usize = 10;
+const DEFAULT_MOUSE_QUEUE_CAPACITY: usize = 50;
+
+// reports written at once at most while a flush interval is set
+const MAX_BATCH_REPORTS: u64 = 16;
+// longest a MOVE may wait for others to share its write
+const MAX_FLUSH_INTERVAL_MS: u64 = 50;
+
+static FLUSH_INTERVAL_MS: Lazy<AtomicU64> = Lazy::new(|| {
+    AtomicU64::new(config::get_or(config::INPUT_CONF, "flush_interval_ms", 0u64).min(MAX_FLUSH_INTERVAL_MS))
+});
+
+// One report is the events up to and including their SYN_REPORT, it is queued
+// and written as a whole so the guest never sees half a report.
+struct Report {
+    events: Vec<input_event>,
+    // only MOVE reports may be dropped, losing a down/up leaves a stuck pointer
+    droppable: bool,
+}
+
+struct QueueState {
+    reports: VecDeque<Report>,
+    capacity: usize,
+    // bumped on every new client so a stale writer thread stops consuming
+    generation: u64,
+    connected: bool,
+}
+
+/// Bounded queue between the JNI entry points and the thread writing to the guest device.
+struct InputQueue {
+    state: Mutex<QueueState>,
+    cond: Condvar,
+    dropped: AtomicU64,
+    // reports handed to the writer and the writes they took, their ratio is the batching gain
+    reports: AtomicU64,
+    writes: AtomicU64,
+}
+
+impl InputQueue {
+    fn new() -> InputQueue {
+        InputQueue {
+            state: Mutex::new(QueueState {
+                reports: VecDeque::new(),
+                capacity: 0,
+                generation: 0,
+                connected: false,
+            }),
+            cond: Condvar::new(),
+            dropped: AtomicU64::new(0),
+            reports: AtomicU64::new(0),
+            writes: AtomicU64::new(0),
+        }
+    }
+
+    fn push(&self, events: Vec<input_event>, droppable: bool) {
+        if events.is_empty() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if !state.connected {
+            return;
+        }
+
+        if state.reports.len() >= state.capacity {
+            // full: make room by dropping the oldest MOVE, never a down/up
+            if let Some(pos) = state.reports.iter().position(|r| r.droppable) {
+                state.reports.remove(pos);
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            } else if droppable {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+
+        state.reports.push_back(Report { events, droppable });
+        self.cond.notify_one();
+    }
+
+    // Blocks until a report is available, `None` once the client of `generation` is gone.
+    // With a flush interval MOVE reports are collected into one write for up to that long,
+    // a down/up or a full batch goes out right away.
+    fn pop(&self, generation: u64) -> Option<Vec<input_event>> {
+        let interval = Duration::from_millis(FLUSH_INTERVAL_MS.load(Ordering::Relaxed));
+        let mut state = self.state.lock().unwrap();
+        let mut batch = Vec::new();
+        let mut reports = 0;
+        let mut deadline: Option<Instant> = None;
+        loop {
+            if !state.connected || state.generation != generation {
+                return None;
+            }
+            while let Some(report) = state.reports.pop_front() {
+                batch.extend(report.events);
+                reports += 1;
+                if !report.droppable || interval.is_zero() || reports >= MAX_BATCH_REPORTS {
+                    return Some(self.flush(batch, reports));
+                }
+            }
+
+            match deadline {
+                None if batch.is_empty() => state = self.cond.wait(state).unwrap(),
+                None => deadline = Some(Instant::now() + interval),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Some(self.flush(batch, reports));
+                    }
+                    state = self.cond.wait_timeout(state, deadline - now).unwrap().0;
+                }
+            }
+        }
+    }
+
+    fn flush(&self, batch: Vec<input_event>, reports: u64) -> Vec<input_event> {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.reports.fetch_add(reports, Ordering::Relaxed);
+        batch
+    }
+
+    fn connect(&self, capacity: usize) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.reports.clear();
+        state.capacity = capacity.max(1);
+        state.generation += 1;
+        state.connected = true;
+        self.cond.notify_all();
+        state.generation
+    }
+
+    fn disconnect(&self, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            state.connected = false;
+            state.reports.clear();
+        }
+        self.cond.notify_all();
+    }
+
+    // Drops the client for good, its writer thread stops at the next pop.
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.connected = false;
+        state.generation += 1;
+        state.reports.clear();
+        self.cond.notify_all();
+    }
+
+    fn stats(&self) -> String {
+        let state = self.state.lock().unwrap();
+        format!(
+            "capacity={} queued={} dropped={} reports={} writes={}",
+            state.capacity,
+            state.reports.len(),
+            self.dropped.load(Ordering::Relaxed),
+            self.reports.load(Ordering::Relaxed),
+            self.writes.load(Ordering::Relaxed)
+        )
+    }
+}
+
+static TOUCH_QUEUE: Lazy<InputQueue> = Lazy::new(InputQueue::new);
+static KEY_QUEUE: Lazy<InputQueue> = Lazy::new(InputQueue::new);
+static MOUSE_QUEUE: Lazy<InputQueue> = Lazy::new(InputQueue::new);
+
+// host pointer id + 1 tracked by each slot, 0 while the slot is free
+static G_INPUT_MT: Lazy<Mutex<[i32;MAX_POINTERS]>> = Lazy::new(|| {std::sync::Mutex::new([0i32;MAX_POINTERS])});
+
+static MT_SLOTS: Lazy<usize> = Lazy::new(|| {
+    config::get_or(config::INPUT_CONF, "max_pointers", DEFAULT_MT_SLOTS).clamp(1, MAX_POINTERS)
+});
+
+static INPUT_ENABLED: AtomicBool = AtomicBool::new(true);
+
+// replay the samples a MOVE batches up, otherwise fast strokes look jagged in the guest
+static HISTORICAL_SAMPLES: Lazy<AtomicBool> = Lazy::new(|| {
+    AtomicBool::new(config::get_or(config::INPUT_CONF, "historical_samples", true))
+});
+
+// serializes init and shutdown, true while the device servers run
+static INPUT_RUNNING: Mutex<bool> = Mutex::new(false);
+// servers of an older epoch quit as soon as they notice
+static INPUT_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// Creates the guest touch, key and mouse devices, a no-op while they exist already.
+pub fn input_init(width: i32, height: i32) {
+    let mut running = INPUT_RUNNING.lock().unwrap();
+    if *running {
+        info!("input system already running");
+        return;
+    }
+    *running = true;
+
+    clear_shutdown();
+    let epoch = INPUT_EPOCH.fetch_add(1, Ordering::AcqRel) + 1;
+    info!("input init {}x{}", width, height);
+    TOUCH_WIDTH.store(width, Ordering::Relaxed);
+    TOUCH_HEIGHT.store(height, Ordering::Relaxed);
+    spawn_input_thread("twoyi-touch", move || touch_server(width, height, epoch));
+    spawn_input_thread("twoyi-key", move || key_server(epoch));
+    spawn_input_thread("twoyi-mouse", move || mouse_server(epoch));
+}
+
+// the servers only shuffle small reports around
+const INPUT_STACK_SIZE: usize = 512 * 1024;
+
+// named, so that the threads can be told apart in ANR and tombstone traces
+fn spawn_input_thread<F: FnOnce() + Send + 'static>(name: &str, f: F) {
+    if let Err(e) = thread::Builder::new().name(name.to_string()).stack_size(INPUT_STACK_SIZE).spawn(f) {
+        error!("spawn {} failed: {}", name, e);
+    }
+}
 
-static INPUT_SENDER: Lazy<Mutex<Option<SyncSender<input_event>>>> = Lazy::new(|| { Mutex::new(None)});
-static KEY_SENDER: Lazy<Mutex<Option<SyncSender<input_event>>>> = Lazy::new(|| { Mutex::new(None)});
+/// Removes the guest touch, key and mouse devices, `input_init` may create them again.
+pub fn input_shutdown() {
+    let mut running = INPUT_RUNNING.lock().unwrap();
+    if !*running {
+        return;
+    }
+    *running = false;
 
-pub fn start_input_system(width: i32, height: i32) {
-    thread::spawn(move || {
-        touch_server(width, height);
-    });
-    thread::spawn(|| {
-        key_server();
-    });
+    info!("input shutdown");
+    INPUT_EPOCH.fetch_add(1, Ordering::AcqRel);
+    *TOUCH_RANGES.lock().unwrap() = None;
+    if let Ok(mut mt) = G_INPUT_MT.lock() {
+        *mt = [0; MAX_POINTERS];
+    }
+    TOUCH_QUEUE.close();
+    KEY_QUEUE.close();
+    MOUSE_QUEUE.close();
+
+    // wakes the servers and writers waiting for a client or a slow reader
+    let one: u64 = 1;
+    unsafe { libc::write(*SHUTDOWN_FD, &one as *const u64 as *const c_void, mem::size_of::<u64>()) };
+}
+
+fn is_current(epoch: u64) -> bool {
+    INPUT_EPOCH.load(Ordering::Acquire) == epoch
+}
+
+// Readable from input_shutdown until the next input_init, every waiting input thread polls it.
+static SHUTDOWN_FD: Lazy<RawFd> = Lazy::new(|| unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) });
+
+const DEFAULT_POLL_INTERVAL_MS: i32 = 100;
+
+// how long an input thread may wait before it looks at the epoch again, even without the eventfd
+static POLL_INTERVAL_MS: Lazy<i32> = Lazy::new(|| {
+    config::get_or(config::INPUT_CONF, "poll_interval_ms", DEFAULT_POLL_INTERVAL_MS).clamp(10, 1000)
+});
+
+fn clear_shutdown() {
+    let mut count: u64 = 0;
+    unsafe { libc::read(*SHUTDOWN_FD, &mut count as *mut u64 as *mut c_void, mem::size_of::<u64>()) };
+}
+
+// Waits until `fd` has `events`, false once input shut down or `epoch` is over.
+fn wait_for(fd: RawFd, events: i16, epoch: u64) -> bool {
+    loop {
+        if !is_current(epoch) {
+            return false;
+        }
+        let mut fds = [
+            libc::pollfd { fd, events, revents: 0 },
+            libc::pollfd { fd: *SHUTDOWN_FD, events: libc::POLLIN, revents: 0 },
+        ];
+        let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, *POLL_INTERVAL_MS) };
+        if ret < 0 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return false;
+        }
+        if fds[1].revents & libc::POLLIN != 0 {
+            return false;
+        }
+        // errors and hangups too, the next accept or write reports them
+        if fds[0].revents != 0 {
+            return true;
+        }
+    }
+}
+
+// The next client of the non-blocking `listener`, `None` once input shut down.
+fn accept_client(listener: &unix_socket::UnixListener, epoch: u64) -> Option<unix_socket::UnixStream> {
+    loop {
+        if !wait_for(listener.as_raw_fd(), libc::POLLIN, epoch) {
+            return None;
+        }
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // accepted sockets don't inherit O_NONBLOCK
+                return stream.set_nonblocking(true).ok().map(|_| stream);
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => {
+                error!("accept failed: {}", e);
+                return None;
+            }
+        }
+    }
+}
+
+// Writes all of `data` to the non-blocking `stream`, a guest that stops reading can't
+// hold up input_shutdown.
+fn write_client(stream: &mut unix_socket::UnixStream, data: &[u8], epoch: u64) -> io::Result<()> {
+    let mut written = 0;
+    while written < data.len() {
+        match stream.write(&data[written..]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if !wait_for(stream.as_raw_fd(), libc::POLLOUT, epoch) {
+                    return Err(io::Error::new(io::ErrorKind::Other, "input shut down"));
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
 }
 
 pub fn input_event_write(
-    tx: &std::sync::mpsc::SyncSender<input_event>, // Ubah Sender menjadi SyncSender
+    report: &mut Vec<input_event>,
     kind: i32,
     code: i32,
     val: i32,
@@ -92,60 +419,501 @@ pub fn input_event_write(
         },
     };
 
-    // GUNAKAN INI: Jangan biarkan input menumpuk jika client sedang sibuk
-    // Jika channel penuh atau error, abaikan saja (drop frame input)
-    let _ = tx.send(ev);
+    report.push(ev);
+}
+
+// The guest runs on the host kernel, so host event times in CLOCK_MONOTONIC
+// nanoseconds are already in the guest timebase, only the unit changes.
+fn event_timeval(time_ns: i64) -> timeval {
+    timeval {
+        tv_sec: (time_ns / 1_000_000_000) as _,
+        tv_usec: ((time_ns % 1_000_000_000) / 1000) as _,
+    }
+}
+
+// Stamps a report with the host event time instead of the time it was written, the
+// guest computes fling velocity from these. Times from the future are left alone.
+fn stamp_report(report: &mut [input_event], time_ns: i64) {
+    if time_ns <= 0 {
+        return;
+    }
+    let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    if time_ns > now.tv_sec as i64 * 1_000_000_000 + now.tv_nsec as i64 {
+        return;
+    }
+
+    let time = event_timeval(time_ns);
+    for ev in report.iter_mut() {
+        ev.time = time;
+    }
+}
+
+/// Queue capacity and dropped MOVE reports, for diagnosing input lag.
+pub fn queue_stats() -> String {
+    format!("touch: {}; key: {}; mouse: {}", TOUCH_QUEUE.stats(), KEY_QUEUE.stats(), MOUSE_QUEUE.stats())
+}
+
+/// Gates all input to the guest, e.g. while a host dialog covers the surface.
+pub fn set_input_enabled(enabled: bool) {
+    if INPUT_ENABLED.swap(enabled, Ordering::AcqRel) == enabled {
+        return;
+    }
+
+    info!("input enabled: {}", enabled);
+    if !enabled {
+        release_all_pointers();
+    }
+}
+
+/// Batches MOVE reports into one write per `interval_ms`, 0 writes every report right away.
+pub fn set_flush_interval(interval_ms: i32) {
+    let interval_ms = (interval_ms.max(0) as u64).min(MAX_FLUSH_INTERVAL_MS);
+    info!("input flush interval: {} ms, before: {}", interval_ms, queue_stats());
+    FLUSH_INTERVAL_MS.store(interval_ms, Ordering::Relaxed);
+    config::set(config::INPUT_CONF, "flush_interval_ms", &interval_ms.to_string());
+}
+
+pub fn set_historical_samples(enabled: bool) {
+    info!("historical touch samples: {}", enabled);
+    HISTORICAL_SAMPLES.store(enabled, Ordering::Release);
+    config::set(config::INPUT_CONF, "historical_samples", &enabled.to_string());
+}
+
+/// Affine correction for digitizers that report slightly offset coordinates.
+#[derive(Clone, Copy)]
+struct Calibration {
+    x_scale: f32,
+    x_offset: f32,
+    y_scale: f32,
+    y_offset: f32,
+}
+
+static CALIBRATION: Lazy<Mutex<Calibration>> = Lazy::new(|| {
+    Mutex::new(Calibration {
+        x_scale: config::get_or(config::INPUT_CONF, "x_scale", 1.0),
+        x_offset: config::get_or(config::INPUT_CONF, "x_offset", 0.0),
+        y_scale: config::get_or(config::INPUT_CONF, "y_scale", 1.0),
+        y_offset: config::get_or(config::INPUT_CONF, "y_offset", 0.0),
+    })
+});
+
+pub fn set_touch_calibration(x_scale: f32, x_offset: f32, y_scale: f32, y_offset: f32) {
+    info!("touch calibration: x * {} + {}, y * {} + {}", x_scale, x_offset, y_scale, y_offset);
+    *CALIBRATION.lock().unwrap() = Calibration { x_scale, x_offset, y_scale, y_offset };
+
+    config::set(config::INPUT_CONF, "x_scale", &x_scale.to_string());
+    config::set(config::INPUT_CONF, "x_offset", &x_offset.to_string());
+    config::set(config::INPUT_CONF, "y_scale", &y_scale.to_string());
+    config::set(config::INPUT_CONF, "y_offset", &y_offset.to_string());
+}
+
+// No rotation is applied for a guest that rotated its display: the framebuffer the host
+// shows stays in panel orientation like the touch device, the guest input reader rotates
+// the raw coordinates itself.
+fn calibrate(x: f32, y: f32) -> (f32, f32) {
+    let c = *CALIBRATION.lock().unwrap();
+    clamp_to_device(x * c.x_scale + c.x_offset, y * c.y_scale + c.y_offset)
+}
+
+// size of the guest touch device, set by input_init
+static TOUCH_WIDTH: AtomicI32 = AtomicI32::new(0);
+static TOUCH_HEIGHT: AtomicI32 = AtomicI32::new(0);
+
+// this many clamped samples hint at a calibration problem rather than edge swipes
+const CLAMP_WARN_THRESHOLD: u64 = 100;
+static CLAMPED_SAMPLES: AtomicU64 = AtomicU64::new(0);
+
+// Digitizers report slightly out of bounds coordinates on edge swipes, the guest
+// device would wrap or reject them.
+fn clamp_to_device(x: f32, y: f32) -> (f32, f32) {
+    let max_x = (TOUCH_WIDTH.load(Ordering::Relaxed) - 1).max(0) as f32;
+    let max_y = (TOUCH_HEIGHT.load(Ordering::Relaxed) - 1).max(0) as f32;
+    let clamped = (x.clamp(0.0, max_x), y.clamp(0.0, max_y));
+
+    if clamped != (x, y) && CLAMPED_SAMPLES.fetch_add(1, Ordering::Relaxed) + 1 == CLAMP_WARN_THRESHOLD {
+        warn!("{} touch samples out of the {}x{} device, check the touch calibration",
+            CLAMP_WARN_THRESHOLD, max_x + 1.0, max_y + 1.0);
+    }
+    clamped
+}
+
+// gestures the host can reserve, as bit flags
+pub const GESTURE_THREE_FINGER_SWIPE_UP: i32 = 1;
+pub const GESTURE_THREE_FINGER_SWIPE_DOWN: i32 = 2;
+
+// how far the three fingers have to travel vertically, in surface pixels
+const GESTURE_SWIPE_DISTANCE: f32 = 200.0;
+
+static RESERVED_GESTURES: AtomicI32 = AtomicI32::new(0);
+
+#[derive(Default)]
+struct GestureState {
+    // centroid when the third finger went down
+    start_y: Option<f32>,
+    // the gesture went to the host, swallow the rest of this touch
+    consumed: bool,
+}
+
+static GESTURE: Lazy<Mutex<GestureState>> = Lazy::new(|| Mutex::new(GestureState::default()));
+
+pub fn set_reserved_gestures(gestures: i32) {
+    info!("reserved gestures: {:#x}", gestures);
+    RESERVED_GESTURES.store(gestures, Ordering::Release);
+}
+
+// Everything is forwarded to the guest right away; only once a reserved gesture
+// is recognized the guest touch is cancelled and the rest goes to the host.
+// Returns true if the event must not reach the guest.
+fn filter_gesture(ev: &MotionEvent, action: MotionAction) -> bool {
+    let mut state = GESTURE.lock().unwrap();
+    if action == MotionAction::Down {
+        *state = GestureState::default();
+    }
+
+    if state.consumed {
+        if action == MotionAction::Up || action == MotionAction::Cancel {
+            *state = GestureState::default();
+        }
+        return true;
+    }
+
+    let reserved = RESERVED_GESTURES.load(Ordering::Acquire);
+    if reserved == 0 {
+        return false;
+    }
+
+    let count = ev.pointer_count();
+    let centroid_y = ev.pointers().map(|p| p.y()).sum::<f32>() / count as f32;
+    match action {
+        MotionAction::PointerDown if count == 3 => state.start_y = Some(centroid_y),
+        MotionAction::PointerDown | MotionAction::PointerUp | MotionAction::Up => state.start_y = None,
+        MotionAction::Move => {
+            if let Some(start_y) = state.start_y {
+                let dy = centroid_y - start_y;
+                let gesture = if dy <= -GESTURE_SWIPE_DISTANCE {
+                    GESTURE_THREE_FINGER_SWIPE_UP
+                } else if dy >= GESTURE_SWIPE_DISTANCE {
+                    GESTURE_THREE_FINGER_SWIPE_DOWN
+                } else {
+                    0
+                };
+
+                if gesture & reserved != 0 {
+                    state.consumed = true;
+                    drop(state);
+
+                    info!("host gesture: {}", gesture);
+                    release_all_pointers();
+                    thread::spawn(move || {
+                        callback::call_static("onHostGesture", "(I)V", &[JValue::Int(gesture)]);
+                    });
+                    return true;
+                }
+            }
+        }
+        _ => (),
+    }
+    false
+}
+
+fn find_slot(mt: &[i32; MAX_POINTERS], pointer_id: i32) -> Option<usize> {
+    mt[..*MT_SLOTS].iter().position(|state| *state == pointer_id + 1)
+}
+
+// A new pointer takes the lowest free slot. Once all of them are taken the newest
+// pointers are dropped until they go up, the ones already down are never touched.
+fn claim_slot(mt: &mut [i32; MAX_POINTERS], pointer_id: i32) -> Option<usize> {
+    if let Some(slot) = find_slot(mt, pointer_id) {
+        return Some(slot);
+    }
+    let slot = mt[..*MT_SLOTS].iter().position(|state| *state == 0)?;
+    mt[slot] = pointer_id + 1;
+    Some(slot)
+}
+
+// Lifts every finger the guest still thinks is down.
+fn release_all_pointers() {
+    let mut down = [false; MAX_POINTERS];
+    if let Ok(mut mt) = G_INPUT_MT.lock() {
+        for (slot, state) in mt.iter_mut().enumerate() {
+            down[slot] = *state != 0;
+            *state = 0;
+        }
+    }
+
+    // a held back touch never reached the guest
+    LONG_PRESS.lock().unwrap().pending = None;
+
+    if !down.iter().any(|d| *d) {
+        return;
+    }
+    let mut report = Vec::new();
+    write_release(&mut report, &down);
+    TOUCH_QUEUE.push(report, false);
+}
+
+fn write_release(tx: &mut Vec<input_event>, down: &[bool; MAX_POINTERS]) {
+    for (slot, _) in down.iter().enumerate().filter(|(_, down)| **down) {
+        input_event_write(tx, EV_ABS, ABS_MT_SLOT, slot as i32);
+        input_event_write(tx, EV_ABS, ABS_MT_TRACKING_ID, -1);
+    }
+    input_event_write(tx, EV_KEY, BTN_TOUCH, 0);
+    input_event_write(tx, EV_SYN, SYN_REPORT, 0);
 }
 
 // Di handle_touch, kita buat pengiriman event lebih "mandiri"
-pub fn handle_touch(ev: MotionEvent) {
+pub fn handle_touch(ev: &MotionEvent) {
+    if !INPUT_ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+    crate::wake_renderer();
+
+    // a mouse is its own guest device, the guest shows a cursor for it
+    if ev.source() == Source::Mouse {
+        handle_mouse(ev);
+        return;
+    }
+
     let action = ev.action();
+    if filter_gesture(ev, action) {
+        return;
+    }
+
     let pointer_index = ev.pointer_index();
     let pointer = ev.pointer_at_index(pointer_index);
     let pointer_id = pointer.pointer_id();
-    let x = pointer.x();
-    let y = pointer.y();
-    let pressure = pointer.pressure();
+    let (x, y) = calibrate(pointer.x(), pointer.y());
+    let sample = TouchSample { x, y, pressure: pointer.pressure(), time_ns: ev.event_time() };
 
-    static G_INPUT_MT: Lazy<Mutex<[i32;MAX_POINTERS]>> = Lazy::new(|| {std::sync::Mutex::new([0i32;MAX_POINTERS])});
+    let mut history = Vec::new();
+    if action == MotionAction::Move
+        && ev.source() == Source::Touchscreen
+        && HISTORICAL_SAMPLES.load(Ordering::Acquire)
+    {
+        // oldest first, each one becomes its own MOVE before the current sample
+        for past in ev.history() {
+            if let Some(p) = past.pointers().nth(pointer_index) {
+                let (hx, hy) = calibrate(p.x(), p.y());
+                history.push(TouchSample { x: hx, y: hy, pressure: p.pressure(), time_ns: past.event_time() });
+            }
+        }
+    }
 
     // Update state MT dalam scope kecil agar lock cepat dilepas
-    {
-        if let Ok(mut mt) = G_INPUT_MT.try_lock() {
-            match action {
-                MotionAction::Down | MotionAction::PointerDown => mt[pointer_id as usize] = 1,
-                MotionAction::Up | MotionAction::PointerUp | MotionAction::Cancel => mt[pointer_id as usize] = 0,
-                _ => (),
+    let reports = match G_INPUT_MT.lock() {
+        Ok(mut mt) => touch_reports(&mut mt, action, pointer_id, &history, sample),
+        Err(_) => return,
+    };
+
+    // KIRIM EVENT: the UI thread only queues, the injection thread writes to the guest
+    for (report, at) in reports {
+        push_touch(report, action, at.x, at.y);
+    }
+}
+
+// One sample of a host pointer, already in guest device coordinates.
+#[derive(Clone, Copy)]
+struct TouchSample {
+    x: f32,
+    y: f32,
+    pressure: f32,
+    time_ns: i64,
+}
+
+// Updates the slot table for `action` of `pointer_id` and builds the reports the guest
+// gets for it, a MOVE gets one per historical sample first. Nothing for a pointer without a slot.
+fn touch_reports(
+    mt: &mut [i32; MAX_POINTERS],
+    action: MotionAction,
+    pointer_id: i32,
+    history: &[TouchSample],
+    sample: TouchSample,
+) -> Vec<(Vec<input_event>, TouchSample)> {
+    // slots that were still down when the host cancelled the gesture
+    let mut cancelled = [false; MAX_POINTERS];
+
+    let slot = match action {
+        MotionAction::Down | MotionAction::PointerDown => claim_slot(mt, pointer_id),
+        MotionAction::Up | MotionAction::PointerUp => {
+            let slot = find_slot(mt, pointer_id);
+            if let Some(slot) = slot {
+                mt[slot] = 0;
+            }
+            slot
+        }
+        MotionAction::Cancel => {
+            for (slot, state) in mt.iter_mut().enumerate() {
+                cancelled[slot] = *state != 0;
+                *state = 0;
             }
+            Some(0)
+        }
+        _ => find_slot(mt, pointer_id),
+    };
+    let slot = match slot {
+        Some(slot) => slot as i32,
+        None => {
+            if action == MotionAction::Down || action == MotionAction::PointerDown {
+                info!("all {} touch slots taken, drop pointer {}", *MT_SLOTS, pointer_id);
+            }
+            return Vec::new();
+        }
+    };
+
+    let mut reports = Vec::with_capacity(history.len() + 1);
+    if action == MotionAction::Move {
+        for past in history {
+            let mut report = Vec::with_capacity(5);
+            let tx = &mut report;
+            input_event_write(tx, EV_ABS, ABS_MT_SLOT, slot);
+            input_event_write(tx, EV_ABS, ABS_MT_POSITION_X, past.x as i32);
+            input_event_write(tx, EV_ABS, ABS_MT_POSITION_Y, past.y as i32);
+            input_event_write(tx, EV_ABS, ABS_MT_PRESSURE, past.pressure as i32);
+            input_event_write(tx, EV_SYN, SYN_REPORT, 0);
+            stamp_report(&mut report, past.time_ns);
+            reports.push((report, *past));
         }
     }
 
-    // KIRIM EVENT: Gunakan try_lock agar UI Thread TIDAK PERNAH menunggu (freeze)
-    if let Ok(sender_lock) = INPUT_SENDER.try_lock() {
-        if let Some(ref tx) = *sender_lock {
-            // Kita bungkus dalam closure atau blok untuk memastikan transmisi cepat
-            match action {
-                MotionAction::Down | MotionAction::PointerDown | MotionAction::Move => {
-                    input_event_write(tx, EV_ABS, ABS_MT_SLOT, pointer_id);
-                    if action != MotionAction::Move {
-                        input_event_write(tx, EV_ABS, ABS_MT_TRACKING_ID, pointer_id + 1);
-                        if pointer_id == 0 { input_event_write(tx, EV_KEY, BTN_TOUCH, 1); }
-                    }
-                    input_event_write(tx, EV_ABS, ABS_MT_POSITION_X, x as i32);
-                    input_event_write(tx, EV_ABS, ABS_MT_POSITION_Y, y as i32);
-                    input_event_write(tx, EV_ABS, ABS_MT_PRESSURE, pressure as i32);
-                    input_event_write(tx, EV_SYN, SYN_REPORT, 0);
-                },
-                MotionAction::Up | MotionAction::PointerUp | MotionAction::Cancel => {
-                    input_event_write(tx, EV_ABS, ABS_MT_SLOT, pointer_id);
-                    input_event_write(tx, EV_ABS, ABS_MT_TRACKING_ID, -1);
-                    if pointer_id == 0 { input_event_write(tx, EV_KEY, BTN_TOUCH, 0); }
-                    input_event_write(tx, EV_SYN, SYN_REPORT, 0);
-                },
-                _ => {}
+    let mut report = Vec::with_capacity(8);
+    let tx = &mut report;
+    match action {
+        MotionAction::Down | MotionAction::PointerDown | MotionAction::Move => {
+            input_event_write(tx, EV_ABS, ABS_MT_SLOT, slot);
+            if action != MotionAction::Move {
+                input_event_write(tx, EV_ABS, ABS_MT_TRACKING_ID, pointer_id + 1);
+                if pointer_id == 0 { input_event_write(tx, EV_KEY, BTN_TOUCH, 1); }
             }
+            input_event_write(tx, EV_ABS, ABS_MT_POSITION_X, sample.x as i32);
+            input_event_write(tx, EV_ABS, ABS_MT_POSITION_Y, sample.y as i32);
+            input_event_write(tx, EV_ABS, ABS_MT_PRESSURE, sample.pressure as i32);
+            input_event_write(tx, EV_SYN, SYN_REPORT, 0);
+        },
+        MotionAction::Up | MotionAction::PointerUp => {
+            input_event_write(tx, EV_ABS, ABS_MT_SLOT, slot);
+            input_event_write(tx, EV_ABS, ABS_MT_TRACKING_ID, -1);
+            if pointer_id == 0 { input_event_write(tx, EV_KEY, BTN_TOUCH, 0); }
+            input_event_write(tx, EV_SYN, SYN_REPORT, 0);
+        },
+        MotionAction::Cancel => {
+            // the host took the gesture away, lift every finger or the guest keeps them stuck
+            write_release(tx, &cancelled);
+        },
+        _ => {}
+    }
+
+    stamp_report(&mut report, sample.time_ns);
+    reports.push((report, sample));
+    reports
+}
+
+const DEFAULT_LONG_PRESS_MS: u64 = 500;
+// a held finger wobbles a little, moving further than this starts a normal drag
+const LONG_PRESS_SLOP: f32 = 16.0;
+
+static RIGHT_CLICK_ON_LONG_PRESS: Lazy<AtomicBool> = Lazy::new(|| {
+    AtomicBool::new(config::get_or(config::INPUT_CONF, "right_click_on_long_press", false))
+});
+
+#[derive(Default)]
+struct LongPress {
+    // bumped on every first finger down, a timer of an older press does nothing
+    sequence: u64,
+    start: (f32, f32),
+    // the down report, held back until it's clear the touch isn't a long press
+    pending: Option<Vec<input_event>>,
+    // the touch became a right click, the rest of it is swallowed
+    fired: bool,
+}
+
+static LONG_PRESS: Lazy<Mutex<LongPress>> = Lazy::new(|| Mutex::new(LongPress::default()));
+
+pub fn set_right_click_on_long_press(enabled: bool) {
+    info!("right click on long press: {}", enabled);
+    RIGHT_CLICK_ON_LONG_PRESS.store(enabled, Ordering::Release);
+    config::set(config::INPUT_CONF, "right_click_on_long_press", &enabled.to_string());
+    if !enabled {
+        *LONG_PRESS.lock().unwrap() = LongPress::default();
+    }
+}
+
+// With right click on long press the first finger down is held back, the guest only
+// sees the touch once it moves, lifts or another finger joins. A touch held still for
+// `long_press_ms` never reaches the guest and becomes a right click instead, so no
+// touch has to be cancelled.
+fn push_touch(report: Vec<input_event>, action: MotionAction, x: f32, y: f32) {
+    let droppable = action == MotionAction::Move;
+    if !RIGHT_CLICK_ON_LONG_PRESS.load(Ordering::Acquire) {
+        TOUCH_QUEUE.push(report, droppable);
+        return;
+    }
+
+    let mut press = LONG_PRESS.lock().unwrap();
+    if action == MotionAction::Down {
+        press.sequence += 1;
+        press.start = (x, y);
+        press.pending = Some(report);
+        press.fired = false;
+
+        let sequence = press.sequence;
+        let delay = config::get_or(config::INPUT_CONF, "long_press_ms", DEFAULT_LONG_PRESS_MS);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(delay));
+            fire_long_press(sequence);
+        });
+        return;
+    }
+
+    if press.fired {
+        if action == MotionAction::Up || action == MotionAction::Cancel {
+            press.fired = false;
+        }
+        return;
+    }
+    if press.pending.is_some() && action == MotionAction::Move {
+        let (dx, dy) = (x - press.start.0, y - press.start.1);
+        if dx * dx + dy * dy < LONG_PRESS_SLOP * LONG_PRESS_SLOP {
+            return;
         }
     }
+    if let Some(down) = press.pending.take() {
+        TOUCH_QUEUE.push(down, false);
+    }
+    drop(press);
+    TOUCH_QUEUE.push(report, droppable);
+}
+
+// The right click goes through the touch device, so it lands exactly at the touch: the held
+// back down, BTN_RIGHT pressed and released while it is down and the lift. The guest turns
+// the button press into a context click and ignores the lift that follows it. The relative
+// mouse can't be placed reliably, the guest accelerates its movement.
+fn fire_long_press(sequence: u64) {
+    let mut press = LONG_PRESS.lock().unwrap();
+    if press.sequence != sequence {
+        return;
+    }
+    let mut report = match press.pending.take() {
+        Some(down) => down,
+        None => return,
+    };
+    press.fired = true;
+    let (x, y) = press.start;
+    drop(press);
+
+    // the down report starts with the slot of its pointer
+    let slot = report.first().filter(|ev| ev.code == ABS_MT_SLOT as u16).map_or(0, |ev| ev.value);
+    info!("long press at {}x{}, right click", x, y);
+    let tx = &mut report;
+    input_event_write(tx, EV_KEY, BTN_RIGHT, 1);
+    input_event_write(tx, EV_SYN, SYN_REPORT, 0);
+    input_event_write(tx, EV_KEY, BTN_RIGHT, 0);
+    input_event_write(tx, EV_SYN, SYN_REPORT, 0);
+    input_event_write(tx, EV_ABS, ABS_MT_SLOT, slot);
+    input_event_write(tx, EV_ABS, ABS_MT_TRACKING_ID, -1);
+    input_event_write(tx, EV_KEY, BTN_TOUCH, 0);
+    input_event_write(tx, EV_SYN, SYN_REPORT, 0);
+    TOUCH_QUEUE.push(report, false);
 }
 
 fn generate_touch_device(width: i32, height: i32) -> device_info {
@@ -192,17 +960,31 @@ fn generate_touch_device(width: i32, height: i32) -> device_info {
     info.abs_min[ABS_MT_TOUCH_MAJOR as usize] = 0;
     info.abs_min[ABS_MT_TOUCH_MINOR as usize] = 15;
 
-    info.abs_min[ABS_MT_SLOT as usize] = 4;
+    info.abs_min[ABS_MT_SLOT as usize] = (*MT_SLOTS - 1) as u32;
     info.abs_min[ABS_MT_PRESSURE as usize] = 0;
     info.abs_max[ABS_MT_PRESSURE as usize] = 80;
 
     info
 }
 
-fn touch_server(width: i32, height: i32) {
+// abs maxes the guest touch device was created with: x, y, pressure, touch major
+static TOUCH_RANGES: Mutex<Option<[i32; 4]>> = Mutex::new(None);
+
+/// Axis maxes of the guest touch device as `[x, y, pressure, touch major]`, `None` without a device.
+pub fn device_ranges() -> Option<[i32; 4]> {
+    *TOUCH_RANGES.lock().unwrap()
+}
+
+fn touch_server(width: i32, height: i32, epoch: u64) {
     let device = generate_touch_device(width, height);
+    *TOUCH_RANGES.lock().unwrap() = Some([
+        device.abs_max[ABS_MT_POSITION_X as usize] as i32,
+        device.abs_max[ABS_MT_POSITION_Y as usize] as i32,
+        device.abs_max[ABS_MT_PRESSURE as usize] as i32,
+        device.abs_max[ABS_MT_TOUCH_MAJOR as usize] as i32,
+    ]);
 
-    loop {
+    while is_current(epoch) {
         let _ = std::fs::remove_file(TOUCH_PATH);
         let listener = match unix_socket::UnixListener::bind(TOUCH_PATH) {
             Ok(l) => l,
@@ -218,42 +1000,34 @@ fn touch_server(width: i32, height: i32) {
             }
         }
 
-        for stream in listener.incoming() {
-            if let Ok(mut stream) = stream {
-                info!("Game input connected!");
-                let _ = stream.set_nonblocking(true);
-                let _ = stream.write_all(unsafe { any_as_u8_slice(&device) });
-
-                // Gunakan Sync Channel dengan limit kecil (misal 100 event)
-                // Ini mencegah penumpukan data yang bikin lag/freeze
-                let (tx, rx) = std::sync::mpsc::sync_channel::<input_event>(100);
+        if listener.set_nonblocking(true).is_err() {
+            continue;
+        }
+        let mut stream = match accept_client(&listener, epoch) {
+            Some(stream) => stream,
+            None => break,
+        };
+        info!("Game input connected!");
+        if write_client(&mut stream, unsafe { any_as_u8_slice(&device) }, epoch).is_err() {
+            continue;
+        }
 
-                {
-                    let mut sender_lock = INPUT_SENDER.lock().unwrap();
-                    *sender_lock = Some(tx);
-                }
+        // Queue kecil, MOVE lama dibuang kalau penuh
+        // Ini mencegah penumpukan data yang bikin lag/freeze
+        let capacity = config::get_or(config::INPUT_CONF, "touch_queue_capacity", DEFAULT_TOUCH_QUEUE_CAPACITY);
+        let generation = TOUCH_QUEUE.connect(capacity);
 
-                loop {
-                    match rx.recv() {
-                        Ok(ev) => {
-                            let data = unsafe { any_as_u8_slice(&ev) };
-                            if let Err(e) = stream.write_all(data) {
-                                if e.kind() != std::io::ErrorKind::WouldBlock {
-                                    error!("Broken pipe, reconnecting...");
-                                    break;
-                                }
-                            }
-                        },
-                        Err(_) => break,
-                    }
-                }
-                // Reset sender saat koneksi putus
-                let mut sender_lock = INPUT_SENDER.lock().unwrap();
-                *sender_lock = None;
+        while let Some(report) = TOUCH_QUEUE.pop(generation) {
+            let data = unsafe { slice_as_u8_slice(&report) };
+            if write_client(&mut stream, data, epoch).is_err() {
+                error!("Broken pipe, reconnecting...");
                 break;
             }
         }
+        // Reset queue saat koneksi putus
+        TOUCH_QUEUE.disconnect(generation);
     }
+    info!("touch server stopped");
 }
 
 fn generate_key_device() -> device_info {
@@ -267,47 +1041,627 @@ fn generate_key_device() -> device_info {
     copy_to_cstr(KEY_DEVICE_UNIQUE_ID, &mut info.unique_id);
 
     info.key_bitmask[14] = 0x1C;
+    for code in keymap::all_linux_key_codes() {
+        info.key_bitmask[code as usize / 8] |= 1 << (code % 8);
+    }
 
     info
 }
 
-pub fn send_key_code(_keycode: i32) {
-    if let Some(ref tx) = *KEY_SENDER.lock().unwrap() {
-        input_event_write(tx, EV_KEY, KEY_BACK, 1);
-        input_event_write(tx, EV_SYN, SYN_REPORT, SYN_REPORT);
-        input_event_write(tx, EV_KEY, KEY_BACK, 0);
+pub const HOME_FORWARD: i32 = 0;
+pub const HOME_HOST: i32 = 1;
+pub const HOME_EXIT_GUEST: i32 = 2;
+
+const AKEYCODE_HOME: i32 = 3;
+
+static HOME_BEHAVIOR: Lazy<AtomicI32> = Lazy::new(|| {
+    AtomicI32::new(config::get_or(config::INPUT_CONF, "home_behavior", HOME_FORWARD))
+});
+
+pub fn set_home_behavior(behavior: i32) {
+    if !(HOME_FORWARD..=HOME_EXIT_GUEST).contains(&behavior) {
+        error!("invalid home key behavior: {}", behavior);
+        return;
+    }
+    info!("home key behavior: {}", behavior);
+    HOME_BEHAVIOR.store(behavior, Ordering::Release);
+    config::set(config::INPUT_CONF, "home_behavior", &behavior.to_string());
+}
+
+// Returns true if HOME is handled on the host side and must not reach the guest. Only a guest
+// HOME from `send_key_event` gets here: Android never delivers the host's own HOME key or
+// gesture to an activity, so a keyboard HOME or the Meta shortcut is all there is.
+fn intercept_home(keycode: i32, down: bool) -> bool {
+    if keycode != AKEYCODE_HOME {
+        return false;
+    }
+    let behavior = HOME_BEHAVIOR.load(Ordering::Acquire);
+    // act once per press, swallow the release too
+    if down {
+        match behavior {
+            HOME_HOST => {
+                info!("home key handled by the host");
+                thread::spawn(|| {
+                    callback::call_static("onHostHome", "()V", &[]);
+                });
+            }
+            HOME_EXIT_GUEST => {
+                info!("home key stops the guest");
+                thread::spawn(container::container_stop);
+            }
+            _ => (),
+        }
+    }
+    behavior != HOME_FORWARD
+}
+
+const AKEYCODE_BACK: i32 = 4;
+const AKEYCODE_ALT_LEFT: i32 = 57;
+const AKEYCODE_ALT_RIGHT: i32 = 58;
+const AKEYCODE_TAB: i32 = 61;
+const AKEYCODE_ESCAPE: i32 = 111;
+const AKEYCODE_META_LEFT: i32 = 117;
+const AKEYCODE_META_RIGHT: i32 = 118;
+const AKEYCODE_APP_SWITCH: i32 = 187;
+
+static KEYBOARD_SHORTCUTS: Lazy<AtomicBool> = Lazy::new(|| {
+    AtomicBool::new(config::get_or(config::INPUT_CONF, "keyboard_shortcuts", false))
+});
+static ALT_HELD: AtomicBool = AtomicBool::new(false);
+// host key -> guest key of the shortcuts still held, the release must go to the same key
+static HELD_SHORTCUTS: Mutex<Vec<(i32, i32)>> = Mutex::new(Vec::new());
+
+/// ESC goes BACK, Super goes HOME and Alt+Tab opens the recents in the guest.
+pub fn set_keyboard_shortcuts(enabled: bool) {
+    info!("keyboard shortcuts: {}", enabled);
+    KEYBOARD_SHORTCUTS.store(enabled, Ordering::Release);
+    config::set(config::INPUT_CONF, "keyboard_shortcuts", &enabled.to_string());
+}
+
+// The guest key a host key stands for with the shortcuts on, `None` to forward it as is.
+fn map_shortcut(keycode: i32, down: bool) -> Option<i32> {
+    if keycode == AKEYCODE_ALT_LEFT || keycode == AKEYCODE_ALT_RIGHT {
+        ALT_HELD.store(down, Ordering::Relaxed);
+    }
+
+    let mut held = HELD_SHORTCUTS.lock().unwrap();
+    if !down {
+        let index = held.iter().position(|(host, _)| *host == keycode)?;
+        return Some(held.swap_remove(index).1);
     }
+    if !KEYBOARD_SHORTCUTS.load(Ordering::Acquire) {
+        return None;
+    }
+    let guest = match keycode {
+        AKEYCODE_ESCAPE => AKEYCODE_BACK,
+        AKEYCODE_META_LEFT | AKEYCODE_META_RIGHT => AKEYCODE_HOME,
+        AKEYCODE_TAB if ALT_HELD.load(Ordering::Relaxed) => AKEYCODE_APP_SWITCH,
+        _ => return None,
+    };
+    // key repeats come as downs too
+    if !held.iter().any(|(host, _)| *host == keycode) {
+        held.push((keycode, guest));
+    }
+    Some(guest)
 }
 
-fn key_server() {
-    let device = generate_key_device();
-    let _ = std::fs::remove_file(KEY_PATH);
-    let listener = unix_socket::UnixListener::bind(KEY_PATH).unwrap();
-    for stream in listener.incoming() {
-        match stream {
-            Ok(mut stream) => {
-                info!("key client connected!");
+pub fn send_key_code(keycode: i32) {
+    if !INPUT_ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+    crate::wake_renderer();
 
-                let _ = stream.write_all(unsafe { any_as_u8_slice(&device) });
+    let mut report = Vec::with_capacity(3);
+    let tx = &mut report;
+    input_event_write(tx, EV_KEY, KEY_BACK, 1);
+    input_event_write(tx, EV_SYN, SYN_REPORT, SYN_REPORT);
+    input_event_write(tx, EV_KEY, KEY_BACK, 0);
+    KEY_QUEUE.push(report, false);
+}
 
-                // GANTI channel() menjadi sync_channel(10)
-                let (tx, rx) = std::sync::mpsc::sync_channel::<input_event>(10);
-                *KEY_SENDER.lock().unwrap() = Some(tx);
+/// Forwards a host key press or release. The key code is translated with the built-in
+/// table, the scan code (already a Linux code for hardware keys) covers the rest.
+pub fn send_key_event(keycode: i32, scancode: i32, down: bool) {
+    if !INPUT_ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+    let (keycode, scancode) = match map_shortcut(keycode, down) {
+        Some(guest) => (guest, 0),
+        None => (keycode, scancode),
+    };
+    if intercept_home(keycode, down) {
+        return;
+    }
+    crate::wake_renderer();
 
-                thread::spawn(move || loop {
-                    let ret = rx.recv();
-                    if let Ok(ev) = ret {
-                        let data = unsafe { any_as_u8_slice(&ev) };
-                        if let Err(_) = stream.write_all(data) { break; }
-                    } else {
-                        break;
-                    }
-                });
+    let code = keymap::linux_key_code(keycode)
+        .or_else(|| Some(scancode).filter(|code| *code > 0 && *code < KEY_MAX));
+    let code = match code {
+        Some(code) => code,
+        None => {
+            info!("unmapped key {}, scan code {}", keycode, scancode);
+            return;
+        }
+    };
+
+    let mut report = Vec::with_capacity(2);
+    let tx = &mut report;
+    input_event_write(tx, EV_KEY, code, down as i32);
+    input_event_write(tx, EV_SYN, SYN_REPORT, 0);
+    KEY_QUEUE.push(report, false);
+}
+
+pub const DPAD_UP: i32 = 0;
+pub const DPAD_DOWN: i32 = 1;
+pub const DPAD_LEFT: i32 = 2;
+pub const DPAD_RIGHT: i32 = 3;
+pub const DPAD_CENTER: i32 = 4;
+
+// AKEYCODE_DPAD_UP..AKEYCODE_DPAD_CENTER, in DPAD_* order
+const DPAD_KEYCODES: [i32; 5] = [19, 20, 21, 22, 23];
+
+/// Presses or releases a D-pad key, the guest repeats it while it is held.
+pub fn send_dpad(direction: i32, down: bool) {
+    match DPAD_KEYCODES.get(direction as usize) {
+        Some(&keycode) if direction >= 0 => send_key_event(keycode, 0, down),
+        _ => error!("invalid dpad direction: {}", direction),
+    }
+}
+
+fn key_server(epoch: u64) {
+    let capacity = config::get_or(config::INPUT_CONF, "key_queue_capacity", DEFAULT_KEY_QUEUE_CAPACITY);
+    queue_server(KEY_PATH, generate_key_device(), &KEY_QUEUE, capacity, epoch);
+}
+
+fn mouse_server(epoch: u64) {
+    let capacity = config::get_or(config::INPUT_CONF, "mouse_queue_capacity", DEFAULT_MOUSE_QUEUE_CAPACITY);
+    queue_server(MOUSE_PATH, generate_mouse_device(), &MOUSE_QUEUE, capacity, epoch);
+}
+
+// Serves one guest device at `path`, each client gets the device description and then the queued reports.
+fn queue_server(path: &str, device: device_info, queue: &'static InputQueue, capacity: usize, epoch: u64) {
+    let _ = std::fs::remove_file(path);
+    let listener = match unix_socket::UnixListener::bind(path) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("bind {} failed: {}", path, e);
+            return;
+        }
+    };
+    if let Err(e) = listener.set_nonblocking(true) {
+        error!("set {} non-blocking failed: {}", path, e);
+        return;
+    }
+    while let Some(mut stream) = accept_client(&listener, epoch) {
+        info!("{} client connected!", path);
+
+        if write_client(&mut stream, unsafe { any_as_u8_slice(&device) }, epoch).is_err() {
+            continue;
+        }
+
+        let generation = queue.connect(capacity);
+
+        spawn_input_thread("twoyi-input-out", move || {
+            while let Some(report) = queue.pop(generation) {
+                let data = unsafe { slice_as_u8_slice(&report) };
+                if write_client(&mut stream, data, epoch).is_err() { break; }
             }
-            Err(_) => {
-                info!("key server error happened!");
-                break;
+            queue.disconnect(generation);
+        });
+    }
+    info!("{} server stopped", path);
+}
+
+fn generate_mouse_device() -> device_info {
+    let mut info: device_info = unsafe { std::mem::MaybeUninit::zeroed().assume_init() };
+
+    info.driver_version = 0x1;
+    info.id.product = 0x1;
+
+    copy_to_cstr(MOUSE_DEVICE_NAME, &mut info.name);
+    copy_to_cstr(MOUSE_PATH, &mut info.physical_location);
+    copy_to_cstr(MOUSE_DEVICE_UNIQUE_ID, &mut info.unique_id);
+
+    for code in [BTN_LEFT, BTN_RIGHT, BTN_MIDDLE] {
+        info.key_bitmask[code as usize / 8] |= 1 << (code % 8);
+    }
+    for code in [REL_X, REL_Y, REL_HWHEEL, REL_WHEEL] {
+        info.rel_bitmask[code as usize / 8] |= 1 << (code % 8);
+    }
+
+    info
+}
+
+// AMOTION_EVENT_BUTTON_*, in the order of the guest buttons
+const MOUSE_BUTTONS: [(u32, i32); 3] = [(1, BTN_LEFT), (2, BTN_RIGHT), (4, BTN_MIDDLE)];
+
+#[derive(Default)]
+struct MouseState {
+    // the host reports absolute positions, the guest device moves relatively
+    last: Option<(f32, f32)>,
+    // sub-pixel movement left over after scaling, carried into the next move
+    remainder: (f32, f32),
+    buttons: u32,
+}
+
+const MIN_POINTER_SENSITIVITY: f32 = 0.1;
+const MAX_POINTER_SENSITIVITY: f32 = 5.0;
+const MAX_POINTER_ACCEL_EXPONENT: f32 = 2.0;
+// moves faster than this many pixels per event get accelerated
+const POINTER_ACCEL_THRESHOLD: f32 = 4.0;
+
+#[derive(Clone, Copy)]
+struct PointerAccel {
+    sensitivity: f32,
+    // 1 is linear, above that quick strokes move further
+    exponent: f32,
+}
+
+static POINTER_ACCEL: Lazy<Mutex<PointerAccel>> = Lazy::new(|| {
+    Mutex::new(PointerAccel {
+        sensitivity: config::get_or(config::INPUT_CONF, "pointer_sensitivity", 1.0f32)
+            .clamp(MIN_POINTER_SENSITIVITY, MAX_POINTER_SENSITIVITY),
+        exponent: config::get_or(config::INPUT_CONF, "pointer_accel_exponent", 1.0f32)
+            .clamp(1.0, MAX_POINTER_ACCEL_EXPONENT),
+    })
+});
+
+/// Scales mouse movement by `sensitivity`, the curve exponent comes from
+/// `pointer_accel_exponent` in input.conf.
+pub fn set_pointer_acceleration(sensitivity: f32) {
+    if !sensitivity.is_finite() {
+        error!("invalid pointer sensitivity: {}", sensitivity);
+        return;
+    }
+    let sensitivity = sensitivity.clamp(MIN_POINTER_SENSITIVITY, MAX_POINTER_SENSITIVITY);
+    let mut accel = POINTER_ACCEL.lock().unwrap();
+    accel.sensitivity = sensitivity;
+    info!("pointer sensitivity: {}, acceleration exponent: {}", sensitivity, accel.exponent);
+    config::set(config::INPUT_CONF, "pointer_sensitivity", &sensitivity.to_string());
+}
+
+fn accelerate(dx: f32, dy: f32) -> (f32, f32) {
+    let accel = *POINTER_ACCEL.lock().unwrap();
+    let speed = (dx * dx + dy * dy).sqrt();
+    let gain = accel.sensitivity * (speed / POINTER_ACCEL_THRESHOLD).max(1.0).powf(accel.exponent - 1.0);
+    (dx * gain, dy * gain)
+}
+
+static MOUSE: Lazy<Mutex<MouseState>> = Lazy::new(|| Mutex::new(MouseState::default()));
+
+fn handle_mouse(ev: &MotionEvent) {
+    let pointer = ev.pointer_at_index(0);
+    let (x, y) = (pointer.x(), pointer.y());
+    let mut report = Vec::with_capacity(8);
+    let tx = &mut report;
+
+    let mut mouse = MOUSE.lock().unwrap();
+    match ev.action() {
+        MotionAction::HoverExit => {
+            mouse.last = None;
+            mouse.remainder = (0.0, 0.0);
+        }
+        MotionAction::Scroll => {
+            let vscroll = pointer.axis_value(ndk::event::Axis::Vscroll);
+            let hscroll = pointer.axis_value(ndk::event::Axis::Hscroll);
+            if vscroll != 0.0 {
+                input_event_write(tx, EV_REL, REL_WHEEL, vscroll.round() as i32);
+            }
+            if hscroll != 0.0 {
+                input_event_write(tx, EV_REL, REL_HWHEEL, hscroll.round() as i32);
+            }
+        }
+        _ => {
+            if let Some((last_x, last_y)) = mouse.last {
+                let (dx, dy) = accelerate(x - last_x, y - last_y);
+                let (dx, dy) = (dx + mouse.remainder.0, dy + mouse.remainder.1);
+                let (rx, ry) = (dx.round(), dy.round());
+                mouse.remainder = (dx - rx, dy - ry);
+                let (dx, dy) = (rx as i32, ry as i32);
+                if dx != 0 {
+                    input_event_write(tx, EV_REL, REL_X, dx);
+                }
+                if dy != 0 {
+                    input_event_write(tx, EV_REL, REL_Y, dy);
+                }
+            }
+            mouse.last = Some((x, y));
+        }
+    }
+
+    let buttons = ev.button_state().0;
+    for (mask, code) in MOUSE_BUTTONS {
+        if (buttons ^ mouse.buttons) & mask != 0 {
+            input_event_write(tx, EV_KEY, code, (buttons & mask != 0) as i32);
+        }
+    }
+    let changed = buttons != mouse.buttons;
+    mouse.buttons = buttons;
+    drop(mouse);
+
+    if report.is_empty() {
+        return;
+    }
+    input_event_write(&mut report, EV_SYN, SYN_REPORT, 0);
+    stamp_report(&mut report, ev.event_time());
+    // plain movement may be dropped, a button change may not
+    MOUSE_QUEUE.push(report, !changed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn sample(x: f32, y: f32) -> TouchSample {
+        TouchSample { x, y, pressure: 1.0, time_ns: 0 }
+    }
+
+    // (code, value) of the events of type `kind` in `report`
+    fn events(report: &[input_event], kind: i32) -> Vec<(i32, i32)> {
+        report.iter().filter(|ev| ev.kind == kind as u16).map(|ev| (ev.code as i32, ev.value)).collect()
+    }
+
+    #[test]
+    fn cancel_releases_every_slot() {
+        let mut mt = [0; MAX_POINTERS];
+        touch_reports(&mut mt, MotionAction::Down, 0, &[], sample(10.0, 10.0));
+        touch_reports(&mut mt, MotionAction::PointerDown, 1, &[], sample(20.0, 20.0));
+        touch_reports(&mut mt, MotionAction::PointerDown, 2, &[], sample(30.0, 30.0));
+
+        let reports = touch_reports(&mut mt, MotionAction::Cancel, 0, &[], sample(10.0, 10.0));
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0].0;
+        assert_eq!(
+            events(report, EV_ABS),
+            [
+                (ABS_MT_SLOT, 0), (ABS_MT_TRACKING_ID, -1),
+                (ABS_MT_SLOT, 1), (ABS_MT_TRACKING_ID, -1),
+                (ABS_MT_SLOT, 2), (ABS_MT_TRACKING_ID, -1),
+            ]
+        );
+        assert_eq!(events(report, EV_KEY), [(BTN_TOUCH, 0)]);
+        assert_eq!(events(report, EV_SYN), [(SYN_REPORT, 0)]);
+        assert!(mt.iter().all(|state| *state == 0));
+    }
+
+    #[test]
+    fn historical_samples_become_moves() {
+        let mut mt = [0; MAX_POINTERS];
+        touch_reports(&mut mt, MotionAction::Down, 0, &[], sample(0.0, 0.0));
+
+        let history = [sample(1.0, 1.0), sample(2.0, 2.0), sample(3.0, 3.0)];
+        let reports = touch_reports(&mut mt, MotionAction::Move, 0, &history, sample(4.0, 4.0));
+        assert_eq!(reports.len(), history.len() + 1);
+        // oldest first, the current sample last
+        for (i, (report, _)) in reports.iter().enumerate() {
+            let abs = events(report, EV_ABS);
+            assert!(abs.contains(&(ABS_MT_POSITION_X, i as i32 + 1)), "sample {}", i);
+            assert!(!abs.iter().any(|(code, _)| *code == ABS_MT_TRACKING_ID), "sample {}", i);
+            assert_eq!(events(report, EV_SYN), [(SYN_REPORT, 0)]);
+        }
+    }
+
+    #[test]
+    fn pointers_beyond_the_slots_are_dropped() {
+        assert_eq!(*MT_SLOTS, DEFAULT_MT_SLOTS);
+        let mut mt = [0; MAX_POINTERS];
+        for id in 0..12 {
+            let action = if id == 0 { MotionAction::Down } else { MotionAction::PointerDown };
+            let reports = touch_reports(&mut mt, action, id, &[], sample(id as f32, 0.0));
+            assert_eq!(reports.is_empty(), id >= 10, "pointer {}", id);
+        }
+        // the pointers already down keep their slots
+        for id in 0..10 {
+            assert_eq!(find_slot(&mt, id), Some(id as usize));
+        }
+        // a dropped pointer stays dropped until it goes up
+        assert!(touch_reports(&mut mt, MotionAction::Move, 11, &[], sample(1.0, 1.0)).is_empty());
+        assert!(touch_reports(&mut mt, MotionAction::PointerUp, 11, &[], sample(1.0, 1.0)).is_empty());
+
+        // a lifted pointer frees its slot for the next one
+        touch_reports(&mut mt, MotionAction::PointerUp, 3, &[], sample(3.0, 0.0));
+        assert_eq!(touch_reports(&mut mt, MotionAction::PointerDown, 12, &[], sample(12.0, 0.0)).len(), 1);
+        assert_eq!(find_slot(&mt, 12), Some(3));
+    }
+
+    const WIDTH: i32 = 720;
+    const HEIGHT: i32 = 1280;
+
+    // every test that needs a device uses this size, so they can run in parallel
+    fn set_device_size() {
+        TOUCH_WIDTH.store(WIDTH, Ordering::Relaxed);
+        TOUCH_HEIGHT.store(HEIGHT, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn out_of_bounds_coordinates_are_clamped() {
+        set_device_size();
+        let (max_x, max_y) = ((WIDTH - 1) as f32, (HEIGHT - 1) as f32);
+        assert_eq!(clamp_to_device(-3.5, -0.1), (0.0, 0.0));
+        assert_eq!(clamp_to_device(-1.0, 100.0), (0.0, 100.0));
+        assert_eq!(clamp_to_device(100.0, HEIGHT as f32 + 40.0), (100.0, max_y));
+        assert_eq!(clamp_to_device(WIDTH as f32, HEIGHT as f32), (max_x, max_y));
+        assert_eq!(clamp_to_device(10.0, 20.0), (10.0, 20.0));
+    }
+
+    #[test]
+    fn event_times_keep_the_monotonic_timebase() {
+        let time = event_timeval(1_234_567_891_234);
+        assert_eq!((time.tv_sec as i64, time.tv_usec as i64), (1234, 567_891));
+
+        let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+        let now_ns = now.tv_sec as i64 * 1_000_000_000 + now.tv_nsec as i64;
+
+        let mut report = Vec::new();
+        input_event_write(&mut report, EV_ABS, ABS_MT_POSITION_X, 1);
+        input_event_write(&mut report, EV_SYN, SYN_REPORT, 0);
+        let past = now_ns - 5_000_000;
+        stamp_report(&mut report, past);
+        for ev in &report {
+            assert_eq!((ev.time.tv_sec as i64, ev.time.tv_usec as i64), (past / 1_000_000_000, past % 1_000_000_000 / 1000));
+        }
+
+        // a time from the future is left alone
+        let written = report[0].time;
+        stamp_report(&mut report, now_ns + 60_000_000_000);
+        assert_eq!((report[0].time.tv_sec, report[0].time.tv_usec), (written.tv_sec, written.tv_usec));
+    }
+
+    // Injecting from several threads at once: every down reaches the writer, a MOVE is
+    // either written or counted as dropped, and every write ends on a whole report.
+    #[test]
+    fn queue_stress() {
+        const PRODUCERS: usize = 4;
+        const REPORTS: usize = 5000;
+        let queue = InputQueue::new();
+        let generation = queue.connect(8);
+
+        let (downs, moves) = thread::scope(|s| {
+            let writer = s.spawn(|| {
+                let (mut downs, mut moves) = (0, 0);
+                while let Some(batch) = queue.pop(generation) {
+                    assert_eq!(batch.last().map(|ev| ev.kind), Some(EV_SYN as u16));
+                    let positions = events(&batch, EV_ABS).iter().filter(|(code, _)| *code == ABS_MT_POSITION_X).count();
+                    let pressed = events(&batch, EV_KEY).len();
+                    downs += pressed;
+                    moves += positions - pressed;
+                }
+                (downs, moves)
+            });
+
+            let producers: Vec<_> = (0..PRODUCERS)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut mt = [0; MAX_POINTERS];
+                        for i in 0..REPORTS {
+                            let action = if i % 10 == 0 { MotionAction::Down } else { MotionAction::Move };
+                            for (report, _) in touch_reports(&mut mt, action, 0, &[], sample(i as f32, 0.0)) {
+                                queue.push(report, action == MotionAction::Move);
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for producer in producers {
+                producer.join().unwrap();
+            }
+
+            // let the writer drain the queue before the client goes away
+            while !queue.state.lock().unwrap().reports.is_empty() {
+                thread::yield_now();
+            }
+            queue.disconnect(generation);
+            writer.join().unwrap()
+        });
+
+        assert_eq!(downs, PRODUCERS * REPORTS / 10);
+        assert_eq!(moves as u64 + queue.dropped.load(Ordering::Relaxed), (PRODUCERS * REPORTS * 9 / 10) as u64);
+    }
+
+    // input_shutdown closes every queue and stops every writer, the tests using either
+    // can't run alongside it
+    static GLOBAL_QUEUES: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn dpad_directions_emit_their_keys() {
+        let _queues = GLOBAL_QUEUES.lock().unwrap_or_else(|e| e.into_inner());
+        let generation = KEY_QUEUE.connect(16);
+        let expected = [
+            (DPAD_UP, KEY_UP),
+            (DPAD_DOWN, KEY_DOWN),
+            (DPAD_LEFT, KEY_LEFT),
+            (DPAD_RIGHT, KEY_RIGHT),
+            (DPAD_CENTER, KEY_SELECT),
+        ];
+        for (direction, key) in expected {
+            send_dpad(direction, true);
+            send_dpad(direction, false);
+            for down in [1, 0] {
+                let report = KEY_QUEUE.pop(generation).expect("no key report");
+                assert_eq!(events(&report, EV_KEY), [(key, down)], "direction {}", direction);
             }
         }
+        // out of range directions send nothing
+        send_dpad(-1, true);
+        send_dpad(DPAD_CENTER + 1, true);
+        assert!(KEY_QUEUE.state.lock().unwrap().reports.is_empty());
+        KEY_QUEUE.disconnect(generation);
+    }
+
+    #[test]
+    fn shutdown_is_quick_while_events_flow() {
+        let _queues = GLOBAL_QUEUES.lock().unwrap_or_else(|e| e.into_inner());
+        *INPUT_RUNNING.lock().unwrap() = true;
+        clear_shutdown();
+        let epoch = INPUT_EPOCH.fetch_add(1, Ordering::AcqRel) + 1;
+
+        // a guest that stopped reading, the writer ends up waiting for room in the socket
+        let (mut stream, _guest) = unix_socket::UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let generation = TOUCH_QUEUE.connect(DEFAULT_TOUCH_QUEUE_CAPACITY);
+        let stop = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let writer = s.spawn(move || {
+                while let Some(report) = TOUCH_QUEUE.pop(generation) {
+                    let data = unsafe { slice_as_u8_slice(&report) };
+                    if write_client(&mut stream, data, epoch).is_err() {
+                        break;
+                    }
+                }
+            });
+            s.spawn(|| {
+                let mut mt = [0; MAX_POINTERS];
+                let mut i = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    let action = if i == 0 { MotionAction::Down } else { MotionAction::Move };
+                    for (report, _) in touch_reports(&mut mt, action, 0, &[], sample((i % WIDTH) as f32, 0.0)) {
+                        TOUCH_QUEUE.push(report, action == MotionAction::Move);
+                    }
+                    i += 1;
+                }
+            });
+
+            thread::sleep(Duration::from_millis(200));
+            let start = Instant::now();
+            input_shutdown();
+            writer.join().unwrap();
+            let elapsed = start.elapsed();
+            stop.store(true, Ordering::Relaxed);
+            assert!(elapsed < Duration::from_millis(500), "shutdown took {:?}", elapsed);
+        });
+        clear_shutdown();
+    }
+
+    // A full socket only delays the rest of a report, it is written once the guest reads
+    // again, so the guest never sees a torn input_event stream.
+    #[test]
+    fn full_socket_keeps_the_rest_of_a_report() {
+        let _queues = GLOBAL_QUEUES.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut stream, mut guest) = unix_socket::UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+
+        // far more than the socket buffer holds
+        let mut report = Vec::new();
+        for i in 0..100_000 {
+            input_event_write(&mut report, EV_ABS, ABS_MT_POSITION_X, i);
+        }
+        input_event_write(&mut report, EV_SYN, SYN_REPORT, 0);
+        let data = unsafe { slice_as_u8_slice(&report) }.to_vec();
+        let epoch = INPUT_EPOCH.load(Ordering::Acquire);
+
+        let received = thread::scope(|s| {
+            let reader = s.spawn(move || {
+                let mut received = Vec::new();
+                guest.read_to_end(&mut received).unwrap();
+                received
+            });
+            write_client(&mut stream, &data, epoch).unwrap();
+            drop(stream);
+            reader.join().unwrap()
+        });
+        assert_eq!(received, data);
     }
 }
@@ -0,0 +1,99 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use uinput_sys::*;
+
+// AKEYCODE_A..AKEYCODE_Z are contiguous, the Linux codes follow the keyboard layout.
+const LETTERS: [i32; 26] = [
+    KEY_A, KEY_B, KEY_C, KEY_D, KEY_E, KEY_F, KEY_G, KEY_H, KEY_I, KEY_J, KEY_K, KEY_L, KEY_M,
+    KEY_N, KEY_O, KEY_P, KEY_Q, KEY_R, KEY_S, KEY_T, KEY_U, KEY_V, KEY_W, KEY_X, KEY_Y, KEY_Z,
+];
+
+// AKEYCODE_NUMPAD_0..AKEYCODE_NUMPAD_9
+const NUMPAD_DIGITS: [i32; 10] = [
+    KEY_KP0, KEY_KP1, KEY_KP2, KEY_KP3, KEY_KP4, KEY_KP5, KEY_KP6, KEY_KP7, KEY_KP8, KEY_KP9,
+];
+
+/// Linux key code for an Android `KeyEvent` key code, `None` if the key isn't in the table.
+pub fn linux_key_code(keycode: i32) -> Option<i32> {
+    let code = match keycode {
+        3 => KEY_HOMEPAGE,
+        4 => KEY_BACK,
+        7 => KEY_0,
+        8..=16 => KEY_1 + (keycode - 8),
+        19 => KEY_UP,
+        20 => KEY_DOWN,
+        21 => KEY_LEFT,
+        22 => KEY_RIGHT,
+        // DPAD_CENTER, the guest Generic.kl maps SELECT to it
+        23 => KEY_SELECT,
+        24 => KEY_VOLUMEUP,
+        25 => KEY_VOLUMEDOWN,
+        29..=54 => LETTERS[(keycode - 29) as usize],
+        55 => KEY_COMMA,
+        56 => KEY_DOT,
+        57 => KEY_LEFTALT,
+        58 => KEY_RIGHTALT,
+        59 => KEY_LEFTSHIFT,
+        60 => KEY_RIGHTSHIFT,
+        61 => KEY_TAB,
+        62 => KEY_SPACE,
+        66 => KEY_ENTER,
+        67 => KEY_BACKSPACE,
+        68 => KEY_GRAVE,
+        69 => KEY_MINUS,
+        70 => KEY_EQUAL,
+        71 => KEY_LEFTBRACE,
+        72 => KEY_RIGHTBRACE,
+        73 => KEY_BACKSLASH,
+        74 => KEY_SEMICOLON,
+        75 => KEY_APOSTROPHE,
+        76 => KEY_SLASH,
+        82 => KEY_MENU,
+        // media
+        85 => KEY_PLAYPAUSE,
+        86 => KEY_STOPCD,
+        87 => KEY_NEXTSONG,
+        88 => KEY_PREVIOUSSONG,
+        89 => KEY_REWIND,
+        90 => KEY_FASTFORWARD,
+        92 => KEY_PAGEUP,
+        93 => KEY_PAGEDOWN,
+        111 => KEY_ESC,
+        112 => KEY_DELETE,
+        113 => KEY_LEFTCTRL,
+        114 => KEY_RIGHTCTRL,
+        115 => KEY_CAPSLOCK,
+        122 => KEY_HOME,
+        123 => KEY_END,
+        124 => KEY_INSERT,
+        126 => KEY_PLAYCD,
+        127 => KEY_PAUSECD,
+        // F1..F10 are contiguous in both, F11 and F12 aren't
+        131..=140 => KEY_F1 + (keycode - 131),
+        141 => KEY_F11,
+        142 => KEY_F12,
+        // numpad
+        143 => KEY_NUMLOCK,
+        144..=153 => NUMPAD_DIGITS[(keycode - 144) as usize],
+        154 => KEY_KPSLASH,
+        155 => KEY_KPASTERISK,
+        156 => KEY_KPMINUS,
+        157 => KEY_KPPLUS,
+        158 => KEY_KPDOT,
+        159 => KEY_KPCOMMA,
+        160 => KEY_KPENTER,
+        161 => KEY_KPEQUAL,
+        164 => KEY_MUTE,
+        // APP_SWITCH, the guest Generic.kl maps APPSELECT to it
+        187 => KEY_APPSELECT,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Every code of the table, the guest key device advertises them.
+pub fn all_linux_key_codes() -> impl Iterator<Item = i32> {
+    (0..=200).filter_map(linux_key_code)
+}
@@ -2,25 +2,51 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use jni::objects::{JString, JObject};
-use jni::sys::{jclass, jfloat, jint, jobject, JNI_ERR, jstring};
+use jni::objects::{JByteArray, JClass, JObjectArray, JString, JObject, JValue};
+use jni::sys::{jboolean, jbyteArray, jclass, jfloat, jfloatArray, jint, jintArray, jlong, jlongArray, jobject, jobjectArray, JNI_ERR, JNI_FALSE, JNI_TRUE, jstring};
 use jni::JNIEnv;
 use jni::{JavaVM, NativeMethod};
-use log::{error, info, debug, LevelFilter};
+use log::{error, info, debug, warn};
 use std::ffi::c_void;
+use once_cell::sync::Lazy;
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Mutex;
 use std::thread;
 
-use android_logger::Config;
-
 use std::fs;
-use std::fs::File;
 use std::os::unix::fs::PermissionsExt;
-use std::process::{Command, Stdio};
 
+mod activities;
+mod biometric;
+mod callback;
+mod camera;
+mod config;
+mod container;
+mod cpu;
+mod cpulimit;
+mod diagnostics;
+mod framepipe;
+mod gpu;
+mod guest;
+mod haptics;
 mod input;
+mod json;
+mod keymap;
+mod logging;
+mod memory;
+mod nfc;
+mod packages;
+mod power;
+mod render_error;
+mod rootfs_extract;
+mod recovery;
 mod renderer_bindings;
+mod selftest;
+mod selinux;
+mod splash;
+mod storage;
+mod users;
 
 macro_rules! jni_method {
     ( $name: tt, $method:tt, $signature:expr ) => {{
@@ -33,6 +59,55 @@ macro_rules! jni_method {
 }
 
 static RENDERER_STARTED: AtomicBool = AtomicBool::new(false);
+// set once startOpenGLRenderer was called, the start waits for a surface with a size
+static RENDERER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// size of the guest display the renderer was started with
+static GUEST_DISPLAY_SIZE: Mutex<(i32, i32)> = Mutex::new((0, 0));
+
+// fps the renderer presents at, the guest display HAL reports the same refresh rate
+static RENDER_FPS: AtomicI32 = AtomicI32::new(0);
+
+struct PendingStart {
+    xdpi: f32,
+    ydpi: f32,
+    fps: i32,
+}
+
+static PENDING_START: Mutex<Option<PendingStart>> = Mutex::new(None);
+
+// window operations come from several JNI entry points, a shared GL context must
+// never see two of them at once
+static WINDOW_LOCK: Mutex<()> = Mutex::new(());
+static RENDERER_PAUSED: AtomicBool = AtomicBool::new(false);
+
+// drop to a low fps while the guest screen doesn't change
+static IDLE_MODE: Lazy<AtomicBool> = Lazy::new(|| {
+    AtomicBool::new(config::get_or(config::RENDERER_CONF, "idle_mode", false))
+});
+const DEFAULT_IDLE_TIMEOUT_MS: i32 = 2000;
+const DEFAULT_IDLE_FPS: i32 = 10;
+
+// exclusive, the scale is in (0.25, 1.0]
+const MIN_RENDER_SCALE: f32 = 0.25;
+const MAX_RENDER_SCALE: f32 = 1.0;
+
+// 0 means the density of the host screen
+const MIN_GUEST_DENSITY: i32 = 120;
+const MAX_GUEST_DENSITY: i32 = 640;
+
+fn is_valid_render_scale(scale: f32) -> bool {
+    scale > MIN_RENDER_SCALE && scale <= MAX_RENDER_SCALE
+}
+
+// A stored scale out of range renders at full resolution.
+fn clamp_render_scale(scale: f32) -> f32 {
+    if is_valid_render_scale(scale) {
+        scale
+    } else {
+        MAX_RENDER_SCALE
+    }
+}
 
 #[no_mangle]
 pub unsafe fn renderer_init(
@@ -71,7 +146,15 @@ pub unsafe fn renderer_init(
 
     if RENDERER_STARTED.compare_exchange(false, true,
         Ordering::Acquire, Ordering::Relaxed).is_err() {
+        if !RENDERER_RUNNING.load(Ordering::Acquire) {
+            // still waiting for a surface with a size
+            if width > 0 && height > 0 {
+                start_pending_renderer(&window);
+            }
+            return;
+        }
         let win = window.ptr().as_ptr() as *mut c_void;
+        let _lock = WINDOW_LOCK.lock().unwrap();
         renderer_bindings::setNativeWindow(win);
         renderer_bindings::resetSubWindow(win, 0, 0, width, height, width, height, 1.0, 0.0);
     } else {
@@ -85,44 +168,139 @@ pub unsafe fn renderer_init(
             }
         }
 
-        input::start_input_system(width, height);
+        biometric::start_biometric_server();
+        haptics::start_haptics_server();
+        camera::start_camera_server();
+        nfc::start_nfc_server();
+
+        // a fixed guest density replaces the one of the host screen
+        let density = config::get_or(config::RENDERER_CONF, "density", 0);
+        let (xdpi, ydpi) = if density > 0 { (density as f32, density as f32) } else { (xdpi, ydpi) };
 
-        thread::spawn(move || {
-            // Memberikan prioritas tinggi pada thread renderer
-            unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, -10); }
+        let pending = PendingStart { xdpi, ydpi, fps: safe_fps };
+        if width == 0 || height == 0 {
+            // some drivers crash on a 0 sized viewport, wait for resetWindow with a real size
+            info!("surface has no size yet, renderer start deferred");
+            *PENDING_START.lock().unwrap() = Some(pending);
+        } else {
+            start_renderer(window, pending);
+        }
+
+        // reaping a stale guest can take seconds, surfaceCreated must not wait for it
+        let loader_path = jstring_to_string(&mut env, loader);
+        let spawned = thread::Builder::new().name("twoyi-guest-start".to_string()).spawn(move || {
+            let status = match loader_path {
+                Some(loader_path) => container::container_start(&loader_path),
+                None => {
+                    error!("invalid loader path, guest not started");
+                    container::StartStatus::Failed
+                }
+            };
+            callback::call_static("onGuestStarted", "(I)V", &[JValue::Int(status.code())]);
+        });
+        if let Err(e) = spawned {
+            error!("spawn guest start failed: {}", e);
+            callback::call_static("onGuestStarted", "(I)V", &[JValue::Int(container::StartStatus::Failed.code())]);
+        }
+    }
+}
+
+fn start_renderer(window: ndk::native_window::NativeWindow, start: PendingStart) {
+    let width = window.width();
+    let height = window.height();
+    input::input_init(width, height);
+    *GUEST_DISPLAY_SIZE.lock().unwrap() = (width, height);
+    RENDERER_RUNNING.store(true, Ordering::Release);
+    // startOpenGLRenderer also programs the guest display config, so the guest vsync matches
+    RENDER_FPS.store(start.fps, Ordering::Release);
+    info!("guest display refresh rate set to {} fps", start.fps);
+    let _ = thread::Builder::new().name("twoyi-gpu-check".to_string()).spawn(gpu::check_acceleration);
+
+    let stack_kb = config::get_or(config::RENDERER_CONF, "render_stack_kb", DEFAULT_RENDER_STACK_KB).clamp(MIN_RENDER_STACK_KB, MAX_RENDER_STACK_KB);
+    let render_thread = thread::Builder::new()
+        .name("twoyi-render".to_string())
+        .stack_size(stack_kb * 1024);
+    let spawned = render_thread.spawn(move || {
+        // Memberikan prioritas tinggi pada thread renderer
+        set_render_thread_priority();
+
+        let scale = clamp_render_scale(config::get_or(config::RENDERER_CONF, "render_scale", MAX_RENDER_SCALE));
+        let sharing = config::get_or(config::RENDERER_CONF, "context_sharing", false);
+        // the renderer doesn't report GL memory, so savings can't be logged
+        info!("gl context sharing: {}", sharing);
+        unsafe {
+            renderer_bindings::setContextSharing(sharing);
+            renderer_bindings::setCompositorThreads(compositor_threads());
+            renderer_bindings::setTonemap(config::get_or(config::RENDERER_CONF, "sdr_tonemap", false));
+            let color_mode = config::get_or(config::RENDERER_CONF, "color_mode", gpu::COLOR_MODE_SRGB);
+            if color_mode != gpu::COLOR_MODE_SRGB {
+                gpu::apply_color_mode(color_mode);
+            }
+            renderer_bindings::setRenderScale(scale);
+            renderer_bindings::setErrorCallback(render_error::on_render_error);
+            renderer_bindings::setIdleParams(
+                config::get_or(config::RENDERER_CONF, "idle_timeout_ms", DEFAULT_IDLE_TIMEOUT_MS),
+                config::get_or(config::RENDERER_CONF, "idle_fps", DEFAULT_IDLE_FPS),
+            );
+            renderer_bindings::setIdleMode(IDLE_MODE.load(Ordering::Acquire));
 
             let win = window.ptr().as_ptr() as *mut c_void;
             renderer_bindings::startOpenGLRenderer(
                 win,
                 width,
                 height,
-                xdpi as i32,
-                ydpi as i32,
-                safe_fps,
+                start.xdpi as i32,
+                start.ydpi as i32,
+                start.fps,
             );
-        });
+        }
+        // the splash is drawn by the renderer, so it can only go up now
+        splash::show();
+    });
+    if let Err(e) = spawned {
+        error!("spawn render thread failed: {}", e);
+    }
+}
 
-        let loader_obj = JObject::from_raw(loader);
-        let loader_jstr = JString::from(loader_obj);
-        let loader_path: String = env.get_string(&loader_jstr).unwrap().into();
+// GL drivers run deep native call chains on the render thread, the 2 MiB default is tight for some
+const DEFAULT_RENDER_STACK_KB: usize = 4096;
+const MIN_RENDER_STACK_KB: usize = 1024;
+const MAX_RENDER_STACK_KB: usize = 65536;
 
-        let working_dir = "/data/data/io.twoyi/rootfs";
-        let log_path = "/data/data/io.twoyi/log.txt";
+// nice values tried for the render thread, lowering below 0 may not be permitted
+const RENDER_THREAD_PRIORITIES: [i32; 3] = [-10, -4, 0];
+static RENDER_THREAD_PRIORITY: AtomicI32 = AtomicI32::new(i32::MIN);
 
-        if let Ok(outputs) = File::create(log_path) {
-            let errors = outputs.try_clone().unwrap();
+fn set_render_thread_priority() {
+    // who = 0 is the calling thread on Linux
+    for priority in RENDER_THREAD_PRIORITIES {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, priority) } == 0 {
+            break;
+        }
+        warn!("render thread priority {} failed: {}", priority, std::io::Error::last_os_error());
+    }
 
-            // Gunakan 'nice' untuk menjalankan container
-            let _ = Command::new("nice")
-                .arg("-n")
-                .arg("5")
-                .arg("./init")
-                .current_dir(working_dir)
-                .env("TYLOADER", loader_path)
-                .stdout(Stdio::from(outputs))
-                .stderr(Stdio::from(errors))
-                .spawn();
+    // -1 is a valid result of getpriority, only errno tells about errors
+    unsafe { *libc::__errno() = 0 };
+    let effective = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+    if effective == -1 && std::io::Error::last_os_error().raw_os_error() != Some(0) {
+        error!("getpriority failed: {}", std::io::Error::last_os_error());
+        return;
+    }
+    info!("render thread priority: {}", effective);
+    RENDER_THREAD_PRIORITY.store(effective, Ordering::Release);
+}
+
+// Starts a renderer deferred by renderer_init, the pending state is taken so it happens once.
+fn start_pending_renderer(window: &ndk::native_window::NativeWindow) -> bool {
+    let pending = PENDING_START.lock().unwrap().take();
+    match pending {
+        Some(start) => {
+            info!("starting deferred renderer {}x{}", window.width(), window.height());
+            start_renderer(window.clone(), start);
+            true
         }
+        None => false,
     }
 }
 
@@ -138,26 +316,176 @@ pub unsafe fn renderer_reset_window(
 ) {
     let surface_obj = JObject::from_raw(surface);
     let window = ndk_sys::ANativeWindow_fromSurface(env.get_native_interface(), surface_obj.as_raw());
+    if !RENDERER_RUNNING.load(Ordering::Acquire) {
+        if let Some(window) = std::ptr::NonNull::new(window) {
+            let window = ndk::native_window::NativeWindow::from_ptr(window);
+            if window.width() > 0 && window.height() > 0 {
+                start_pending_renderer(&window);
+            }
+        }
+        return;
+    }
+    let _lock = WINDOW_LOCK.lock().unwrap();
     renderer_bindings::resetSubWindow(window as *mut c_void, 0, 0, _width, _height, _width, _height, 1.0, 0.0);
+    invalidate_display();
 }
 
 #[no_mangle]
 pub unsafe fn renderer_remove_window(env: JNIEnv, _clz: jclass, surface: jobject) {
     let surface_obj = JObject::from_raw(surface);
     let window = ndk_sys::ANativeWindow_fromSurface(env.get_native_interface(), surface_obj.as_raw());
+    let _lock = WINDOW_LOCK.lock().unwrap();
     renderer_bindings::removeSubWindow(window as *mut c_void);
 }
 
+// Redraws the guest so a stale frame doesn't stay on screen after pause or a surface change.
+fn invalidate_display() {
+    if RENDERER_RUNNING.load(Ordering::Acquire) {
+        unsafe { renderer_bindings::forceRedraw() };
+    }
+}
+
+#[no_mangle]
+pub fn invalidate_guest_display(_env: JNIEnv, _clz: jclass) {
+    invalidate_display();
+}
+
+#[no_mangle]
+pub unsafe fn renderer_pause(_env: JNIEnv, _clz: jclass) {
+    if !RENDERER_RUNNING.load(Ordering::Acquire) {
+        return;
+    }
+    if RENDERER_PAUSED.compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+        info!("renderer paused");
+        renderer_bindings::pauseRendering();
+        power::on_host_background();
+    }
+}
+
+#[no_mangle]
+pub unsafe fn renderer_resume(_env: JNIEnv, _clz: jclass) {
+    if RENDERER_PAUSED.compare_exchange(true, false, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+        info!("renderer resumed");
+        renderer_bindings::resumeRendering();
+        power::on_host_foreground();
+        // the last frame may be stale, draw everything again.
+        invalidate_display();
+    }
+}
+
+/// Renders the guest at a lower internal resolution, input is not affected
+/// because the frame is upscaled back to the full surface.
+#[no_mangle]
+pub fn set_render_scale(_env: JNIEnv, _clz: jclass, scale: jfloat) {
+    if !is_valid_render_scale(scale) {
+        error!("invalid render scale {}, must be in ({}, {}]", scale, MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        return;
+    }
+    config::set(config::RENDERER_CONF, "render_scale", &scale.to_string());
+    apply_render_scale(scale);
+}
+
+const DEFAULT_COMPOSITOR_THREADS: i32 = 1;
+
+// Compositor threads from renderer.conf clamped to the cores. With a shared
+// context all sub-windows draw through one context, so composition stays on one thread.
+fn compositor_threads() -> i32 {
+    let requested = config::get_or(config::RENDERER_CONF, "compositor_threads", DEFAULT_COMPOSITOR_THREADS);
+    if config::get_or(config::RENDERER_CONF, "context_sharing", false) {
+        if requested > 1 {
+            warn!("gl context sharing is on, compositing on a single thread");
+        }
+        return 1;
+    }
+    let cores = thread::available_parallelism().map(|n| n.get() as i32).unwrap_or(1);
+    requested.clamp(1, cores)
+}
+
+pub(crate) fn render_stats() -> String {
+    format!(
+        "started={} paused={} render_scale={} idle_mode={} compositor_threads={}",
+        RENDERER_RUNNING.load(Ordering::Acquire),
+        RENDERER_PAUSED.load(Ordering::Acquire),
+        clamp_render_scale(config::get_or(config::RENDERER_CONF, "render_scale", MAX_RENDER_SCALE)),
+        IDLE_MODE.load(Ordering::Acquire),
+        compositor_threads(),
+    )
+}
+
+/// Reads a Java string without panicking, `None` for null or undecodable strings.
+pub(crate) unsafe fn jstring_to_string(env: &mut JNIEnv, obj: jstring) -> Option<String> {
+    if obj.is_null() {
+        return None;
+    }
+
+    let jstr = JString::from(JObject::from_raw(obj));
+    let java_str = env.get_string(&jstr).ok()?;
+    decode_modified_utf8(java_str.to_bytes())
+}
+
+// Java hands out modified UTF-8: NUL is C0 80 and supplementary characters are
+// surrogate pairs of two 3 byte sequences, so decode to UTF-16 first.
+fn decode_modified_utf8(bytes: &[u8]) -> Option<String> {
+    fn continuation(bytes: &[u8], i: usize) -> Option<u16> {
+        match bytes.get(i) {
+            Some(b) if b & 0xc0 == 0x80 => Some((b & 0x3f) as u16),
+            _ => None,
+        }
+    }
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        let (unit, len) = match b {
+            0x01..=0x7f => (b as u16, 1),
+            0xc0..=0xdf => ((((b & 0x1f) as u16) << 6) | continuation(bytes, i + 1)?, 2),
+            0xe0..=0xef => {
+                let high = ((b & 0x0f) as u16) << 12;
+                (high | (continuation(bytes, i + 1)? << 6) | continuation(bytes, i + 2)?, 3)
+            }
+            _ => return None,
+        };
+        units.push(unit);
+        i += len;
+    }
+    // pairs the surrogates, lone ones are rejected
+    String::from_utf16(&units).ok()
+}
+
+// The persisted render scale, what the renderer runs at unless memory pressure lowered it.
+pub(crate) fn render_scale() -> f32 {
+    clamp_render_scale(config::get_or(config::RENDERER_CONF, "render_scale", MAX_RENDER_SCALE))
+}
+
+// Changes the render scale of the running renderer without persisting it.
+pub(crate) fn apply_render_scale(scale: f32) {
+    let scale = clamp_render_scale(scale);
+    info!("set render scale: {}", scale);
+    if RENDERER_RUNNING.load(Ordering::Acquire) {
+        unsafe { renderer_bindings::setRenderScale(scale) };
+    }
+}
+
+// Input restores the full frame rate right away instead of waiting for guest damage.
+pub(crate) fn wake_renderer() {
+    if IDLE_MODE.load(Ordering::Acquire) && RENDERER_RUNNING.load(Ordering::Acquire) {
+        unsafe { renderer_bindings::wakeRenderer() };
+    }
+}
+
 #[no_mangle]
 pub unsafe fn handle_touch(mut env: JNIEnv, _clz: jclass, event: jobject) {
     if event.is_null() { return; }
     let event_obj = JObject::from_raw(event);
 
+    // the Java MotionEvent owns the native event and may recycle it once we return,
+    // the ndk wrapper never frees it, so input only borrows it for this call.
     if let Ok(ptr_field) = env.get_field(&event_obj, "mNativePtr", "J") {
         if let Ok(ptr_val) = ptr_field.j() {
             if let Some(nonptr) = std::ptr::NonNull::new(ptr_val as *mut ndk_sys::AInputEvent) {
                 let ev = ndk::event::MotionEvent::from_ptr(nonptr);
-                input::handle_touch(ev);
+                input::handle_touch(&ev);
             }
         }
     }
@@ -168,6 +496,887 @@ pub fn send_key_code(_env: JNIEnv, _clz: jclass, keycode: jint) {
     input::send_key_code(keycode);
 }
 
+#[no_mangle]
+pub unsafe fn get_guest_storage_info(mut env: JNIEnv, _clz: jclass) -> jlongArray {
+    let info = storage::guest_storage_info();
+    match env.new_long_array(info.len() as i32) {
+        Ok(arr) => {
+            let _ = env.set_long_array_region(&arr, 0, &info);
+            arr.into_raw()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe fn get_guest_app_data_size(mut env: JNIEnv, _clz: jclass, package: jstring) -> jlong {
+    let package = match jstring_to_string(&mut env, package) {
+        Some(s) => s,
+        None => return -1,
+    };
+    storage::guest_app_data_size(&package)
+}
+
+#[no_mangle]
+pub fn send_biometric_result(_env: JNIEnv, _clz: jclass, success: jboolean) {
+    biometric::send_result(success != 0);
+}
+
+#[no_mangle]
+pub fn get_guest_build_info(mut env: JNIEnv, _clz: jclass) -> jstring {
+    match env.new_string(guest::build_info()) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe fn set_guest_model(mut env: JNIEnv, _clz: jclass, model: jstring, manufacturer: jstring) -> jboolean {
+    let model = match jstring_to_string(&mut env, model) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    let manufacturer = match jstring_to_string(&mut env, manufacturer) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    guest::set_model(&model, &manufacturer) as jboolean
+}
+
+#[no_mangle]
+pub fn wipe_guest_data(_env: JNIEnv, _clz: jclass) -> jboolean {
+    container::wipe_guest_data() as jboolean
+}
+
+#[no_mangle]
+pub fn wipe_guest_full(_env: JNIEnv, _clz: jclass) -> jboolean {
+    container::wipe_guest_full() as jboolean
+}
+
+#[no_mangle]
+pub fn get_gl_info(mut env: JNIEnv, _clz: jclass) -> jstring {
+    let info = if !RENDERER_RUNNING.load(Ordering::Acquire) {
+        "error: renderer not started".to_string()
+    } else {
+        match gpu::gl_info() {
+            Some(info) => info.describe(),
+            None => "error: GL context not created yet".to_string(),
+        }
+    };
+
+    match env.new_string(info) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn set_input_enabled(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    input::set_input_enabled(enabled != 0);
+}
+
+#[no_mangle]
+pub fn set_haptics_enabled(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    haptics::set_haptics_enabled(enabled != 0);
+}
+
+#[no_mangle]
+pub fn get_input_queue_stats(mut env: JNIEnv, _clz: jclass) -> jstring {
+    match env.new_string(input::queue_stats()) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn set_guest_screen_timeout(_env: JNIEnv, _clz: jclass, ms: jint) {
+    thread::spawn(move || {
+        guest::set_screen_timeout(ms);
+    });
+}
+
+#[no_mangle]
+pub fn set_historical_touch_enabled(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    input::set_historical_samples(enabled != 0);
+}
+
+#[no_mangle]
+pub fn get_guest_cpu_usage(mut env: JNIEnv, _clz: jclass) -> jfloatArray {
+    let usage = cpu::guest_cpu_usage();
+    match env.new_float_array(usage.len() as i32) {
+        Ok(arr) => {
+            let _ = env.set_float_array_region(&arr, 0, &usage);
+            arr.into_raw()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn set_touch_calibration(
+    _env: JNIEnv,
+    _clz: jclass,
+    x_scale: jfloat,
+    x_offset: jfloat,
+    y_scale: jfloat,
+    y_offset: jfloat,
+) {
+    input::set_touch_calibration(x_scale, x_offset, y_scale, y_offset);
+}
+
+#[no_mangle]
+pub fn set_guest_immersive(_env: JNIEnv, _clz: jclass, enabled: jboolean) -> jboolean {
+    guest::set_immersive(enabled != 0) as jboolean
+}
+
+#[no_mangle]
+pub fn get_guest_immersive(_env: JNIEnv, _clz: jclass) -> jboolean {
+    guest::is_immersive() as jboolean
+}
+
+#[no_mangle]
+pub fn on_trim_memory(_env: JNIEnv, _clz: jclass, level: jint) {
+    thread::spawn(move || {
+        memory::on_trim_memory(level);
+    });
+}
+
+#[no_mangle]
+pub fn set_reserved_gesture(_env: JNIEnv, _clz: jclass, gestures: jint) {
+    input::set_reserved_gestures(gestures);
+}
+
+#[no_mangle]
+pub fn set_json_log_enabled(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    logging::set_json_log(enabled != 0);
+}
+
+#[no_mangle]
+pub unsafe fn push_frame_to_guest(env: JNIEnv, _clz: jclass, rgba: jbyteArray, width: jint, height: jint) {
+    let rgba = JByteArray::from_raw(rgba);
+    if let Ok(data) = env.convert_byte_array(&rgba) {
+        camera::push_frame(&data, width, height);
+    }
+}
+
+#[no_mangle]
+pub fn start_camera_forward(_env: JNIEnv, _clz: jclass, id: jint) {
+    thread::spawn(move || {
+        camera::start_forward(id);
+    });
+}
+
+#[no_mangle]
+pub fn stop_camera_forward(_env: JNIEnv, _clz: jclass, id: jint) {
+    thread::spawn(move || {
+        camera::stop_forward(id);
+    });
+}
+
+#[no_mangle]
+pub fn get_last_boot_error(mut env: JNIEnv, _clz: jclass) -> jstring {
+    match env.new_string(container::last_boot_error()) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn set_guest_composition(_env: JNIEnv, _clz: jclass, mode: jint) {
+    guest::set_composition(mode);
+}
+
+#[no_mangle]
+pub fn set_guest_density(_env: JNIEnv, _clz: jclass, dpi: jint) {
+    if dpi != 0 && !(MIN_GUEST_DENSITY..=MAX_GUEST_DENSITY).contains(&dpi) {
+        error!("invalid guest density: {}", dpi);
+        return;
+    }
+
+    info!("set guest density: {}", dpi);
+    config::set(config::RENDERER_CONF, "density", &dpi.to_string());
+    // the config covers the next boot, a running guest is updated right away
+    thread::spawn(move || {
+        guest::set_density(dpi);
+    });
+}
+
+#[no_mangle]
+pub fn list_guest_packages(mut env: JNIEnv, _clz: jclass) -> jobjectArray {
+    let packages = packages::list_packages();
+    let array = match env.new_object_array(packages.len() as i32, "java/lang/String", JObject::null()) {
+        Ok(array) => array,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    for (i, package) in packages.iter().enumerate() {
+        if let Ok(s) = env.new_string(package) {
+            let _ = env.set_object_array_element(&array, i as i32, s);
+        }
+    }
+    array.into_raw()
+}
+
+#[no_mangle]
+pub unsafe fn get_package_label(mut env: JNIEnv, clz: jclass, package: jstring) -> jstring {
+    let package = match jstring_to_string(&mut env, package) {
+        Some(s) => s,
+        None => return std::ptr::null_mut(),
+    };
+
+    let clazz = JClass::from_raw(clz);
+    let label = packages::package_label(&mut env, &clazz, &package);
+    match env.new_string(label) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe fn launch_guest_app(mut env: JNIEnv, _clz: jclass, package: jstring) -> jboolean {
+    let package = match jstring_to_string(&mut env, package) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    packages::launch(&package) as jboolean
+}
+
+#[no_mangle]
+pub unsafe fn uninstall_guest_app(mut env: JNIEnv, _clz: jclass, package: jstring) -> jint {
+    let package = match jstring_to_string(&mut env, package) {
+        Some(s) => s,
+        None => return packages::UNINSTALL_FAILED,
+    };
+    packages::uninstall(&package)
+}
+
+#[no_mangle]
+pub unsafe fn read_guest_file(mut env: JNIEnv, _clz: jclass, path: jstring) -> jbyteArray {
+    let path = match jstring_to_string(&mut env, path) {
+        Some(s) => s,
+        None => return std::ptr::null_mut(),
+    };
+
+    match storage::read_guest_file(&path) {
+        Ok(data) => match env.byte_array_from_slice(&data) {
+            Ok(array) => array.into_raw(),
+            Err(_) => std::ptr::null_mut(),
+        },
+        Err(storage::FileError::PermissionDenied) => {
+            let _ = env.throw_new("java/lang/SecurityException", format!("permission denied: {}", path));
+            std::ptr::null_mut()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe fn write_guest_file(mut env: JNIEnv, _clz: jclass, path: jstring, data: jbyteArray) -> jboolean {
+    let path = match jstring_to_string(&mut env, path) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    let data = match env.convert_byte_array(&JByteArray::from_raw(data)) {
+        Ok(data) => data,
+        Err(_) => return JNI_FALSE,
+    };
+
+    match storage::write_guest_file(&path, &data) {
+        Ok(_) => JNI_TRUE,
+        Err(storage::FileError::PermissionDenied) => {
+            let _ = env.throw_new("java/lang/SecurityException", format!("permission denied: {}", path));
+            JNI_FALSE
+        }
+        Err(_) => JNI_FALSE,
+    }
+}
+
+#[no_mangle]
+pub fn set_idle_mode(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    let enabled = enabled != 0;
+    info!("set idle mode: {}", enabled);
+    IDLE_MODE.store(enabled, Ordering::Release);
+    config::set(config::RENDERER_CONF, "idle_mode", &enabled.to_string());
+    if RENDERER_RUNNING.load(Ordering::Acquire) {
+        unsafe { renderer_bindings::setIdleMode(enabled) };
+    }
+}
+
+#[no_mangle]
+pub fn input_init(_env: JNIEnv, _clz: jclass, width: jint, height: jint) {
+    input::input_init(width, height);
+}
+
+#[no_mangle]
+pub fn input_shutdown(_env: JNIEnv, _clz: jclass) {
+    input::input_shutdown();
+}
+
+#[no_mangle]
+pub unsafe fn get_guest_setting(mut env: JNIEnv, _clz: jclass, namespace: jstring, key: jstring) -> jstring {
+    let namespace = match jstring_to_string(&mut env, namespace) {
+        Some(s) => s,
+        None => return std::ptr::null_mut(),
+    };
+    let key = match jstring_to_string(&mut env, key) {
+        Some(s) => s,
+        None => return std::ptr::null_mut(),
+    };
+
+    match guest::get_setting(&namespace, &key) {
+        Some(value) => match env.new_string(value) {
+            Ok(s) => s.into_raw(),
+            Err(_) => std::ptr::null_mut(),
+        },
+        None => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe fn set_guest_setting(mut env: JNIEnv, _clz: jclass, namespace: jstring, key: jstring, value: jstring) -> jboolean {
+    let namespace = match jstring_to_string(&mut env, namespace) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    let key = match jstring_to_string(&mut env, key) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    let value = match jstring_to_string(&mut env, value) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+
+    guest::put_setting(&namespace, &key, &value) as jboolean
+}
+
+#[no_mangle]
+pub fn set_guest_volume(_env: JNIEnv, _clz: jclass, percent: jint) -> jboolean {
+    guest::set_volume(percent) as jboolean
+}
+
+#[no_mangle]
+pub fn get_guest_volume(_env: JNIEnv, _clz: jclass) -> jint {
+    guest::volume()
+}
+
+#[no_mangle]
+pub fn send_key_event_with_scancode(_env: JNIEnv, _clz: jclass, keycode: jint, scancode: jint, down: jboolean) {
+    input::send_key_event(keycode, scancode, down != 0);
+}
+
+#[no_mangle]
+pub unsafe fn export_diagnostics(mut env: JNIEnv, _clz: jclass, path: jstring) -> jboolean {
+    let path = match jstring_to_string(&mut env, path) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+
+    thread::spawn(move || {
+        let success = diagnostics::export(&path);
+        callback::with_env(|env| {
+            if let Ok(jpath) = env.new_string(&path) {
+                callback::call_static_in(
+                    env,
+                    "onDiagnosticsExported",
+                    "(Ljava/lang/String;Z)V",
+                    &[JValue::Object(&jpath), JValue::Bool(success as jboolean)],
+                );
+            }
+        });
+    });
+    JNI_TRUE
+}
+
+#[no_mangle]
+pub unsafe fn set_guest_app_resident(mut env: JNIEnv, _clz: jclass, package: jstring, resident: jboolean) -> jboolean {
+    let package = match jstring_to_string(&mut env, package) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    memory::set_app_resident(&package, resident != 0) as jboolean
+}
+
+#[no_mangle]
+pub fn get_guest_refresh_rate(_env: JNIEnv, _clz: jclass) -> jfloat {
+    let render_fps = RENDER_FPS.load(Ordering::Acquire);
+    match guest::refresh_rate() {
+        Some(rate) => {
+            info!("guest refresh rate: {}, render fps: {}", rate, render_fps);
+            if render_fps > 0 && (rate - render_fps as f32).abs() > 1.0 {
+                warn!("guest refresh rate {} doesn't match render fps {}, animations run at the wrong speed", rate, render_fps);
+            }
+            rate
+        }
+        None => -1.0,
+    }
+}
+
+#[no_mangle]
+pub fn set_context_sharing(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    let enabled = enabled != 0;
+    info!("set gl context sharing: {}, applied on the next start", enabled);
+    config::set(config::RENDERER_CONF, "context_sharing", &enabled.to_string());
+}
+
+#[no_mangle]
+pub fn get_render_thread_priority(_env: JNIEnv, _clz: jclass) -> jint {
+    RENDER_THREAD_PRIORITY.load(Ordering::Acquire)
+}
+
+#[no_mangle]
+pub unsafe fn mount_into_running_guest(mut env: JNIEnv, _clz: jclass, host_path: jstring, guest_path: jstring) -> jint {
+    let host_path = match jstring_to_string(&mut env, host_path) {
+        Some(s) => s,
+        None => return container::MOUNT_INVALID_PATH,
+    };
+    let guest_path = match jstring_to_string(&mut env, guest_path) {
+        Some(s) => s,
+        None => return container::MOUNT_INVALID_PATH,
+    };
+    match container::mount_into_guest(&host_path, &guest_path) {
+        Ok(_) => container::MOUNT_SUCCESS,
+        Err(e) => e.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe fn unmount_from_running_guest(mut env: JNIEnv, _clz: jclass, guest_path: jstring) -> jint {
+    let guest_path = match jstring_to_string(&mut env, guest_path) {
+        Some(s) => s,
+        None => return container::MOUNT_INVALID_PATH,
+    };
+    match container::unmount_from_guest(&guest_path) {
+        Ok(_) => container::MOUNT_SUCCESS,
+        Err(e) => e.code(),
+    }
+}
+
+#[no_mangle]
+pub fn get_guest_wakelocks(env: JNIEnv, _clz: jclass) -> jstring {
+    match env.new_string(power::wake_locks_json()) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe fn release_guest_wakelock(mut env: JNIEnv, _clz: jclass, tag: jstring) -> jboolean {
+    let tag = match jstring_to_string(&mut env, tag) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    power::release(&tag) as jboolean
+}
+
+#[no_mangle]
+pub fn set_guest_pointer_debug(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    let enabled = enabled != 0;
+    thread::spawn(move || {
+        guest::set_pointer_debug(enabled);
+    });
+}
+
+#[no_mangle]
+pub fn set_compositor_threads(_env: JNIEnv, _clz: jclass, threads: jint) {
+    config::set(config::RENDERER_CONF, "compositor_threads", &threads.to_string());
+    let threads = compositor_threads();
+    // the renderer doesn't report presented frames, only the target fps can be logged
+    info!("compositor threads: {}, target fps: {}", threads, RENDER_FPS.load(Ordering::Acquire));
+    if RENDERER_RUNNING.load(Ordering::Acquire) {
+        unsafe { renderer_bindings::setCompositorThreads(threads) };
+    }
+}
+
+#[no_mangle]
+pub fn run_self_test(env: JNIEnv, _clz: jclass) -> jstring {
+    match env.new_string(selftest::run()) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe fn set_guest_proxy(mut env: JNIEnv, _clz: jclass, host: jstring, port: jint) -> jboolean {
+    // the host doesn't matter when clearing
+    let host = jstring_to_string(&mut env, host).unwrap_or_default();
+    guest::set_proxy(&host, port) as jboolean
+}
+
+#[no_mangle]
+pub unsafe fn open_frame_pipe(mut env: JNIEnv, _clz: jclass, path: jstring, fps: jint) -> jint {
+    let path = match jstring_to_string(&mut env, path) {
+        Some(s) => s,
+        None => return -1,
+    };
+    framepipe::open(&path, fps)
+}
+
+#[no_mangle]
+pub fn close_frame_pipe(_env: JNIEnv, _clz: jclass, handle: jint) {
+    framepipe::close(handle);
+}
+
+#[no_mangle]
+pub unsafe fn set_guest_hostname(mut env: JNIEnv, _clz: jclass, name: jstring) -> jboolean {
+    let name = match jstring_to_string(&mut env, name) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    guest::set_hostname(&name) as jboolean
+}
+
+#[no_mangle]
+pub fn set_home_key_behavior(_env: JNIEnv, _clz: jclass, behavior: jint) {
+    input::set_home_behavior(behavior);
+}
+
+#[no_mangle]
+pub fn trim_guest_memory(_env: JNIEnv, _clz: jclass, level: jint) {
+    thread::spawn(move || {
+        memory::trim_guest_memory(level);
+    });
+}
+
+#[no_mangle]
+pub fn recover_guest(_env: JNIEnv, _clz: jclass) {
+    thread::spawn(|| {
+        let action = recovery::recover();
+        callback::call_static("onGuestRecovered", "(I)V", &[JValue::Int(action)]);
+    });
+}
+
+#[no_mangle]
+pub fn set_guest_font_scale(_env: JNIEnv, _clz: jclass, scale: jfloat) {
+    thread::spawn(move || {
+        guest::set_font_scale(scale);
+    });
+}
+
+// `[x, y, width, height]` of what changed in the last frame, in guest display pixels
+// from the top left. The whole display when the driver doesn't track damage.
+fn last_dirty_region() -> [jint; 4] {
+    if !RENDERER_RUNNING.load(Ordering::Acquire) {
+        return [0; 4];
+    }
+    let (mut x, mut y, mut w, mut h) = (0, 0, 0, 0);
+    if unsafe { renderer_bindings::getDirtyRegion(&mut x, &mut y, &mut w, &mut h) } != 0 {
+        return [x, y, w, h];
+    }
+    let (width, height) = *GUEST_DISPLAY_SIZE.lock().unwrap();
+    [0, 0, width, height]
+}
+
+#[no_mangle]
+pub fn get_last_dirty_region(mut env: JNIEnv, _clz: jclass) -> jintArray {
+    let region = last_dirty_region();
+    match env.new_int_array(region.len() as i32) {
+        Ok(arr) => {
+            let _ = env.set_int_array_region(&arr, 0, &region);
+            arr.into_raw()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe fn backup_guest_app(mut env: JNIEnv, _clz: jclass, package: jstring, path: jstring) -> jboolean {
+    let package = match jstring_to_string(&mut env, package) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    let path = match jstring_to_string(&mut env, path) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    packages::backup(&package, &path) as jboolean
+}
+
+#[no_mangle]
+pub unsafe fn restore_guest_app(mut env: JNIEnv, _clz: jclass, package: jstring, path: jstring) -> jboolean {
+    let package = match jstring_to_string(&mut env, package) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    let path = match jstring_to_string(&mut env, path) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    packages::restore(&package, &path) as jboolean
+}
+
+#[no_mangle]
+pub fn set_right_click_on_long_press(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    input::set_right_click_on_long_press(enabled != 0);
+}
+
+#[no_mangle]
+pub fn get_renderer_capabilities(env: JNIEnv, _clz: jclass) -> jstring {
+    let caps = match gpu::capabilities_json() {
+        Some(caps) => caps,
+        None => return std::ptr::null_mut(),
+    };
+    match env.new_string(caps) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn list_guest_users(mut env: JNIEnv, _clz: jclass) -> jobjectArray {
+    let users = users::list_users();
+    let array = match env.new_object_array(users.len() as i32, "java/lang/String", JObject::null()) {
+        Ok(array) => array,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    for (i, user) in users.iter().enumerate() {
+        if let Ok(s) = env.new_string(user) {
+            let _ = env.set_object_array_element(&array, i as i32, s);
+        }
+    }
+    array.into_raw()
+}
+
+#[no_mangle]
+pub fn switch_guest_user(_env: JNIEnv, _clz: jclass, id: jint) -> jboolean {
+    users::switch_user(id) as jboolean
+}
+
+#[no_mangle]
+pub unsafe fn create_guest_user(mut env: JNIEnv, _clz: jclass, name: jstring) -> jint {
+    let name = match jstring_to_string(&mut env, name) {
+        Some(s) => s,
+        None => return -1,
+    };
+    users::create_user(&name)
+}
+
+#[no_mangle]
+pub unsafe fn send_nfc_tag(env: JNIEnv, _clz: jclass, tag: jbyteArray) {
+    if tag.is_null() {
+        return;
+    }
+    let tag = JByteArray::from_raw(tag);
+    if let Ok(data) = env.convert_byte_array(&tag) {
+        nfc::send_tag(&data);
+    }
+}
+
+#[no_mangle]
+pub fn set_input_flush_interval(_env: JNIEnv, _clz: jclass, interval_ms: jint) {
+    input::set_flush_interval(interval_ms);
+}
+
+#[no_mangle]
+pub fn is_hardware_accelerated(_env: JNIEnv, _clz: jclass) -> jboolean {
+    gpu::is_hardware_accelerated() as jboolean
+}
+
+#[no_mangle]
+pub fn set_keep_guest_awake(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    power::set_keep_awake(enabled != 0);
+}
+
+#[no_mangle]
+pub fn set_guest_animation_scale(_env: JNIEnv, _clz: jclass, scale: jfloat) {
+    thread::spawn(move || {
+        guest::set_animation_scale(scale);
+    });
+}
+
+#[no_mangle]
+pub unsafe fn set_guest_init_args(mut env: JNIEnv, _clz: jclass, args: jobjectArray) {
+    let mut parsed = Vec::new();
+    if !args.is_null() {
+        let array = JObjectArray::from_raw(args);
+        let len = env.get_array_length(&array).unwrap_or(0);
+        for i in 0..len {
+            let arg = match env.get_object_array_element(&array, i) {
+                Ok(arg) => arg,
+                Err(_) => return,
+            };
+            match jstring_to_string(&mut env, arg.into_raw()) {
+                Some(s) => parsed.push(s),
+                None => return,
+            }
+        }
+    }
+    container::set_init_args(parsed);
+}
+
+#[no_mangle]
+pub fn set_guest_color_mode(_env: JNIEnv, _clz: jclass, mode: jint) {
+    if !(gpu::COLOR_MODE_SRGB..=gpu::COLOR_MODE_HDR).contains(&mode) {
+        error!("invalid color mode: {}", mode);
+        return;
+    }
+    config::set(config::RENDERER_CONF, "color_mode", &mode.to_string());
+    let applied = if RENDERER_RUNNING.load(Ordering::Acquire) {
+        gpu::apply_color_mode(mode)
+    } else {
+        mode
+    };
+    thread::spawn(move || {
+        guest::set_color_mode(applied);
+    });
+}
+
+#[no_mangle]
+pub fn get_input_device_ranges(mut env: JNIEnv, _clz: jclass) -> jintArray {
+    let ranges = match input::device_ranges() {
+        Some(ranges) => ranges,
+        None => return std::ptr::null_mut(),
+    };
+    match env.new_int_array(ranges.len() as i32) {
+        Ok(arr) => {
+            let _ = env.set_int_array_region(&arr, 0, &ranges);
+            arr.into_raw()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn reboot_guest(_env: JNIEnv, _clz: jclass, mode: jint) {
+    thread::spawn(move || {
+        let status = container::container_reboot(mode);
+        callback::call_static("onGuestStarted", "(I)V", &[JValue::Int(status.code())]);
+    });
+}
+
+#[no_mangle]
+pub fn get_guest_activity_stack(env: JNIEnv, _clz: jclass) -> jstring {
+    match env.new_string(activities::activity_stack().join("\n")) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn get_guest_top_activity(env: JNIEnv, _clz: jclass) -> jstring {
+    match env.new_string(activities::top_activity().unwrap_or_default()) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn send_dpad(_env: JNIEnv, _clz: jclass, direction: jint) {
+    input::send_dpad(direction, true);
+    input::send_dpad(direction, false);
+}
+
+#[no_mangle]
+pub fn send_dpad_key(_env: JNIEnv, _clz: jclass, direction: jint, down: jboolean) {
+    input::send_dpad(direction, down != 0);
+}
+
+#[no_mangle]
+pub fn list_crash_logs(mut env: JNIEnv, _clz: jclass) -> jobjectArray {
+    let logs = container::list_crash_logs();
+    let array = match env.new_object_array(logs.len() as i32, "java/lang/String", JObject::null()) {
+        Ok(array) => array,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    for (i, log) in logs.iter().enumerate() {
+        if let Ok(s) = env.new_string(log) {
+            let _ = env.set_object_array_element(&array, i as i32, s);
+        }
+    }
+    array.into_raw()
+}
+
+#[no_mangle]
+pub fn set_pointer_acceleration(_env: JNIEnv, _clz: jclass, sensitivity: jfloat) {
+    input::set_pointer_acceleration(sensitivity);
+}
+
+#[no_mangle]
+pub fn set_guest_airplane_mode(_env: JNIEnv, _clz: jclass, enabled: jboolean) -> jboolean {
+    guest::set_airplane_mode(enabled != 0) as jboolean
+}
+
+#[no_mangle]
+pub fn set_guest_wifi_enabled(_env: JNIEnv, _clz: jclass, enabled: jboolean) -> jboolean {
+    guest::set_wifi_enabled(enabled != 0) as jboolean
+}
+
+#[no_mangle]
+pub unsafe fn set_boot_splash(mut env: JNIEnv, _clz: jclass, path: jstring) {
+    let path = match jstring_to_string(&mut env, path) {
+        Some(s) => s,
+        None => return,
+    };
+    splash::set_boot_splash(&path);
+}
+
+#[no_mangle]
+pub fn get_guest_selinux_mode(env: JNIEnv, _clz: jclass) -> jstring {
+    match env.new_string(selinux::mode()) {
+        Ok(s) => s.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn get_recent_selinux_denials(mut env: JNIEnv, _clz: jclass) -> jobjectArray {
+    let denials = selinux::recent_denials();
+    let array = match env.new_object_array(denials.len() as i32, "java/lang/String", JObject::null()) {
+        Ok(array) => array,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    for (i, denial) in denials.iter().enumerate() {
+        if let Ok(s) = env.new_string(denial) {
+            let _ = env.set_object_array_element(&array, i as i32, s);
+        }
+    }
+    array.into_raw()
+}
+
+#[no_mangle]
+pub fn set_keyboard_shortcuts(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    input::set_keyboard_shortcuts(enabled != 0);
+}
+
+#[no_mangle]
+pub unsafe fn extract_rootfs(mut env: JNIEnv, _clz: jclass, archive: jstring, dest: jstring) -> jboolean {
+    let archive = match jstring_to_string(&mut env, archive) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    let dest = match jstring_to_string(&mut env, dest) {
+        Some(s) => s,
+        None => return JNI_FALSE,
+    };
+    rootfs_extract::extract(&archive, &dest) as jboolean
+}
+
+#[no_mangle]
+pub fn set_sdr_to_hdr_tonemap(_env: JNIEnv, _clz: jclass, enabled: jboolean) {
+    let enabled = enabled != 0;
+    info!("sdr to hdr tonemap: {}", enabled);
+    config::set(config::RENDERER_CONF, "sdr_tonemap", &enabled.to_string());
+    if RENDERER_RUNNING.load(Ordering::Acquire) {
+        unsafe { renderer_bindings::setTonemap(enabled) };
+    }
+}
+
+#[no_mangle]
+pub unsafe fn set_guest_app_cpu_limit(mut env: JNIEnv, _clz: jclass, package: jstring, percent: jint) -> jint {
+    let package = match jstring_to_string(&mut env, package) {
+        Some(s) => s,
+        None => return cpulimit::CPU_LIMIT_INVALID_ARGS,
+    };
+    match cpulimit::set_app_cpu_limit(&package, percent) {
+        Ok(_) => cpulimit::CPU_LIMIT_SUCCESS,
+        Err(e) => e.code(),
+    }
+}
+
+#[no_mangle]
+pub fn stop_container_graceful(_env: JNIEnv, _clz: jclass, timeout_ms: jint) -> jboolean {
+    container::container_stop_graceful(timeout_ms) as jboolean
+}
+
 unsafe fn register_natives(jvm: &JavaVM, class_name: &str, methods: &[NativeMethod]) -> jint {
     let mut env = jvm.get_env().unwrap();
     let jni_version = env.get_version().unwrap();
@@ -181,34 +1390,146 @@ unsafe fn register_natives(jvm: &JavaVM, class_name: &str, methods: &[NativeMeth
         }
     };
 
-    let result = env.register_native_methods(&clazz, methods);
-
-    if result.is_ok() {
+    if env.register_native_methods(&clazz, methods).is_ok() {
         debug!("register_natives : succeed");
-        version
-    } else {
-        error!("register_natives : failed ");
-        JNI_ERR
+        return version;
     }
+    let _ = env.exception_clear();
+
+    // the batch doesn't say which one is wrong, register them one by one to find out
+    let mut failed = 0;
+    for method in methods {
+        if let Err(e) = env.register_native_methods(&clazz, std::slice::from_ref(method)) {
+            let _ = env.exception_clear();
+            error!(
+                "register_natives : {}{} failed: {:?}",
+                method.name.to_string_lossy(),
+                method.sig.to_string_lossy(),
+                e
+            );
+            failed += 1;
+        }
+    }
+    error!("register_natives : {} of {} methods failed", failed, methods.len());
+    JNI_ERR
 }
 
 #[no_mangle]
 #[allow(non_snake_case)]
 unsafe fn JNI_OnLoad(jvm: JavaVM, _reserved: *mut c_void) -> jint {
-    android_logger::init_once(
-        Config::default()
-            .with_max_level(LevelFilter::Warn)
-            .with_tag("CLIENT_EGL"),
-    );
+    logging::init();
 
     let class_name = "io/twoyi/Renderer";
     let jni_methods = [
         jni_method!(init, renderer_init, "(Landroid/view/Surface;Ljava/lang/String;FFI)V"),
         jni_method!(resetWindow, renderer_reset_window, "(Landroid/view/Surface;IIII)V"),
         jni_method!(removeWindow, renderer_remove_window, "(Landroid/view/Surface;)V"),
+        jni_method!(pause, renderer_pause, "()V"),
+        jni_method!(resume, renderer_resume, "()V"),
+        jni_method!(invalidateGuestDisplay, invalidate_guest_display, "()V"),
+        jni_method!(setRenderScale, set_render_scale, "(F)V"),
         jni_method!(handleTouch, handle_touch, "(Landroid/view/MotionEvent;)V"),
         jni_method!(sendKeycode, send_key_code, "(I)V"),
+        jni_method!(setInputEnabled, set_input_enabled, "(Z)V"),
+        jni_method!(getInputQueueStats, get_input_queue_stats, "()Ljava/lang/String;"),
+        jni_method!(setHistoricalTouchEnabled, set_historical_touch_enabled, "(Z)V"),
+        jni_method!(setTouchCalibration, set_touch_calibration, "(FFFF)V"),
+        jni_method!(setReservedGesture, set_reserved_gesture, "(I)V"),
+        jni_method!(getGuestStorageInfo, get_guest_storage_info, "()[J"),
+        jni_method!(getGuestAppDataSize, get_guest_app_data_size, "(Ljava/lang/String;)J"),
+        jni_method!(sendBiometricResult, send_biometric_result, "(Z)V"),
+        jni_method!(setHapticsEnabled, set_haptics_enabled, "(Z)V"),
+        jni_method!(pushFrameToGuest, push_frame_to_guest, "([BII)V"),
+        jni_method!(startCameraForward, start_camera_forward, "(I)V"),
+        jni_method!(stopCameraForward, stop_camera_forward, "(I)V"),
+        jni_method!(getLastBootError, get_last_boot_error, "()Ljava/lang/String;"),
+        jni_method!(getGuestBuildInfo, get_guest_build_info, "()Ljava/lang/String;"),
+        jni_method!(setGuestModel, set_guest_model, "(Ljava/lang/String;Ljava/lang/String;)Z"),
+        jni_method!(setGuestScreenTimeout, set_guest_screen_timeout, "(I)V"),
+        jni_method!(setGuestImmersive, set_guest_immersive, "(Z)Z"),
+        jni_method!(getGuestImmersive, get_guest_immersive, "()Z"),
+        jni_method!(wipeGuestData, wipe_guest_data, "()Z"),
+        jni_method!(wipeGuestFull, wipe_guest_full, "()Z"),
+        jni_method!(getGlInfo, get_gl_info, "()Ljava/lang/String;"),
+        jni_method!(getGuestCpuUsage, get_guest_cpu_usage, "()[F"),
+        jni_method!(onTrimMemory, on_trim_memory, "(I)V"),
+        jni_method!(setJsonLogEnabled, set_json_log_enabled, "(Z)V"),
+        jni_method!(setGuestComposition, set_guest_composition, "(I)V"),
+        jni_method!(setGuestDensity, set_guest_density, "(I)V"),
+        jni_method!(listGuestPackages, list_guest_packages, "()[Ljava/lang/String;"),
+        jni_method!(getPackageLabel, get_package_label, "(Ljava/lang/String;)Ljava/lang/String;"),
+        jni_method!(launchGuestApp, launch_guest_app, "(Ljava/lang/String;)Z"),
+        jni_method!(uninstallGuestApp, uninstall_guest_app, "(Ljava/lang/String;)I"),
+        jni_method!(readGuestFile, read_guest_file, "(Ljava/lang/String;)[B"),
+        jni_method!(writeGuestFile, write_guest_file, "(Ljava/lang/String;[B)Z"),
+        jni_method!(setIdleMode, set_idle_mode, "(Z)V"),
+        jni_method!(inputInit, input_init, "(II)V"),
+        jni_method!(inputShutdown, input_shutdown, "()V"),
+        jni_method!(getGuestSetting, get_guest_setting, "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;"),
+        jni_method!(setGuestSetting, set_guest_setting, "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)Z"),
+        jni_method!(setGuestVolume, set_guest_volume, "(I)Z"),
+        jni_method!(getGuestVolume, get_guest_volume, "()I"),
+        jni_method!(sendKeyEventWithScancode, send_key_event_with_scancode, "(IIZ)V"),
+        jni_method!(exportDiagnostics, export_diagnostics, "(Ljava/lang/String;)Z"),
+        jni_method!(setGuestAppResident, set_guest_app_resident, "(Ljava/lang/String;Z)Z"),
+        jni_method!(getGuestRefreshRate, get_guest_refresh_rate, "()F"),
+        jni_method!(setContextSharing, set_context_sharing, "(Z)V"),
+        jni_method!(getRenderThreadPriority, get_render_thread_priority, "()I"),
+        jni_method!(mountIntoRunningGuest, mount_into_running_guest, "(Ljava/lang/String;Ljava/lang/String;)I"),
+        jni_method!(unmountFromRunningGuest, unmount_from_running_guest, "(Ljava/lang/String;)I"),
+        jni_method!(getGuestWakelocks, get_guest_wakelocks, "()Ljava/lang/String;"),
+        jni_method!(releaseGuestWakelock, release_guest_wakelock, "(Ljava/lang/String;)Z"),
+        jni_method!(setGuestPointerDebug, set_guest_pointer_debug, "(Z)V"),
+        jni_method!(setCompositorThreads, set_compositor_threads, "(I)V"),
+        jni_method!(runSelfTest, run_self_test, "()Ljava/lang/String;"),
+        jni_method!(setGuestProxy, set_guest_proxy, "(Ljava/lang/String;I)Z"),
+        jni_method!(openFramePipe, open_frame_pipe, "(Ljava/lang/String;I)I"),
+        jni_method!(closeFramePipe, close_frame_pipe, "(I)V"),
+        jni_method!(setGuestHostname, set_guest_hostname, "(Ljava/lang/String;)Z"),
+        jni_method!(setHomeKeyBehavior, set_home_key_behavior, "(I)V"),
+        jni_method!(trimGuestMemory, trim_guest_memory, "(I)V"),
+        jni_method!(recoverGuest, recover_guest, "()V"),
+        jni_method!(setGuestFontScale, set_guest_font_scale, "(F)V"),
+        jni_method!(getLastDirtyRegion, get_last_dirty_region, "()[I"),
+        jni_method!(backupGuestApp, backup_guest_app, "(Ljava/lang/String;Ljava/lang/String;)Z"),
+        jni_method!(restoreGuestApp, restore_guest_app, "(Ljava/lang/String;Ljava/lang/String;)Z"),
+        jni_method!(setRightClickOnLongPress, set_right_click_on_long_press, "(Z)V"),
+        jni_method!(getRendererCapabilities, get_renderer_capabilities, "()Ljava/lang/String;"),
+        jni_method!(listGuestUsers, list_guest_users, "()[Ljava/lang/String;"),
+        jni_method!(switchGuestUser, switch_guest_user, "(I)Z"),
+        jni_method!(createGuestUser, create_guest_user, "(Ljava/lang/String;)I"),
+        jni_method!(sendNfcTag, send_nfc_tag, "([B)V"),
+        jni_method!(setInputFlushInterval, set_input_flush_interval, "(I)V"),
+        jni_method!(isHardwareAccelerated, is_hardware_accelerated, "()Z"),
+        jni_method!(setKeepGuestAwake, set_keep_guest_awake, "(Z)V"),
+        jni_method!(setGuestAnimationScale, set_guest_animation_scale, "(F)V"),
+        jni_method!(setGuestInitArgs, set_guest_init_args, "([Ljava/lang/String;)V"),
+        jni_method!(setGuestColorMode, set_guest_color_mode, "(I)V"),
+        jni_method!(getInputDeviceRanges, get_input_device_ranges, "()[I"),
+        jni_method!(rebootGuest, reboot_guest, "(I)V"),
+        jni_method!(getGuestActivityStack, get_guest_activity_stack, "()Ljava/lang/String;"),
+        jni_method!(getGuestTopActivity, get_guest_top_activity, "()Ljava/lang/String;"),
+        jni_method!(sendDpad, send_dpad, "(I)V"),
+        jni_method!(sendDpadKey, send_dpad_key, "(IZ)V"),
+        jni_method!(listCrashLogs, list_crash_logs, "()[Ljava/lang/String;"),
+        jni_method!(setPointerAcceleration, set_pointer_acceleration, "(F)V"),
+        jni_method!(setGuestAirplaneMode, set_guest_airplane_mode, "(Z)Z"),
+        jni_method!(setGuestWifiEnabled, set_guest_wifi_enabled, "(Z)Z"),
+        jni_method!(setBootSplash, set_boot_splash, "(Ljava/lang/String;)V"),
+        jni_method!(getGuestSelinuxMode, get_guest_selinux_mode, "()Ljava/lang/String;"),
+        jni_method!(getRecentSelinuxDenials, get_recent_selinux_denials, "()[Ljava/lang/String;"),
+        jni_method!(setKeyboardShortcuts, set_keyboard_shortcuts, "(Z)V"),
+        jni_method!(extractRootfs, extract_rootfs, "(Ljava/lang/String;Ljava/lang/String;)Z"),
+        jni_method!(setSdrToHdrTonemap, set_sdr_to_hdr_tonemap, "(Z)V"),
+        jni_method!(setGuestAppCpuLimit, set_guest_app_cpu_limit, "(Ljava/lang/String;I)I"),
+        jni_method!(stopContainerGraceful, stop_container_graceful, "(I)Z"),
     ];
 
+    if let Ok(mut env) = jvm.get_env() {
+        if let Ok(clazz) = env.find_class(class_name) {
+            callback::init(&mut env, &clazz);
+        }
+    }
+
     register_natives(&jvm, class_name, jni_methods.as_ref())
 }
\ No newline at end of file
@@ -0,0 +1,91 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use android_logger::{AndroidLogger, Config};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{config, json};
+
+const EVENTS_PATH: &'static str = "/data/data/io.twoyi/events.jsonl";
+
+// logcat keeps its level, the JSON events also get the info! lines
+const LOGCAT_LEVEL: LevelFilter = LevelFilter::Warn;
+const JSON_LEVEL: LevelFilter = LevelFilter::Info;
+
+static JSON_ENABLED: AtomicBool = AtomicBool::new(false);
+
+struct TwoyiLogger {
+    android: AndroidLogger,
+    events: Mutex<Option<File>>,
+}
+
+impl Log for TwoyiLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.android.enabled(metadata) || JSON_ENABLED.load(Ordering::Relaxed)
+    }
+
+    fn log(&self, record: &Record) {
+        self.android.log(record);
+
+        if JSON_ENABLED.load(Ordering::Relaxed) && record.level() <= Level::Info {
+            self.write_event(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref mut file) = *self.events.lock().unwrap() {
+            let _ = file.flush();
+        }
+    }
+}
+
+impl TwoyiLogger {
+    fn write_event(&self, record: &Record) {
+        let mut events = self.events.lock().unwrap();
+        if events.is_none() {
+            *events = OpenOptions::new().create(true).append(true).open(EVENTS_PATH).ok();
+        }
+
+        if let Some(ref mut file) = *events {
+            let ts = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+            let line = format!(
+                "{{\"ts\":{},\"level\":\"{}\",\"module\":\"{}\",\"message\":\"{}\"}}\n",
+                ts,
+                record.level(),
+                json::escape(record.module_path().unwrap_or_default()),
+                json::escape(&record.args().to_string())
+            );
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+}
+
+/// Installs the logger, logcat output is the same as before, JSON lines go to
+/// events.jsonl when `json_log` is set in container.conf.
+pub fn init() {
+    let logger = TwoyiLogger {
+        android: AndroidLogger::new(Config::default().with_max_level(LOGCAT_LEVEL).with_tag("CLIENT_EGL")),
+        events: Mutex::new(None),
+    };
+
+    if log::set_logger(Box::leak(Box::new(logger))).is_ok() {
+        apply(config::get_or(config::CONTAINER_CONF, "json_log", false));
+    }
+}
+
+pub fn set_json_log(enabled: bool) {
+    apply(enabled);
+    config::set(config::CONTAINER_CONF, "json_log", &enabled.to_string());
+}
+
+fn apply(enabled: bool) {
+    JSON_ENABLED.store(enabled, Ordering::Relaxed);
+    log::set_max_level(if enabled { JSON_LEVEL } else { LOGCAT_LEVEL });
+}
@@ -0,0 +1,215 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use jni::objects::JValue;
+use once_cell::sync::Lazy;
+
+use log::{error, info, warn};
+
+use crate::{callback, guest, storage};
+
+// ComponentCallbacks2 levels
+const TRIM_MEMORY_RUNNING_LOW: i32 = 10;
+const TRIM_MEMORY_RUNNING_CRITICAL: i32 = 15;
+const TRIM_MEMORY_UI_HIDDEN: i32 = 20;
+const TRIM_MEMORY_COMPLETE: i32 = 80;
+
+// render scale used while the host is critically low on memory
+const LOW_MEMORY_RENDER_SCALE: f32 = 0.5;
+
+// render scale from before the host ran critically low, put back once that is over
+static SCALE_BEFORE_PRESSURE: Mutex<Option<f32>> = Mutex::new(None);
+
+/// Reacts to the host's onTrimMemory before the system kills the guest init for us.
+pub fn on_trim_memory(level: i32) {
+    let critical = level == TRIM_MEMORY_RUNNING_CRITICAL || level >= TRIM_MEMORY_COMPLETE;
+    // hidden, nothing is drawn; below RUNNING_LOW, the pressure eased
+    if !critical && (level == TRIM_MEMORY_UI_HIDDEN || level < TRIM_MEMORY_RUNNING_LOW) {
+        if let Some(scale) = SCALE_BEFORE_PRESSURE.lock().unwrap().take() {
+            info!("host memory pressure over (level {}), render scale back to {}", level, scale);
+            crate::apply_render_scale(scale);
+        }
+    }
+
+    if critical {
+        warn!("host memory critical (level {}), trimming guest memory and lowering render scale", level);
+        SCALE_BEFORE_PRESSURE.lock().unwrap().get_or_insert_with(crate::render_scale);
+        crate::apply_render_scale(LOW_MEMORY_RENDER_SCALE);
+        trim_guest_memory(TRIM_AGGRESSIVE);
+    } else if level >= TRIM_MEMORY_RUNNING_LOW {
+        warn!("host memory low (level {}), trimming guest apps", level);
+        trim_guest_memory(TRIM_LIGHT);
+    } else {
+        info!("trim memory level {} ignored", level);
+        return;
+    }
+
+    callback::call_static("onGuestLowMemory", "(I)V", &[JValue::Int(level)]);
+}
+
+pub const TRIM_LIGHT: i32 = 0;
+pub const TRIM_MODERATE: i32 = 1;
+pub const TRIM_AGGRESSIVE: i32 = 2;
+
+/// Asks every guest app process to give memory back, with the trim level of its
+/// activity manager that matches `level`. Dropping caches or compacting the shared
+/// kernel's memory needs root, which the app uid doesn't have.
+pub fn trim_guest_memory(level: i32) {
+    let level = level.clamp(TRIM_LIGHT, TRIM_AGGRESSIVE);
+    let before = mem_available_kb();
+
+    trim_guest_apps(match level {
+        TRIM_LIGHT => "RUNNING_MODERATE",
+        TRIM_MODERATE => "RUNNING_LOW",
+        _ => "COMPLETE",
+    });
+
+    // the kernel is shared, MemAvailable covers host and guest alike
+    match (before, mem_available_kb()) {
+        (Some(before), Some(after)) => {
+            info!("trim guest memory level {}: {} kB reclaimed", level, after as i64 - before as i64)
+        }
+        _ => info!("trim guest memory level {} done", level),
+    }
+}
+
+fn trim_guest_apps(level: &str) {
+    let output = match guest::shell("ps -A -o NAME") {
+        Some(output) if output.success => output.stdout,
+        _ => return,
+    };
+    let processes: Vec<&str> = output
+        .lines()
+        .map(|line| line.trim())
+        .filter(|name| storage::is_valid_package(name.split(':').next().unwrap_or_default()))
+        .collect();
+
+    if processes.is_empty() {
+        return;
+    }
+    // one shell for all of them, every adb shell costs a round trip
+    let cmd: Vec<String> = processes
+        .iter()
+        .map(|process| format!("am send-trim-memory {} {}", process, level))
+        .collect();
+    let _ = guest::shell(&cmd.join("; "));
+    info!("sent trim memory {} to {} guest processes", level, processes.len());
+}
+
+fn mem_available_kb() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+// FOREGROUND_APP_ADJ, going below the oom_score_adj_min of 0 needs CAP_SYS_RESOURCE
+const RESIDENT_OOM_ADJ: i32 = 0;
+// the guest activity manager rewrites oom_score_adj on every state change
+const RESIDENT_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct ResidentApps {
+    packages: HashSet<String>,
+    // oom_score_adj of each pinned pid before we touched it
+    original: HashMap<i32, i32>,
+}
+
+static RESIDENT_APPS: Lazy<Mutex<ResidentApps>> = Lazy::new(|| {
+    thread::spawn(resident_keeper);
+    Mutex::new(ResidentApps::default())
+});
+
+/// Keeps the processes of a running guest app at foreground priority for the
+/// low memory killers, or gives them back their own value.
+pub fn set_app_resident(package: &str, resident: bool) -> bool {
+    if !storage::is_valid_package(package) {
+        error!("invalid package name: {}", package);
+        return false;
+    }
+
+    let pids = package_pids(package);
+    if pids.is_empty() {
+        error!("{} isn't running", package);
+        return false;
+    }
+
+    let mut apps = RESIDENT_APPS.lock().unwrap();
+    info!("set {} resident: {}", package, resident);
+    if resident {
+        apps.packages.insert(package.to_string());
+        pids.iter().all(|pid| pin(&mut apps, *pid))
+    } else {
+        apps.packages.remove(package);
+        pids.iter().all(|pid| match apps.original.remove(pid) {
+            Some(adj) => write_oom_adj(*pid, adj),
+            None => true,
+        })
+    }
+}
+
+fn pin(apps: &mut ResidentApps, pid: i32) -> bool {
+    if !apps.original.contains_key(&pid) {
+        match read_oom_adj(pid) {
+            Some(adj) => apps.original.insert(pid, adj),
+            None => return false,
+        };
+    }
+    read_oom_adj(pid) == Some(RESIDENT_OOM_ADJ) || write_oom_adj(pid, RESIDENT_OOM_ADJ)
+}
+
+fn resident_keeper() {
+    loop {
+        thread::sleep(RESIDENT_INTERVAL);
+
+        let mut apps = RESIDENT_APPS.lock().unwrap();
+        // dead processes can't be restored
+        apps.original.retain(|pid, _| fs::metadata(format!("/proc/{}", pid)).is_ok());
+        let packages: Vec<String> = apps.packages.iter().cloned().collect();
+        for package in packages {
+            for pid in package_pids(&package) {
+                pin(&mut apps, pid);
+            }
+        }
+    }
+}
+
+// Processes named `package` or `package:service`, guest pids are host pids.
+pub fn package_pids(package: &str) -> Vec<i32> {
+    let entries = match fs::read_dir("/proc") {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+
+    let service_prefix = format!("{}:", package);
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: i32 = entry.file_name().to_str()?.parse().ok()?;
+            let cmdline = fs::read(entry.path().join("cmdline")).ok()?;
+            let name = cmdline.split(|b| *b == 0).next()?;
+            let name = std::str::from_utf8(name).ok()?;
+            (name == package || name.starts_with(&service_prefix)).then_some(pid)
+        })
+        .collect()
+}
+
+fn read_oom_adj(pid: i32) -> Option<i32> {
+    fs::read_to_string(format!("/proc/{}/oom_score_adj", pid)).ok()?.trim().parse().ok()
+}
+
+fn write_oom_adj(pid: i32, adj: i32) -> bool {
+    match fs::write(format!("/proc/{}/oom_score_adj", pid), adj.to_string()) {
+        Ok(_) => true,
+        Err(e) => {
+            error!("set oom_score_adj of {} to {} failed: {}", pid, adj, e);
+            false
+        }
+    }
+}
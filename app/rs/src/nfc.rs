@@ -0,0 +1,102 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::Write;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use unix_socket::{UnixListener, UnixStream};
+
+use log::{error, info, warn};
+
+use crate::config;
+
+const NFC_PATH: &'static str = "/data/data/io.twoyi/rootfs/dev/socket/nfc";
+
+// A tag as the host read it: [u8 technology][u8 id length][id][NDEF message, may be empty].
+// The guest NFC HAL stub gets it prefixed with [u32 length], little endian.
+// Technologies: 1 NFC-A, 2 NFC-B, 3 NFC-F, 4 NFC-V, 5 ISO-DEP, 6 MIFARE Classic, 7 MIFARE Ultralight.
+const MAX_TECH: u8 = 7;
+
+// NFC ids are 4, 7 or 10 bytes, NFC-F ones 8
+const MAX_TAG_ID: usize = 10;
+// more than any NDEF tag holds
+const MAX_TAG_SIZE: usize = 64 * 1024;
+
+const TAG_WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+static NFC_STREAM: Lazy<Mutex<Option<UnixStream>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn start_nfc_server() {
+    if !config::get_or(config::CONTAINER_CONF, "nfc_forwarding", false) {
+        info!("nfc forwarding disabled");
+        return;
+    }
+    thread::spawn(|| {
+        nfc_server();
+    });
+}
+
+fn nfc_server() {
+    let _ = std::fs::remove_file(NFC_PATH);
+    let listener = match UnixListener::bind(NFC_PATH) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("bind nfc socket failed: {}", e);
+            return;
+        }
+    };
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(_) => break,
+        };
+
+        // the HAL never sends anything, the stream is only written by send_tag
+        info!("nfc hal connected!");
+        let _ = stream.set_write_timeout(Some(TAG_WRITE_TIMEOUT));
+        *NFC_STREAM.lock().unwrap() = Some(stream);
+    }
+}
+
+/// Forwards a tag read by the host to the guest, which dispatches the tag discovered intents.
+pub fn send_tag(tag: &[u8]) {
+    if !config::get_or(config::CONTAINER_CONF, "nfc_forwarding", false) {
+        return;
+    }
+    if !is_valid_tag(tag) {
+        error!("invalid nfc tag of {} bytes", tag.len());
+        return;
+    }
+
+    let mut stream = NFC_STREAM.lock().unwrap();
+    let ret = match stream.as_mut() {
+        Some(s) => s
+            .write_all(&(tag.len() as u32).to_le_bytes())
+            .and_then(|_| s.write_all(tag)),
+        None => {
+            warn!("nfc hal not connected, tag dropped");
+            return;
+        }
+    };
+    match ret {
+        Ok(_) => info!("nfc tag forwarded, technology {}", tag[0]),
+        Err(e) => {
+            error!("forward nfc tag failed: {}", e);
+            *stream = None;
+        }
+    }
+}
+
+fn is_valid_tag(tag: &[u8]) -> bool {
+    if tag.len() < 2 || tag.len() > MAX_TAG_SIZE {
+        return false;
+    }
+    let tech = tag[0];
+    let id_len = tag[1] as usize;
+    (1..=MAX_TECH).contains(&tech) && id_len <= MAX_TAG_ID && tag.len() >= 2 + id_len
+}
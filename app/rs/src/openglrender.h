@@ -8,6 +8,8 @@
 
 #pragma once
 
+#include <stdbool.h>
+
 extern int destroyOpenGLSubwindow();
 
 extern void repaintOpenGLDisplay();
@@ -18,4 +20,74 @@ extern int resetSubWindow(void* p_window, int wx, int wy, int ww, int wh, int fb
 
 extern int startOpenGLRenderer(void* win, int width, int height, int xdpi, int ydpi, int fps);
 
-extern int removeSubWindow(void* );
\ No newline at end of file
+extern int removeSubWindow(void* );
+
+extern void pauseRendering();
+
+extern void resumeRendering();
+
+// The guest is rendered at scale * surface size and upscaled to the surface,
+// so input coordinates always stay in surface space.
+extern void setRenderScale(float scale);
+
+// Composes and presents the guest layers again even if nothing changed.
+extern void forceRedraw();
+
+// Context loss, swap failures and driver errors are reported here, on the render thread.
+extern void setErrorCallback(void (*callback)(int code, const char* message));
+
+// Queried on the render thread, returns non-zero if the GL context doesn't exist yet.
+extern int getGlStrings(const char** vendor, const char** renderer, const char** version, const char** extensions);
+
+// Without guest damage for timeout_ms the render loop drops to idle_fps while idle mode
+// is on, the next damage or wakeRenderer() brings back the full rate.
+extern void setIdleMode(bool enabled);
+
+extern void setIdleParams(int timeout_ms, int idle_fps);
+
+extern void wakeRenderer();
+
+// One GL context for all sub-windows instead of one each, only honored before startOpenGLRenderer.
+extern void setContextSharing(bool enabled);
+
+// Threads used to composite sub-windows, 1 composites them all on the render thread.
+extern void setCompositorThreads(int threads);
+
+// EGL surface color space: 0 sRGB, 1 Display P3, 2 BT.2020 PQ. The surface is recreated on the
+// next frame; returns the mode in effect, 0 if the requested one isn't supported. Frame
+// readbacks stay RGBA8888, so in wide gamut their pixels are Display P3 and in HDR clipped.
+extern int setColorMode(int mode);
+
+// Guest content is SDR: on an HDR surface it is tonemapped into the HDR range when enabled,
+// otherwise the surface is switched back to sRGB, which is the default.
+extern void setTonemap(bool enabled);
+
+// Color space the EGL surface was created with as a setColorMode() value, -1 before it exists.
+extern int getSurfaceColorSpace();
+
+// Drawn instead of the guest output until hideSplash(), `rgba` is copied. NULL draws black.
+extern void setSplashImage(const unsigned char* rgba, int width, int height);
+
+// Cross-fades from the splash to the guest output over fade_ms.
+extern void hideSplash(int fade_ms);
+
+// Called on the render thread after each presented frame with the composed frame as RGBA,
+// `stride` in bytes. NULL stops the readback.
+extern void setFrameCallback(void (*callback)(const unsigned char* rgba, int width, int height, int stride));
+
+// Bounding box of the pixels that changed in the last frame, in guest display pixels with
+// the origin at the top left. Returns 0 if the driver doesn't track damage.
+extern int getDirtyRegion(int* x, int* y, int* width, int* height);
+
+struct RendererCapabilities {
+    int gles_major;
+    int gles_minor;
+    int vulkan_available;
+    // VK_MAKE_VERSION encoded
+    unsigned int vulkan_api_version;
+    int max_texture_size;
+    int native_fence_sync;
+};
+
+// Fills `caps` from a throwaway EGL context, works before startOpenGLRenderer. Returns 0 on success.
+extern int probeCapabilities(struct RendererCapabilities* caps);
@@ -0,0 +1,406 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use jni::objects::{JClass, JValue};
+use jni::JNIEnv;
+use once_cell::sync::Lazy;
+
+use log::{error, info, warn};
+
+use crate::guest;
+use crate::memory;
+use crate::rootfs_extract;
+use crate::storage;
+
+const ROOTFS_PATH: &'static str = "/data/data/io.twoyi/rootfs";
+
+// the guest package manager rewrites it on every install and uninstall
+const PACKAGES_XML: &'static str = "/data/data/io.twoyi/rootfs/data/system/packages.xml";
+
+#[derive(Default)]
+struct PackageCache {
+    // mtime of packages.xml the entries were read at
+    stamp: Option<SystemTime>,
+    packages: Vec<String>,
+    labels: HashMap<String, String>,
+}
+
+static CACHE: Lazy<Mutex<PackageCache>> = Lazy::new(|| Mutex::new(PackageCache::default()));
+
+fn packages_stamp() -> Option<SystemTime> {
+    fs::metadata(PACKAGES_XML).and_then(|meta| meta.modified()).ok()
+}
+
+// Drops everything cached once the guest installed or removed a package.
+fn validate(cache: &mut PackageCache) {
+    let stamp = packages_stamp();
+    if stamp.is_none() || stamp != cache.stamp {
+        cache.packages.clear();
+        cache.labels.clear();
+        cache.stamp = stamp;
+    }
+}
+
+/// Installed guest packages sorted by name, empty if the guest isn't booted.
+pub fn list_packages() -> Vec<String> {
+    let mut cache = CACHE.lock().unwrap();
+    validate(&mut cache);
+    if !cache.packages.is_empty() {
+        return cache.packages.clone();
+    }
+
+    let output = match guest::shell("pm list packages") {
+        Some(output) if output.success => output,
+        Some(output) => {
+            error!("list guest packages failed: {}", output.stderr.trim());
+            return Vec::new();
+        }
+        None => return Vec::new(),
+    };
+
+    let mut packages: Vec<String> = output
+        .stdout
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("package:"))
+        .map(|package| package.to_string())
+        .collect();
+    packages.sort();
+
+    info!("guest packages: {}", packages.len());
+    cache.packages = packages.clone();
+    packages
+}
+
+/// Display name of `package`, the package name itself if the label can't be loaded.
+pub fn package_label(env: &mut JNIEnv, clazz: &JClass, package: &str) -> String {
+    if !storage::is_valid_package(package) {
+        error!("invalid package name: {}", package);
+        return package.to_string();
+    }
+
+    if let Some(label) = cached_label(package) {
+        return label;
+    }
+
+    match apk_path(package).and_then(|apk| load_label(env, clazz, &apk)) {
+        Some(label) => {
+            let mut cache = CACHE.lock().unwrap();
+            validate(&mut cache);
+            cache.labels.insert(package.to_string(), label.clone());
+            label
+        }
+        None => package.to_string(),
+    }
+}
+
+/// Starts the launcher activity of `package`, false if it has none, is disabled or the guest isn't ready.
+pub fn launch(package: &str) -> bool {
+    if !storage::is_valid_package(package) {
+        error!("invalid package name: {}", package);
+        return false;
+    }
+
+    if is_disabled(package) {
+        error!("launch {} failed: package is disabled", package);
+        return false;
+    }
+
+    // monkey resolves the launcher activity itself
+    let cmd = format!("monkey -p {} -c android.intent.category.LAUNCHER 1", package);
+    let output = match guest::shell(&cmd) {
+        Some(output) => output,
+        None => return false,
+    };
+
+    if output.stdout.contains("No activities found") {
+        error!("launch {} failed: no launcher activity", package);
+        return false;
+    }
+    if !output.success || !output.stdout.contains("Events injected: 1") {
+        error!("launch {} failed: {}", package, output.stderr.trim());
+        return false;
+    }
+
+    info!("launched {}", package);
+    true
+}
+
+pub const UNINSTALL_SUCCESS: i32 = 0;
+pub const UNINSTALL_FAILED: i32 = 1;
+pub const UNINSTALL_NOT_INSTALLED: i32 = 2;
+pub const UNINSTALL_SYSTEM_APP: i32 = 3;
+pub const UNINSTALL_GUEST_NOT_READY: i32 = 4;
+
+/// Removes `package` from the guest, returns one of the `UNINSTALL_*` codes.
+pub fn uninstall(package: &str) -> i32 {
+    if !storage::is_valid_package(package) {
+        error!("invalid package name: {}", package);
+        return UNINSTALL_FAILED;
+    }
+
+    let system = match guest::shell("pm list packages -s") {
+        Some(output) => has_package(&output.stdout, package),
+        None => return UNINSTALL_GUEST_NOT_READY,
+    };
+    if system {
+        error!("uninstall {} failed: system app", package);
+        return UNINSTALL_SYSTEM_APP;
+    }
+    if !list_packages().iter().any(|p| p == package) {
+        error!("uninstall {} failed: not installed", package);
+        return UNINSTALL_NOT_INSTALLED;
+    }
+
+    let output = match guest::shell(&format!("pm uninstall {}", package)) {
+        Some(output) => output,
+        None => return UNINSTALL_GUEST_NOT_READY,
+    };
+    // pm prints "Success" or "Failure [REASON]"
+    if output.stdout.trim() == "Success" {
+        info!("uninstalled {}", package);
+        UNINSTALL_SUCCESS
+    } else {
+        error!("uninstall {} failed: {} {}", package, output.stdout.trim(), output.stderr.trim());
+        UNINSTALL_FAILED
+    }
+}
+
+const APP_DATA_PATH: &'static str = "/data/data/io.twoyi/rootfs/data/data";
+// where pm points the `lib` link of a data directory, resolved in the guest
+const GUEST_APP_CODE_PATH: &'static str = "/data/app/";
+
+/// Archives the data directory of `package` as a gzipped tar at `path`, the app must not run.
+pub fn backup(package: &str, path: &str) -> bool {
+    if !can_move_data(package) {
+        return false;
+    }
+    if !Path::new(APP_DATA_PATH).join(package).is_dir() {
+        error!("backup {} failed: no data directory", package);
+        return false;
+    }
+
+    let ok = run_tar(&["-czf", path, "-C", APP_DATA_PATH, package]);
+    if ok {
+        info!("backed up {} to {}", package, path);
+    }
+    ok
+}
+
+/// Replaces the data directory of `package` with the archive at `path` from `backup`.
+pub fn restore(package: &str, path: &str) -> bool {
+    if !can_move_data(package) {
+        return false;
+    }
+    if !list_packages().iter().any(|p| p == package) {
+        error!("restore {} failed: not installed", package);
+        return false;
+    }
+    if !archive_only_contains(path, package) {
+        return false;
+    }
+
+    // extracted next to the data directory and swapped in, a failed restore keeps the old data
+    let data_dir = Path::new(APP_DATA_PATH).join(package);
+    let staging = Path::new(APP_DATA_PATH).join(format!(".restore-{}", package));
+    let old = Path::new(APP_DATA_PATH).join(format!(".old-{}", package));
+    let _ = fs::remove_dir_all(&staging);
+    let _ = fs::remove_dir_all(&old);
+    if let Err(e) = fs::create_dir(&staging) {
+        error!("create {} failed: {}", staging.display(), e);
+        return false;
+    }
+    let swapped = run_tar(&["-xzf", path, "-C", &staging.to_string_lossy()])
+        && swap_in(&staging.join(package), &data_dir, &old);
+    let _ = fs::remove_dir_all(&staging);
+    if !swapped {
+        return false;
+    }
+    let _ = fs::remove_dir_all(&old);
+
+    // the archive may come from another install where the app had another uid
+    let uid = match package_uid(package) {
+        Some(uid) => uid,
+        None => {
+            warn!("restored {} from {}, uid unknown so ownership isn't fixed", package, path);
+            return true;
+        }
+    };
+    let cmd = format!("chown -R {uid}:{uid} /data/data/{pkg} && restorecon -R /data/data/{pkg}", uid = uid, pkg = package);
+    match guest::shell(&cmd) {
+        Some(output) if output.success => info!("restored {} from {}, owner {}", package, path, uid),
+        Some(output) => warn!("restored {} from {}, fixing ownership failed: {}", package, path, output.stderr.trim()),
+        None => warn!("restored {} from {}, guest not ready so ownership isn't fixed", package, path),
+    }
+    true
+}
+
+// `pm list packages -U` prints "package:com.example uid:10050"
+fn package_uid(package: &str) -> Option<u32> {
+    let output = guest::shell(&format!("pm list packages -U {}", package))?;
+    output.stdout.lines().find_map(|line| {
+        let (name, uid) = line.trim().strip_prefix("package:")?.split_once(" uid:")?;
+        if name != package {
+            return None;
+        }
+        uid.split(',').next()?.trim().parse().ok()
+    })
+}
+
+fn can_move_data(package: &str) -> bool {
+    if !storage::is_valid_package(package) {
+        error!("invalid package name: {}", package);
+        return false;
+    }
+    // a running app writes while the files are copied
+    if !memory::package_pids(package).is_empty() {
+        error!("{} is running, stop it first", package);
+        return false;
+    }
+    true
+}
+
+// Moves `new` to `dir`. The current `dir` is moved to `old` first and put back if that fails.
+fn swap_in(new: &Path, dir: &Path, old: &Path) -> bool {
+    let had_dir = dir.exists();
+    if had_dir {
+        if let Err(e) = fs::rename(dir, old) {
+            error!("move {} aside failed: {}", dir.display(), e);
+            return false;
+        }
+    }
+    if let Err(e) = fs::rename(new, dir) {
+        error!("move {} to {} failed: {}", new.display(), dir.display(), e);
+        if had_dir {
+            let _ = fs::rename(old, dir);
+        }
+        return false;
+    }
+    true
+}
+
+// Everything in the archive has to be below `package/` and nothing may escape the data
+// directory: no `..`, no symlink pointing out of it and nothing extracted through a symlink.
+fn archive_only_contains(path: &str, package: &str) -> bool {
+    let output = match Command::new("tar").args(["-tvzf", path]).output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            error!("list {} failed: {}", path, String::from_utf8_lossy(&output.stderr).trim());
+            return false;
+        }
+        Err(e) => {
+            error!("run tar failed: {}", e);
+            return false;
+        }
+    };
+
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let entries: Vec<(&str, Option<&str>)> = listing.lines().filter_map(rootfs_extract::parse_entry).collect();
+    let symlinks: HashSet<&str> = entries.iter().filter(|(_, target)| target.is_some()).map(|(name, _)| *name).collect();
+
+    let prefix = format!("{}/", package);
+    for (entry, target) in &entries {
+        let inside = (*entry == package || entry.starts_with(&prefix)) && !entry.split('/').any(|part| part == "..");
+        let mut through_link = false;
+        let mut parent = *entry;
+        while let Some((dir, _)) = parent.rsplit_once('/') {
+            through_link |= symlinks.contains(dir);
+            parent = dir;
+        }
+        let escapes = target.is_some_and(|target| !link_stays_inside(entry, target, package));
+        if !inside || through_link || escapes {
+            error!("{} isn't a backup of {}: {}", path, package, entry);
+            return false;
+        }
+    }
+    true
+}
+
+// The target of the link `entry` resolved without touching the disk has to stay below
+// `package`. Of absolute targets only the `lib` link into the guest app code is allowed.
+fn link_stays_inside(entry: &str, target: &str, package: &str) -> bool {
+    if target.starts_with('/') {
+        return *entry == format!("{}/lib", package) && target.starts_with(GUEST_APP_CODE_PATH) && !target.contains("..");
+    }
+    let mut parts: Vec<&str> = entry.split('/').collect();
+    parts.pop();
+    for part in target.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                if parts.pop().is_none() {
+                    return false;
+                }
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.first() == Some(&package)
+}
+
+fn run_tar(args: &[&str]) -> bool {
+    match Command::new("tar").args(args).output() {
+        Ok(output) if output.status.success() => true,
+        Ok(output) => {
+            error!("tar {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr).trim());
+            false
+        }
+        Err(e) => {
+            error!("run tar failed: {}", e);
+            false
+        }
+    }
+}
+
+fn has_package(list: &str, package: &str) -> bool {
+    list.lines().any(|line| line.trim().strip_prefix("package:") == Some(package))
+}
+
+fn is_disabled(package: &str) -> bool {
+    match guest::shell("pm list packages -d") {
+        Some(output) => has_package(&output.stdout, package),
+        None => false,
+    }
+}
+
+fn cached_label(package: &str) -> Option<String> {
+    let mut cache = CACHE.lock().unwrap();
+    validate(&mut cache);
+    cache.labels.get(package).cloned()
+}
+
+// The base apk of `package` as seen from the host.
+fn apk_path(package: &str) -> Option<PathBuf> {
+    let output = guest::shell(&format!("pm path {}", package))?;
+    let path = output.stdout.lines().find_map(|line| line.trim().strip_prefix("package:"))?;
+    Some(PathBuf::from(ROOTFS_PATH).join(path.trim_start_matches('/')))
+}
+
+// Only the host package manager can parse an apk, ask `Renderer.loadApkLabel`.
+fn load_label(env: &mut JNIEnv, clazz: &JClass, apk: &Path) -> Option<String> {
+    let jpath = env.new_string(apk.to_string_lossy()).ok()?;
+    let ret = env.call_static_method(
+        clazz,
+        "loadApkLabel",
+        "(Ljava/lang/String;)Ljava/lang/String;",
+        &[JValue::Object(&jpath)],
+    );
+
+    let label = match ret.and_then(|v| v.l()) {
+        Ok(label) => label,
+        Err(e) => {
+            error!("load label of {} failed: {:?}", apk.display(), e);
+            let _ = env.exception_clear();
+            return None;
+        }
+    };
+    // null when the host couldn't parse the apk
+    unsafe { crate::jstring_to_string(env, label.into_raw()) }
+}
@@ -0,0 +1,280 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use log::{error, info, warn};
+
+use crate::{config, guest, json, storage};
+
+struct WakeLock {
+    level: String,
+    tag: String,
+    uid: String,
+    pid: Option<i32>,
+}
+
+// packages we denied WAKE_LOCK, one per line. The guest keeps app ops across boots,
+// so a deny the host app didn't undo before it died is undone on the next boot.
+const DENIED_PATH: &'static str = "/data/data/io.twoyi/wakelock_denied";
+const BOOT_TIMEOUT: Duration = Duration::from_secs(120);
+
+static DENIED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| {
+    let denied = fs::read_to_string(DENIED_PATH).unwrap_or_default();
+    Mutex::new(denied.lines().map(|line| line.to_string()).collect())
+});
+
+/// Wakelocks held in the guest as a JSON array, empty if the guest isn't ready.
+pub fn wake_locks_json() -> String {
+    let locks: Vec<String> = wake_locks()
+        .iter()
+        .map(|lock| {
+            json::to_json(&[
+                ("level", lock.level.clone()),
+                ("tag", lock.tag.clone()),
+                ("uid", lock.uid.clone()),
+                ("package", lock.pid.and_then(process_package).unwrap_or_default()),
+            ])
+        })
+        .collect();
+    format!("[{}]", locks.join(","))
+}
+
+/// Denies the apps holding the wakelock `tag` the WAKE_LOCK op until the host app comes
+/// back to the foreground or the guest boots again. False if nobody holds it or it
+/// belongs to the system.
+pub fn release(tag: &str) -> bool {
+    let packages: HashSet<String> = wake_locks()
+        .iter()
+        .filter(|lock| lock.tag == tag)
+        .filter_map(|lock| lock.pid.and_then(process_package))
+        .collect();
+    if packages.is_empty() {
+        error!("release wakelock {} failed: no app holds it", tag);
+        return false;
+    }
+
+    packages.iter().all(|package| {
+        let released = deny(package);
+        if released {
+            info!("released wakelock {} of {}", tag, package);
+        }
+        released
+    })
+}
+
+// The guest power manager checks WAKE_LOCK on acquire only, a lock already held goes with
+// its process. `am kill` only kills processes that are safe to kill, a foreground app keeps it.
+fn deny(package: &str) -> bool {
+    // recorded first, a deny must never be left behind unrecorded
+    let mut denied = DENIED.lock().unwrap();
+    if denied.insert(package.to_string()) {
+        save_denied(&denied);
+    }
+    drop(denied);
+
+    if !set_wake_lock_allowed(package, false) {
+        return false;
+    }
+    match guest::shell(&format!("am kill {}", package)) {
+        Some(output) if output.success => (),
+        Some(output) => warn!("kill background processes of {} failed: {}", package, output.stderr.trim()),
+        None => (),
+    }
+    true
+}
+
+fn allow_denied() {
+    let mut denied = DENIED.lock().unwrap();
+    if denied.is_empty() {
+        return;
+    }
+    denied.retain(|package| {
+        let allowed = set_wake_lock_allowed(package, true);
+        if allowed {
+            info!("wakelocks of {} allowed again", package);
+        }
+        !allowed
+    });
+    save_denied(&denied);
+}
+
+fn save_denied(denied: &HashSet<String>) {
+    let lines: Vec<&str> = denied.iter().map(|package| package.as_str()).collect();
+    if let Err(e) = fs::write(DENIED_PATH, lines.join("\n")) {
+        error!("write {} failed: {}", DENIED_PATH, e);
+    }
+}
+
+enum Task {
+    Background,
+    Foreground,
+    KeepAwake(bool),
+    GuestBooted,
+}
+
+// host transitions go through one thread so they are applied in the order they happened
+static WORKER: Lazy<Mutex<Sender<Task>>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for task in receiver {
+            match task {
+                Task::Background => host_background(),
+                Task::Foreground => host_foreground(),
+                Task::KeepAwake(enabled) => keep_awake(enabled),
+                Task::GuestBooted => allow_denied(),
+            }
+        }
+    });
+    Mutex::new(sender)
+});
+
+fn submit(task: Task) {
+    let _ = WORKER.lock().unwrap().send(task);
+}
+
+static KEEP_AWAKE: Lazy<AtomicBool> = Lazy::new(|| {
+    AtomicBool::new(config::get_or(config::CONTAINER_CONF, "keep_guest_awake", false))
+});
+static HOST_FOREGROUND: AtomicBool = AtomicBool::new(true);
+
+/// Keeps the guest screen on while the host app is in the foreground, it may
+/// sleep again once the host goes to the background.
+pub fn set_keep_awake(enabled: bool) {
+    submit(Task::KeepAwake(enabled));
+}
+
+fn keep_awake(enabled: bool) {
+    KEEP_AWAKE.store(enabled, Ordering::Release);
+    config::set(config::CONTAINER_CONF, "keep_guest_awake", &enabled.to_string());
+    apply_stay_on(enabled && HOST_FOREGROUND.load(Ordering::Acquire));
+}
+
+fn apply_stay_on(on: bool) {
+    // the guest is always "plugged in", so stay on while plugged means always on
+    match guest::shell(&format!("svc power stayon {}", on)) {
+        Some(output) if output.success => info!("guest stay awake: {}", on),
+        Some(output) => error!("set guest stay awake {} failed: {}", on, output.stderr.trim()),
+        None => (),
+    }
+}
+
+/// Called when the host app goes to the background, lets the guest sleep and releases
+/// every app wakelock if `release_wakelocks_in_background` is set. Doesn't block.
+pub fn on_host_background() {
+    submit(Task::Background);
+}
+
+/// Gives the apps denied their wakelocks back and keeps the guest awake again. Doesn't block.
+pub fn on_host_foreground() {
+    submit(Task::Foreground);
+}
+
+/// Called when the guest starts, denies left over from an earlier run are undone once it booted.
+pub fn on_guest_start() {
+    if DENIED.lock().unwrap().is_empty() {
+        return;
+    }
+    thread::spawn(|| {
+        if guest::wait_for_boot(BOOT_TIMEOUT) {
+            submit(Task::GuestBooted);
+        }
+    });
+}
+
+fn host_background() {
+    HOST_FOREGROUND.store(false, Ordering::Release);
+    if KEEP_AWAKE.load(Ordering::Acquire) {
+        apply_stay_on(false);
+    }
+    if !config::get_or(config::CONTAINER_CONF, "release_wakelocks_in_background", false) {
+        return;
+    }
+
+    let packages: HashSet<String> = wake_locks().iter().filter_map(|lock| lock.pid.and_then(process_package)).collect();
+    for package in packages {
+        if deny(&package) {
+            info!("released wakelocks of {} in background", package);
+        }
+    }
+}
+
+fn host_foreground() {
+    HOST_FOREGROUND.store(true, Ordering::Release);
+    if KEEP_AWAKE.load(Ordering::Acquire) {
+        apply_stay_on(true);
+    }
+    allow_denied();
+}
+
+fn set_wake_lock_allowed(package: &str, allowed: bool) -> bool {
+    let mode = if allowed { "allow" } else { "ignore" };
+    match guest::shell(&format!("cmd appops set {} WAKE_LOCK {}", package, mode)) {
+        Some(output) if output.success => true,
+        Some(output) => {
+            error!("set WAKE_LOCK of {} to {} failed: {}", package, mode, output.stderr.trim());
+            false
+        }
+        None => false,
+    }
+}
+
+// Parses the "Wake Locks: size=N" section of `dumpsys power`, lines look like
+//   PARTIAL_WAKE_LOCK 'tag' ACQ=-1m3s (uid=10050 pid=5040)
+fn wake_locks() -> Vec<WakeLock> {
+    let output = match guest::shell("dumpsys power") {
+        Some(output) if output.success => output.stdout,
+        Some(output) => {
+            warn!("dumpsys power failed: {}", output.stderr.trim());
+            return Vec::new();
+        }
+        None => return Vec::new(),
+    };
+
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("Wake Locks:"))
+        .skip(1)
+        .take_while(|line| !line.trim().is_empty())
+        .filter_map(parse_wake_lock)
+        .collect()
+}
+
+fn parse_wake_lock(line: &str) -> Option<WakeLock> {
+    let line = line.trim();
+    let level = line.split_whitespace().next()?;
+    let start = line.find('\'')?;
+    let end = line.rfind('\'').filter(|end| *end > start)?;
+    let owner = &line[line.rfind('(')? + 1..];
+
+    let field = |name: &str| {
+        owner
+            .split(|c: char| c == ' ' || c == ')')
+            .find_map(|part| part.strip_prefix(name))
+            .map(|value| value.to_string())
+    };
+    Some(WakeLock {
+        level: level.to_string(),
+        tag: line[start + 1..end].to_string(),
+        uid: field("uid=")?,
+        pid: field("pid=").and_then(|pid| pid.parse().ok()),
+    })
+}
+
+// guest pids are host pids, the process name is the package or `package:service`
+fn process_package(pid: i32) -> Option<String> {
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let name = cmdline.split(|b| *b == 0).next()?;
+    let name = std::str::from_utf8(name).ok()?;
+    let package = name.split(':').next()?;
+    storage::is_valid_package(package).then(|| package.to_string())
+}
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use log::{error, info, warn};
+
+use crate::{guest, storage};
+
+const ANR_TRACES: &'static str = "/data/data/io.twoyi/rootfs/data/anr/traces.txt";
+
+// traces older than this belong to an ANR that is over
+const RECENT_ANR: Duration = Duration::from_secs(5 * 60);
+
+// a window manager that can't dump itself in time is deadlocked
+const WINDOW_DUMP_TIMEOUT_SECS: u32 = 5;
+
+// exit status of toybox timeout when the command didn't finish
+const TIMED_OUT: &'static str = "124";
+
+pub const RECOVER_NONE: i32 = 0;
+pub const RECOVER_FORCE_STOPPED: i32 = 1;
+pub const RECOVER_RESTARTED_SYSTEM_SERVER: i32 = 2;
+pub const RECOVER_FAILED: i32 = 3;
+
+/// Unfreezes the guest UI: force-stops an app showing the ANR dialog, or restarts
+/// the guest system_server if the window manager doesn't respond. Returns the
+/// `RECOVER_*` action taken, `RECOVER_NONE` if no ANR was found.
+pub fn recover() -> i32 {
+    log_recent_anr();
+
+    let cmd = format!(
+        "timeout {} dumpsys window windows; echo status=$?",
+        WINDOW_DUMP_TIMEOUT_SECS
+    );
+    let output = match guest::shell(&cmd) {
+        Some(output) => output.stdout,
+        None => return RECOVER_FAILED,
+    };
+
+    if output.lines().last().and_then(|line| line.trim().strip_prefix("status=")) == Some(TIMED_OUT) {
+        warn!("guest window manager unresponsive, restarting system_server");
+        return if restart_system_server() { RECOVER_RESTARTED_SYSTEM_SERVER } else { RECOVER_FAILED };
+    }
+
+    // mCurrentFocus=Window{a1b2c3 u0 Application Not Responding: com.example}
+    let package = output
+        .lines()
+        .filter(|line| line.contains("mCurrentFocus="))
+        .find_map(|line| line.split("Application Not Responding: ").nth(1))
+        .map(|rest| rest.trim_end_matches('}').trim().to_string());
+    match package {
+        Some(package) if storage::is_valid_package(&package) => {
+            warn!("guest app {} not responding, force-stopping it", package);
+            match guest::shell(&format!("am force-stop {}", package)) {
+                Some(output) if output.success => {
+                    info!("recovered guest: force-stopped {}", package);
+                    RECOVER_FORCE_STOPPED
+                }
+                _ => {
+                    error!("force-stop {} failed", package);
+                    RECOVER_FAILED
+                }
+            }
+        }
+        _ => {
+            info!("no guest ANR found, nothing to recover");
+            RECOVER_NONE
+        }
+    }
+}
+
+fn restart_system_server() -> bool {
+    // init restarts zygote and with it system_server
+    match guest::shell("kill -9 $(pidof system_server)") {
+        Some(output) if output.success => {
+            info!("recovered guest: restarted system_server");
+            true
+        }
+        Some(output) => {
+            error!("restart guest system_server failed: {}", output.stderr.trim());
+            false
+        }
+        None => false,
+    }
+}
+
+// The header of the last traces names the process, e.g. "----- pid 1234 at ... -----"
+// followed by "Cmd line: com.example".
+fn log_recent_anr() {
+    let recent = fs::metadata(ANR_TRACES)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < RECENT_ANR);
+    if !recent {
+        return;
+    }
+
+    if let Ok(traces) = fs::read_to_string(ANR_TRACES) {
+        let header: Vec<&str> = traces
+            .lines()
+            .filter(|line| line.starts_with("----- pid") || line.starts_with("Cmd line:"))
+            .take(2)
+            .collect();
+        warn!("recent guest ANR: {}", header.join(" "));
+    }
+}
@@ -0,0 +1,62 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use jni::objects::JValue;
+use once_cell::sync::Lazy;
+
+use log::error;
+
+use crate::callback;
+
+// the same error again within this window is only counted, not delivered
+const DEBOUNCE_WINDOW: Duration = Duration::from_secs(5);
+
+struct LastError {
+    code: i32,
+    message: String,
+    at: Instant,
+    suppressed: u32,
+}
+
+static LAST_ERROR: Lazy<Mutex<Option<LastError>>> = Lazy::new(|| Mutex::new(None));
+
+/// Called by the renderer on its own thread for context loss, swap and driver errors.
+pub extern "C" fn on_render_error(code: c_int, message: *const c_char) {
+    let message = if message.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned()
+    };
+    error!("render error {}: {}", code, message);
+
+    {
+        let mut last = LAST_ERROR.lock().unwrap();
+        if let Some(ref mut last) = *last {
+            if last.code == code && last.message == message && last.at.elapsed() < DEBOUNCE_WINDOW {
+                last.suppressed += 1;
+                return;
+            }
+            if last.suppressed > 0 {
+                error!("render error {} repeated {} more times", last.code, last.suppressed);
+            }
+        }
+        *last = Some(LastError { code, message: message.clone(), at: Instant::now(), suppressed: 0 });
+    }
+
+    callback::with_env(|env| {
+        if let Ok(jmessage) = env.new_string(&message) {
+            callback::call_static_in(
+                env,
+                "onRenderError",
+                "(ILjava/lang/String;)V",
+                &[JValue::Int(code), JValue::Object(&jmessage)],
+            );
+        }
+    });
+}
@@ -1,5 +1,21 @@
 /* automatically generated by rust-bindgen 0.59.2 */
 
+use std::os::raw::{c_char, c_void};
+
+use log::warn;
+use once_cell::sync::OnceCell;
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RendererCapabilities {
+    pub gles_major: ::std::os::raw::c_int,
+    pub gles_minor: ::std::os::raw::c_int,
+    pub vulkan_available: ::std::os::raw::c_int,
+    pub vulkan_api_version: ::std::os::raw::c_uint,
+    pub max_texture_size: ::std::os::raw::c_int,
+    pub native_fence_sync: ::std::os::raw::c_int,
+}
+
 #[allow(dead_code)]
 #[link(name="OpenglRender")]
 extern "C" {
@@ -32,3 +48,104 @@ extern "C" {
 
     pub fn removeSubWindow(arg1: *mut ::std::os::raw::c_void) -> ::std::os::raw::c_int;
 }
+
+// Entry points newer than the prebuilt libOpenglRender.so. Linking them directly keeps
+// libtwoyi.so from loading against that renderer, so they're looked up on first call and
+// a renderer without one gets the fallback value instead.
+macro_rules! optional_renderer_fns {
+    ($(pub fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)? = $fallback:expr;)*) => {
+        $(
+            #[allow(non_snake_case, dead_code)]
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                static SYMBOL: OnceCell<usize> = OnceCell::new();
+                let addr = *SYMBOL.get_or_init(|| lookup(concat!(stringify!($name), "\0")));
+                if addr == 0 {
+                    return $fallback;
+                }
+                let f: unsafe extern "C" fn($($ty),*) $(-> $ret)? = std::mem::transmute(addr);
+                f($($arg),*)
+            }
+        )*
+    };
+}
+
+fn lookup(name: &'static str) -> usize {
+    static RENDERER: OnceCell<usize> = OnceCell::new();
+    // libOpenglRender.so is already loaded as our dependency, this only gets its handle
+    let handle = *RENDERER.get_or_init(|| unsafe {
+        libc::dlopen(b"libOpenglRender.so\0".as_ptr() as *const c_char, libc::RTLD_NOW) as usize
+    });
+    if handle == 0 {
+        return 0;
+    }
+    let addr = unsafe { libc::dlsym(handle as *mut c_void, name.as_ptr() as *const c_char) } as usize;
+    if addr == 0 {
+        warn!("renderer has no {}, falling back", name.trim_end_matches('\0'));
+    }
+    addr
+}
+
+optional_renderer_fns! {
+    pub fn pauseRendering() = ();
+
+    pub fn resumeRendering() = ();
+
+    pub fn getGlStrings(
+        vendor: *mut *const ::std::os::raw::c_char,
+        renderer: *mut *const ::std::os::raw::c_char,
+        version: *mut *const ::std::os::raw::c_char,
+        extensions: *mut *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int = -1;
+
+    pub fn setRenderScale(scale: f32) = ();
+
+    pub fn forceRedraw() = ();
+
+    pub fn setErrorCallback(
+        callback: extern "C" fn(code: ::std::os::raw::c_int, message: *const ::std::os::raw::c_char),
+    ) = ();
+
+    pub fn setIdleMode(enabled: bool) = ();
+
+    pub fn setIdleParams(timeout_ms: ::std::os::raw::c_int, idle_fps: ::std::os::raw::c_int) = ();
+
+    pub fn wakeRenderer() = ();
+
+    pub fn setContextSharing(enabled: bool) = ();
+
+    pub fn setCompositorThreads(threads: ::std::os::raw::c_int) = ();
+
+    pub fn setFrameCallback(
+        callback: ::std::option::Option<
+            extern "C" fn(
+                rgba: *const ::std::os::raw::c_uchar,
+                width: ::std::os::raw::c_int,
+                height: ::std::os::raw::c_int,
+                stride: ::std::os::raw::c_int,
+            ),
+        >,
+    ) = ();
+
+    pub fn getDirtyRegion(
+        x: *mut ::std::os::raw::c_int,
+        y: *mut ::std::os::raw::c_int,
+        width: *mut ::std::os::raw::c_int,
+        height: *mut ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int = 0;
+
+    pub fn probeCapabilities(caps: *mut RendererCapabilities) -> ::std::os::raw::c_int = -1;
+
+    pub fn setColorMode(mode: ::std::os::raw::c_int) -> ::std::os::raw::c_int = 0;
+
+    pub fn setTonemap(enabled: bool) = ();
+
+    pub fn getSurfaceColorSpace() -> ::std::os::raw::c_int = -1;
+
+    pub fn setSplashImage(
+        rgba: *const ::std::os::raw::c_uchar,
+        width: ::std::os::raw::c_int,
+        height: ::std::os::raw::c_int,
+    ) = ();
+
+    pub fn hideSplash(fade_ms: ::std::os::raw::c_int) = ();
+}
@@ -0,0 +1,217 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use jni::objects::JValue;
+use log::{error, info, warn};
+
+use crate::callback;
+
+// written once everything is extracted, with the size and mtime of the archive
+const DONE_MARKER: &'static str = ".twoyi-extracted";
+// present while extracting, left behind if the app died halfway
+const PARTIAL_MARKER: &'static str = ".twoyi-extracting";
+
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Extracts a tar or tar.gz rootfs into `dest` with the host tar, reporting progress to
+/// `Renderer.onExtractProgress`. The 7z rootfs shipped with the app is extracted by RomManager.
+///
+/// The archive is listed first, which checks its integrity and that nothing escapes `dest`.
+/// There is no real resume: an extraction cut short is redone from the start over what it left.
+pub fn extract(archive: &str, dest: &str) -> bool {
+    let gzip = if archive.ends_with(".tar.gz") || archive.ends_with(".tgz") {
+        true
+    } else if archive.ends_with(".tar") {
+        false
+    } else {
+        error!("{} isn't a tar archive", archive);
+        return false;
+    };
+
+    let meta = match fs::metadata(archive) {
+        Ok(meta) if meta.is_file() => meta,
+        _ => {
+            error!("rootfs archive {} doesn't exist", archive);
+            return false;
+        }
+    };
+    let total = meta.len();
+    let stamp = format!("{}:{}", total, meta.mtime());
+
+    let dest = Path::new(dest);
+    if !dest.is_absolute() || fs::create_dir_all(dest).is_err() {
+        error!("invalid rootfs destination {}", dest.display());
+        return false;
+    }
+    let done = dest.join(DONE_MARKER);
+    let partial = dest.join(PARTIAL_MARKER);
+    if fs::read_to_string(&done).is_ok_and(|s| s == stamp) {
+        info!("{} already extracted to {}", archive, dest.display());
+        report(100, total);
+        return true;
+    }
+    if partial.exists() {
+        warn!("extraction of {} into {} was cut short, extracting it again", archive, dest.display());
+    }
+
+    if !verify(archive, gzip) {
+        return false;
+    }
+    let _ = fs::remove_file(&done);
+    if let Err(e) = File::create(&partial) {
+        error!("create {} failed: {}", partial.display(), e);
+        return false;
+    }
+
+    info!("extracting {} ({} bytes) to {}", archive, total, dest.display());
+    if !untar(archive, dest, gzip, total) {
+        return false;
+    }
+    if let Err(e) = fs::write(&done, &stamp) {
+        error!("write {} failed: {}", done.display(), e);
+        return false;
+    }
+    let _ = fs::remove_file(&partial);
+    info!("extracted {} to {}", archive, dest.display());
+    true
+}
+
+// Lists the whole archive, which reads every block, and refuses entries that leave the
+// destination, either by their path or by being extracted through a symlink of the archive.
+fn verify(archive: &str, gzip: bool) -> bool {
+    let flags = if gzip { "-tvzf" } else { "-tvf" };
+    let output = match Command::new("tar").args([flags, archive]).output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            error!("{} is corrupt: {}", archive, String::from_utf8_lossy(&output.stderr).trim());
+            return false;
+        }
+        Err(e) => {
+            error!("run tar failed: {}", e);
+            return false;
+        }
+    };
+
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let entries: Vec<(&str, Option<&str>)> = listing.lines().filter_map(parse_entry).collect();
+    let symlinks: HashSet<&str> = entries.iter().filter(|(_, target)| target.is_some()).map(|(name, _)| *name).collect();
+
+    for (name, _) in &entries {
+        if name.starts_with('/') || name.split('/').any(|part| part == "..") {
+            error!("{} has an entry outside the rootfs: {}", archive, name);
+            return false;
+        }
+        // a symlink may point anywhere, nothing may be written through one
+        let mut parent = *name;
+        while let Some((dir, _)) = parent.rsplit_once('/') {
+            if symlinks.contains(dir) {
+                error!("{} writes {} through the symlink {}", archive, name, dir);
+                return false;
+            }
+            parent = dir;
+        }
+    }
+    true
+}
+
+// `lrwxrwxrwx root/root 0 2021-10-20 12:00 ./etc -> /system/etc` of `tar -tv` to
+// ("etc", Some("/system/etc")), the target is `None` for anything but a symlink.
+pub(crate) fn parse_entry(line: &str) -> Option<(&str, Option<&str>)> {
+    let mut rest = line.trim_start();
+    for _ in 0..5 {
+        let field_end = rest.find(char::is_whitespace)?;
+        rest = rest[field_end..].trim_start();
+    }
+    let (name, target) = match rest.split_once(" -> ") {
+        Some((name, target)) if line.starts_with('l') => (name, Some(target)),
+        _ => (rest, None),
+    };
+    let name = name.trim_start_matches("./").trim_end_matches('/');
+    (!name.is_empty() && name != ".").then_some((name, target))
+}
+
+// Feeds the archive to tar through a pipe, so the bytes handed over are the progress.
+fn untar(archive: &str, dest: &Path, gzip: bool, total: u64) -> bool {
+    let mut input = match File::open(archive) {
+        Ok(input) => input,
+        Err(e) => {
+            error!("open {} failed: {}", archive, e);
+            return false;
+        }
+    };
+    let flags = if gzip { "-xzf" } else { "-xf" };
+    let mut child = match Command::new("tar")
+        .args([flags, "-", "-C"])
+        .arg(dest)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            error!("run tar failed: {}", e);
+            return false;
+        }
+    };
+
+    // tar warns about every device node it can't create, unread its stderr fills and blocks it
+    let mut stderr = child.stderr.take().unwrap();
+    let errors = thread::spawn(move || {
+        let mut errors = String::new();
+        let _ = stderr.read_to_string(&mut errors);
+        errors
+    });
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut written = 0u64;
+    let mut last_percent = -1;
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                error!("read {} failed: {}", archive, e);
+                break;
+            }
+        };
+        // tar quit early, its exit status tells why
+        if stdin.write_all(&buf[..n]).is_err() {
+            break;
+        }
+        written += n as u64;
+        let percent = (written * 100 / total.max(1)) as i32;
+        if percent != last_percent {
+            last_percent = percent;
+            report(percent, written);
+        }
+    }
+    drop(stdin);
+
+    let status = child.wait();
+    let errors = errors.join().unwrap_or_default();
+    match status {
+        Ok(status) if status.success() && written == total => true,
+        Ok(_) => {
+            error!("extract {} failed: {}", archive, errors.trim());
+            false
+        }
+        Err(e) => {
+            error!("wait for tar failed: {}", e);
+            false
+        }
+    }
+}
+
+fn report(percent: i32, bytes: u64) {
+    callback::call_static("onExtractProgress", "(IJ)V", &[JValue::Int(percent), JValue::Long(bytes as i64)]);
+}
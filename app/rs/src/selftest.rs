@@ -0,0 +1,131 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+
+use log::{info, warn};
+use unix_socket::UnixListener;
+
+use crate::{container, input, json, storage};
+
+const ROOTFS_PATH: &'static str = "/data/data/io.twoyi/rootfs";
+
+// a rootfs missing one of these never finishes booting
+const KEY_GUEST_FILES: [&'static str; 4] = [
+    "system/build.prop",
+    "system/bin/sh",
+    "system/framework/framework.jar",
+    "vendor/default.prop",
+];
+
+// the guest data partition grows on first boot, below this it fails in odd ways
+const MIN_FREE_STORAGE: i64 = 512 * 1024 * 1024;
+
+struct Check {
+    name: &'static str,
+    result: Result<String, String>,
+}
+
+/// Runs the pre-flight checks and returns a JSON report:
+/// `{"passed":bool,"checks":[{"name":..,"passed":bool,"detail":..}]}`.
+pub fn run() -> String {
+    let checks = [
+        Check { name: "input_sockets", result: check_input_sockets() },
+        Check { name: "init", result: check_init() },
+        Check { name: "guest_files", result: check_guest_files() },
+        Check { name: "free_storage", result: check_free_storage() },
+    ];
+
+    let entries: Vec<String> = checks
+        .iter()
+        .map(|check| {
+            let (passed, detail) = match &check.result {
+                Ok(detail) => (true, detail),
+                Err(detail) => {
+                    warn!("self test {} failed: {}", check.name, detail);
+                    (false, detail)
+                }
+            };
+            format!(
+                "{{\"name\":\"{}\",\"passed\":{},\"detail\":\"{}\"}}",
+                check.name,
+                passed,
+                json::escape(detail)
+            )
+        })
+        .collect();
+
+    let passed = checks.iter().all(|check| check.result.is_ok());
+    info!("self test passed: {}", passed);
+    format!("{{\"passed\":{},\"checks\":[{}]}}", passed, entries.join(","))
+}
+
+// The guest reads its input devices from sockets the input servers bind in the rootfs.
+fn check_input_sockets() -> Result<String, String> {
+    let sockets = [input::TOUCH_PATH, input::KEY_PATH];
+    let dir = Path::new(input::TOUCH_PATH).parent().unwrap();
+    if !dir.is_dir() {
+        return Err(format!("{} is missing", dir.display()));
+    }
+
+    let mut bound = 0;
+    for socket in sockets {
+        match fs::symlink_metadata(socket) {
+            Ok(meta) if meta.file_type().is_socket() => bound += 1,
+            Ok(_) => return Err(format!("{} isn't a socket", socket)),
+            Err(_) => (),
+        }
+    }
+    if bound == sockets.len() {
+        return Ok("touch and key sockets are bound".to_string());
+    }
+
+    // the servers aren't up yet, they have to be able to bind there
+    let probe = dir.join(".selftest");
+    let _ = fs::remove_file(&probe);
+    match UnixListener::bind(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Ok(format!("sockets can be bound in {}", dir.display()))
+        }
+        Err(e) => Err(format!("bind in {} failed: {}", dir.display(), e)),
+    }
+}
+
+fn check_init() -> Result<String, String> {
+    let init = Path::new(ROOTFS_PATH).join(container::init_binary().trim_start_matches("./"));
+    let meta = fs::metadata(&init).map_err(|e| format!("{}: {}", init.display(), e))?;
+    if !meta.is_file() {
+        return Err(format!("{} is not a file", init.display()));
+    }
+    if meta.permissions().mode() & 0o111 == 0 {
+        return Err(format!("{} is not executable", init.display()));
+    }
+    Ok(format!("{} is executable", init.display()))
+}
+
+fn check_guest_files() -> Result<String, String> {
+    let missing: Vec<&str> = KEY_GUEST_FILES
+        .iter()
+        .filter(|file| !Path::new(ROOTFS_PATH).join(file).is_file())
+        .copied()
+        .collect();
+    if missing.is_empty() {
+        Ok(format!("{} files present", KEY_GUEST_FILES.len()))
+    } else {
+        Err(format!("missing: {}", missing.join(", ")))
+    }
+}
+
+fn check_free_storage() -> Result<String, String> {
+    let free = storage::guest_storage_info()[1];
+    let detail = format!("{} MiB free", free / 1024 / 1024);
+    if free >= MIN_FREE_STORAGE {
+        Ok(detail)
+    } else {
+        Err(format!("{}, need {} MiB", detail, MIN_FREE_STORAGE / 1024 / 1024))
+    }
+}
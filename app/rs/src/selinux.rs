@@ -0,0 +1,46 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs;
+
+
+use crate::guest;
+
+const LOG_PATH: &'static str = "/data/data/io.twoyi/log.txt";
+
+// enough to see what a failing app ran into without flooding the UI
+const MAX_DENIALS: usize = 50;
+
+/// "Enforcing", "Permissive" or "Disabled" as the guest reports it, empty if the guest isn't ready.
+///
+/// The guest runs on the host kernel and sees its selinuxfs, this is the host mode. Only the
+/// host root can change it, so there is no setter.
+pub fn mode() -> String {
+    match guest::shell("getenforce") {
+        Some(output) if output.success => output.stdout.trim().to_string(),
+        _ => String::new(),
+    }
+}
+
+/// The most recent `avc: denied` records, oldest first. Only the ones guest userspace logs
+/// (property and service manager checks) are in the guest logcat: kernel denials go to the
+/// host audit log, which the guest logd doesn't read and the app can't. Before the guest
+/// runs only the guest init output in log.txt is searched.
+pub fn recent_denials() -> Vec<String> {
+    let log = match guest::shell("logcat -d -b all") {
+        Some(output) if output.success => output.stdout,
+        _ => String::from_utf8_lossy(&fs::read(LOG_PATH).unwrap_or_default()).into_owned(),
+    };
+
+    let denials: Vec<String> = log
+        .lines()
+        .filter_map(|line| {
+            // the same record reads "avc:  denied" in the kernel log
+            let start = line.find("avc: denied").or_else(|| line.find("avc:  denied"))?;
+            Some(line[start..].trim_end().to_string())
+        })
+        .collect();
+    let skip = denials.len().saturating_sub(MAX_DENIALS);
+    denials.into_iter().skip(skip).collect()
+}
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use jni::objects::{JIntArray, JValue};
+use log::{error, info, warn};
+
+use crate::callback;
+use crate::config;
+use crate::guest;
+use crate::renderer_bindings;
+
+const FADE_MS: i32 = 300;
+// a guest that didn't boot by then gets its output shown anyway
+const BOOT_TIMEOUT: Duration = Duration::from_secs(120);
+
+// set while the renderer draws the splash instead of the guest output
+static SHOWING: AtomicBool = AtomicBool::new(false);
+
+/// Sets the image shown while the guest boots, empty for none. Replaces the one on
+/// screen if the guest is still booting.
+pub fn set_boot_splash(path: &str) {
+    config::set(config::RENDERER_CONF, "boot_splash", path);
+    info!("boot splash: {}", if path.is_empty() { "none" } else { path });
+    if SHOWING.load(Ordering::Acquire) {
+        apply(path);
+    }
+}
+
+/// Shows the configured splash until the guest booted, called once the renderer started.
+/// The renderer also starts again for a new surface, the splash stays off if the guest is up.
+pub fn show() {
+    let path: String = config::get(config::RENDERER_CONF, "boot_splash").unwrap_or_default();
+    if path.is_empty() || SHOWING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    let spawned = thread::Builder::new().name("twoyi-splash".to_string()).spawn(move || {
+        if booted() {
+            SHOWING.store(false, Ordering::Release);
+            return;
+        }
+        apply(&path);
+        if !guest::wait_for_boot(BOOT_TIMEOUT) {
+            warn!("guest didn't boot, hiding the boot splash");
+        }
+        SHOWING.store(false, Ordering::Release);
+        unsafe { renderer_bindings::hideSplash(FADE_MS) };
+    });
+    if let Err(e) = spawned {
+        error!("spawn splash thread failed: {}", e);
+        SHOWING.store(false, Ordering::Release);
+    }
+}
+
+// an unreachable guest hasn't booted yet
+fn booted() -> bool {
+    guest::shell("getprop sys.boot_completed").is_some_and(|output| output.stdout.trim() == "1")
+}
+
+fn apply(path: &str) {
+    match load(path) {
+        Some((rgba, width, height)) => unsafe {
+            renderer_bindings::setSplashImage(rgba.as_ptr(), width, height);
+        },
+        None => {
+            error!("load boot splash {} failed, showing black", path);
+            unsafe { renderer_bindings::setSplashImage(std::ptr::null(), 0, 0) };
+        }
+    }
+}
+
+// Only the host can decode images: `Renderer.decodeSplash` returns [width, height, argb...].
+fn load(path: &str) -> Option<(Vec<u8>, i32, i32)> {
+    let mut image = None;
+    callback::with_env(|env| {
+        let jpath = match env.new_string(path) {
+            Ok(jpath) => jpath,
+            Err(_) => return,
+        };
+        let value = callback::call_static_value(env, "decodeSplash", "(Ljava/lang/String;)[I", &[JValue::Object(&jpath)]);
+        let array = match value.and_then(|v| v.l().ok()) {
+            Some(array) if !array.is_null() => JIntArray::from(array),
+            _ => return,
+        };
+
+        let len = env.get_array_length(&array).unwrap_or(0).max(0) as usize;
+        let mut pixels = vec![0; len];
+        if len < 2 || env.get_int_array_region(&array, 0, &mut pixels).is_err() {
+            return;
+        }
+        let (width, height) = (pixels[0], pixels[1]);
+        if width <= 0 || height <= 0 || width as usize * height as usize != len - 2 {
+            return;
+        }
+        let rgba = pixels[2..]
+            .iter()
+            .flat_map(|argb| {
+                let [a, r, g, b] = (*argb as u32).to_be_bytes();
+                [r, g, b, a]
+            })
+            .collect();
+        image = Some((rgba, width, height));
+    });
+    image
+}
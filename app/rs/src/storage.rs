@@ -0,0 +1,211 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use log::{error, info};
+
+const ROOTFS_PATH: &'static str = "/data/data/io.twoyi/rootfs";
+const GUEST_DATA_PATH: &'static str = "/data/data/io.twoyi/rootfs/data";
+
+// files bigger than this have to be shared through a folder, not a JNI array
+pub const MAX_GUEST_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+const MAX_SYMLINKS: usize = 40;
+
+#[derive(Debug)]
+pub enum FileError {
+    InvalidPath,
+    NotFound,
+    PermissionDenied,
+    TooLarge,
+    Io(io::Error),
+}
+
+impl From<io::Error> for FileError {
+    fn from(e: io::Error) -> FileError {
+        match e.kind() {
+            io::ErrorKind::NotFound => FileError::NotFound,
+            io::ErrorKind::PermissionDenied => FileError::PermissionDenied,
+            _ => FileError::Io(e),
+        }
+    }
+}
+
+// per-package data lives in several places, the sdcard one is the biggest usually.
+const APP_DATA_DIRS: [&'static str; 3] = [
+    "/data/data/io.twoyi/rootfs/data/data",
+    "/data/data/io.twoyi/rootfs/data/user_de/0",
+    "/data/data/io.twoyi/rootfs/sdcard/Android/data",
+];
+
+/// Returns `[used, free, total]` in bytes, `used` is what the guest data partition takes.
+pub fn guest_storage_info() -> [i64; 3] {
+    let (free, total) = match statvfs(ROOTFS_PATH) {
+        Some(x) => x,
+        None => (0, 0),
+    };
+
+    let used = dir_size(Path::new(GUEST_DATA_PATH));
+
+    info!("guest storage used: {}, free: {}, total: {}", used, free, total);
+    [used as i64, free as i64, total as i64]
+}
+
+/// Returns the bytes used by the data of `package`, or -1 if the name is invalid.
+pub fn guest_app_data_size(package: &str) -> i64 {
+    if !is_valid_package(package) {
+        error!("invalid package name: {}", package);
+        return -1;
+    }
+
+    let mut seen = HashSet::new();
+    let mut size = 0;
+    for dir in APP_DATA_DIRS.iter() {
+        let path = Path::new(dir).join(package);
+        if let Ok(meta) = fs::symlink_metadata(&path) {
+            size += walk(&path, meta.dev(), &mut seen);
+        }
+    }
+    size as i64
+}
+
+fn statvfs(path: &str) -> Option<(u64, u64)> {
+    let cpath = std::ffi::CString::new(path).ok()?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(cpath.as_ptr(), &mut st) } != 0 {
+        error!("statvfs {} failed: {}", path, std::io::Error::last_os_error());
+        return None;
+    }
+
+    let frsize = st.f_frsize as u64;
+    Some((st.f_bavail as u64 * frsize, st.f_blocks as u64 * frsize))
+}
+
+fn dir_size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(meta) => walk(path, meta.dev(), &mut HashSet::new()),
+        Err(_) => 0,
+    }
+}
+
+// Sums allocated bytes below `path`. Symlinks are never followed, every inode is
+// counted once, and directories on another device (bind mounts) are skipped.
+fn walk(path: &Path, dev: u64, seen: &mut HashSet<(u64, u64)>) -> u64 {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(_) => return 0,
+    };
+
+    if meta.dev() != dev || !seen.insert((meta.dev(), meta.ino())) {
+        return 0;
+    }
+
+    let mut size = meta.blocks() * 512;
+    if meta.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() {
+                size += walk(&entry.path(), dev, seen);
+            }
+        }
+    }
+    size
+}
+
+pub fn is_valid_package(package: &str) -> bool {
+    !package.is_empty()
+        && package != "."
+        && package != ".."
+        && package.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_')
+}
+
+/// Reads the guest file at the absolute guest `path`, up to `MAX_GUEST_FILE_SIZE`.
+pub fn read_guest_file(path: &str) -> Result<Vec<u8>, FileError> {
+    let ret = guest_path(path).ok_or(FileError::InvalidPath).and_then(|host| {
+        let meta = fs::metadata(&host)?;
+        if !meta.is_file() {
+            return Err(FileError::InvalidPath);
+        }
+        if meta.len() > MAX_GUEST_FILE_SIZE {
+            return Err(FileError::TooLarge);
+        }
+
+        // the size may change between stat and read
+        let mut data = Vec::new();
+        File::open(&host)?.take(MAX_GUEST_FILE_SIZE + 1).read_to_end(&mut data)?;
+        if data.len() as u64 > MAX_GUEST_FILE_SIZE {
+            return Err(FileError::TooLarge);
+        }
+        Ok(data)
+    });
+
+    if let Err(ref e) = ret {
+        error!("read guest file {} failed: {:?}", path, e);
+    }
+    ret
+}
+
+/// Replaces the guest file at the absolute guest `path` with `data`.
+pub fn write_guest_file(path: &str, data: &[u8]) -> Result<(), FileError> {
+    let ret = if data.len() as u64 > MAX_GUEST_FILE_SIZE {
+        Err(FileError::TooLarge)
+    } else {
+        guest_path(path)
+            .ok_or(FileError::InvalidPath)
+            .and_then(|host| fs::write(host, data).map_err(FileError::from))
+    };
+
+    match ret {
+        Ok(_) => info!("wrote guest file {}, {} bytes", path, data.len()),
+        Err(ref e) => error!("write guest file {} failed: {:?}", path, e),
+    }
+    ret
+}
+
+// Maps an absolute guest path into the rootfs. Symlinks are resolved here rather
+// than by the host, so absolute guest links can never point at host files.
+pub fn guest_path(path: &str) -> Option<PathBuf> {
+    if !path.starts_with('/') {
+        return None;
+    }
+
+    let root = Path::new(ROOTFS_PATH);
+    let mut pending: Vec<String> = path.split('/').filter(|p| !p.is_empty()).rev().map(String::from).collect();
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+
+    while let Some(part) = pending.pop() {
+        match part.as_str() {
+            "." => continue,
+            ".." => {
+                resolved.pop();
+                continue;
+            }
+            _ => (),
+        }
+
+        let host = root.join(&resolved).join(&part);
+        match fs::symlink_metadata(&host) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return None;
+                }
+                let target = fs::read_link(&host).ok()?;
+                let target = target.to_str()?;
+                if target.starts_with('/') {
+                    resolved = PathBuf::new();
+                }
+                pending.extend(target.split('/').filter(|p| !p.is_empty()).rev().map(String::from));
+            }
+            _ => resolved.push(part),
+        }
+    }
+    Some(root.join(resolved))
+}
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{error, info};
+
+use crate::guest;
+
+// a switch or creation takes seconds in the guest, a second one meanwhile is refused
+static BUSY: AtomicBool = AtomicBool::new(false);
+
+struct Busy;
+
+impl Busy {
+    fn acquire() -> Option<Busy> {
+        if BUSY.swap(true, Ordering::AcqRel) {
+            error!("a guest user operation is already in progress");
+            return None;
+        }
+        Some(Busy)
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        BUSY.store(false, Ordering::Release);
+    }
+}
+
+/// Guest users as `id:name`, empty if the guest isn't ready.
+pub fn list_users() -> Vec<String> {
+    let output = match guest::shell("pm list users") {
+        Some(output) if output.success => output.stdout,
+        Some(output) => {
+            error!("list guest users failed: {}", output.stderr.trim());
+            return Vec::new();
+        }
+        None => return Vec::new(),
+    };
+
+    // UserInfo{0:Owner:13} running
+    output
+        .lines()
+        .filter_map(|line| {
+            let info = line.trim().strip_prefix("UserInfo{")?;
+            let info = &info[..info.find('}')?];
+            let (id, rest) = info.split_once(':')?;
+            let name = rest.rsplit_once(':').map_or(rest, |(name, _flags)| name);
+            Some(format!("{}:{}", id, name))
+        })
+        .collect()
+}
+
+pub fn switch_user(id: i32) -> bool {
+    if id < 0 {
+        error!("invalid guest user: {}", id);
+        return false;
+    }
+    let _busy = match Busy::acquire() {
+        Some(busy) => busy,
+        None => return false,
+    };
+
+    match guest::shell(&format!("am switch-user {}", id)) {
+        Some(output) if output.success && output.stderr.trim().is_empty() => {
+            info!("switched to guest user {}", id);
+            true
+        }
+        Some(output) => {
+            error!("switch to guest user {} failed: {}", id, output.stderr.trim());
+            false
+        }
+        None => false,
+    }
+}
+
+/// Creates a guest user named `name`, returns its id or -1.
+pub fn create_user(name: &str) -> i32 {
+    if name.trim().is_empty() || name.contains(|c: char| c.is_control()) {
+        error!("invalid guest user name: {:?}", name);
+        return -1;
+    }
+    let _busy = match Busy::acquire() {
+        Some(busy) => busy,
+        None => return -1,
+    };
+
+    let output = match guest::shell(&format!("pm create-user {}", guest::shell_quote(name))) {
+        Some(output) => output,
+        None => return -1,
+    };
+    // Success: created user id 10
+    let id = output
+        .stdout
+        .trim()
+        .strip_prefix("Success: created user id ")
+        .and_then(|id| id.trim().parse().ok());
+    match id {
+        Some(id) => {
+            info!("created guest user {}: {}", id, name);
+            id
+        }
+        None => {
+            error!("create guest user {} failed: {} {}", name, output.stdout.trim(), output.stderr.trim());
+            -1
+        }
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+package io.twoyi;
+
+import android.content.Context;
+import android.graphics.ImageFormat;
+import android.hardware.camera2.CameraAccessException;
+import android.hardware.camera2.CameraCaptureSession;
+import android.hardware.camera2.CameraCharacteristics;
+import android.hardware.camera2.CameraDevice;
+import android.hardware.camera2.CameraManager;
+import android.hardware.camera2.CaptureRequest;
+import android.hardware.camera2.params.StreamConfigurationMap;
+import android.media.Image;
+import android.media.ImageReader;
+import android.os.Handler;
+import android.os.HandlerThread;
+import android.util.Log;
+import android.util.Size;
+
+import androidx.annotation.NonNull;
+
+import java.nio.ByteBuffer;
+import java.util.Collections;
+
+/**
+ * Feeds the frames of a host camera to the guest camera HAL with {@link Renderer#pushFrameToGuest}.
+ * Everything runs on its own thread, the caller checks the CAMERA permission.
+ */
+class CameraForwarder {
+
+    private static final String TAG = "CameraForwarder";
+
+    private final CameraManager mCameraManager;
+    private final HandlerThread mThread;
+    private final Handler mHandler;
+
+    private String mCameraId;
+    private CameraDevice mCamera;
+    private CameraCaptureSession mSession;
+    private ImageReader mReader;
+    private byte[] mRgba;
+
+    CameraForwarder(Context context) {
+        mCameraManager = context.getSystemService(CameraManager.class);
+        mThread = new HandlerThread("camera-forward");
+        mThread.start();
+        mHandler = new Handler(mThread.getLooper());
+    }
+
+    /**
+     * Opens camera {@code cameraId} at its supported size closest to width x height,
+     * a camera forwarded before is closed.
+     */
+    void start(int cameraId, int width, int height) {
+        mHandler.post(() -> open(String.valueOf(cameraId), width, height));
+    }
+
+    void stop(int cameraId) {
+        mHandler.post(() -> {
+            if (String.valueOf(cameraId).equals(mCameraId)) {
+                close();
+            }
+        });
+    }
+
+    void release() {
+        mHandler.post(() -> {
+            close();
+            mThread.quitSafely();
+        });
+    }
+
+    private void open(String cameraId, int width, int height) {
+        close();
+
+        Size size = closestSize(cameraId, width, height);
+        if (size == null) {
+            return;
+        }
+
+        ImageReader reader = ImageReader.newInstance(size.getWidth(), size.getHeight(), ImageFormat.YUV_420_888, 2);
+        reader.setOnImageAvailableListener(this::onImageAvailable, mHandler);
+        mReader = reader;
+        mCameraId = cameraId;
+
+        try {
+            mCameraManager.openCamera(cameraId, new CameraDevice.StateCallback() {
+                @Override
+                public void onOpened(@NonNull CameraDevice camera) {
+                    // stopped or restarted while opening
+                    if (reader != mReader) {
+                        camera.close();
+                        return;
+                    }
+                    mCamera = camera;
+                    startSession(camera, reader);
+                }
+
+                @Override
+                public void onDisconnected(@NonNull CameraDevice camera) {
+                    camera.close();
+                    if (camera == mCamera) {
+                        close();
+                    }
+                }
+
+                @Override
+                public void onError(@NonNull CameraDevice camera, int error) {
+                    Log.e(TAG, "camera " + cameraId + " error: " + error);
+                    onDisconnected(camera);
+                }
+            }, mHandler);
+        } catch (CameraAccessException | SecurityException e) {
+            Log.e(TAG, "open camera " + cameraId + " failed", e);
+            close();
+            return;
+        }
+        Log.i(TAG, "forwarding camera " + cameraId + " at " + size);
+    }
+
+    private void startSession(CameraDevice camera, ImageReader reader) {
+        try {
+            camera.createCaptureSession(Collections.singletonList(reader.getSurface()), new CameraCaptureSession.StateCallback() {
+                @Override
+                public void onConfigured(@NonNull CameraCaptureSession session) {
+                    if (reader != mReader) {
+                        session.close();
+                        return;
+                    }
+                    mSession = session;
+                    try {
+                        CaptureRequest.Builder request = camera.createCaptureRequest(CameraDevice.TEMPLATE_PREVIEW);
+                        request.addTarget(reader.getSurface());
+                        session.setRepeatingRequest(request.build(), null, mHandler);
+                    } catch (CameraAccessException | IllegalStateException e) {
+                        Log.e(TAG, "start capture failed", e);
+                        close();
+                    }
+                }
+
+                @Override
+                public void onConfigureFailed(@NonNull CameraCaptureSession session) {
+                    Log.e(TAG, "configure capture session failed");
+                    if (reader == mReader) {
+                        close();
+                    }
+                }
+            }, mHandler);
+        } catch (CameraAccessException | IllegalStateException e) {
+            Log.e(TAG, "create capture session failed", e);
+            close();
+        }
+    }
+
+    private Size closestSize(String cameraId, int width, int height) {
+        StreamConfigurationMap map;
+        try {
+            CameraCharacteristics characteristics = mCameraManager.getCameraCharacteristics(cameraId);
+            map = characteristics.get(CameraCharacteristics.SCALER_STREAM_CONFIGURATION_MAP);
+        } catch (CameraAccessException | IllegalArgumentException e) {
+            Log.e(TAG, "no camera " + cameraId, e);
+            return null;
+        }
+        Size[] sizes = map == null ? null : map.getOutputSizes(ImageFormat.YUV_420_888);
+        if (sizes == null || sizes.length == 0) {
+            Log.e(TAG, "camera " + cameraId + " has no YUV output");
+            return null;
+        }
+
+        Size best = sizes[0];
+        for (Size size : sizes) {
+            int distance = Math.abs(size.getWidth() - width) + Math.abs(size.getHeight() - height);
+            if (distance < Math.abs(best.getWidth() - width) + Math.abs(best.getHeight() - height)) {
+                best = size;
+            }
+        }
+        return best;
+    }
+
+    private void onImageAvailable(ImageReader reader) {
+        Image image = reader.acquireLatestImage();
+        if (image == null) {
+            return;
+        }
+        try {
+            Renderer.pushFrameToGuest(toRgba(image), image.getWidth(), image.getHeight());
+        } finally {
+            image.close();
+        }
+    }
+
+    // BT.601 limited range, the native side converts back to NV21 with the same coefficients.
+    private byte[] toRgba(Image image) {
+        int width = image.getWidth();
+        int height = image.getHeight();
+        if (mRgba == null || mRgba.length != width * height * 4) {
+            mRgba = new byte[width * height * 4];
+        }
+
+        Image.Plane[] planes = image.getPlanes();
+        ByteBuffer yPlane = planes[0].getBuffer();
+        ByteBuffer uPlane = planes[1].getBuffer();
+        ByteBuffer vPlane = planes[2].getBuffer();
+        int yRowStride = planes[0].getRowStride();
+        int uvRowStride = planes[1].getRowStride();
+        int uvPixelStride = planes[1].getPixelStride();
+
+        for (int row = 0; row < height; row++) {
+            for (int col = 0; col < width; col++) {
+                int y = (yPlane.get(row * yRowStride + col) & 0xff) - 16;
+                int uv = (row / 2) * uvRowStride + (col / 2) * uvPixelStride;
+                int u = (uPlane.get(uv) & 0xff) - 128;
+                int v = (vPlane.get(uv) & 0xff) - 128;
+
+                int c = 298 * y + 128;
+                int o = (row * width + col) * 4;
+                mRgba[o] = clamp((c + 409 * v) >> 8);
+                mRgba[o + 1] = clamp((c - 100 * u - 208 * v) >> 8);
+                mRgba[o + 2] = clamp((c + 516 * u) >> 8);
+                mRgba[o + 3] = (byte) 0xff;
+            }
+        }
+        return mRgba;
+    }
+
+    private static byte clamp(int value) {
+        return (byte) Math.max(0, Math.min(255, value));
+    }
+
+    private void close() {
+        if (mSession != null) {
+            mSession.close();
+            mSession = null;
+        }
+        if (mCamera != null) {
+            mCamera.close();
+            mCamera = null;
+        }
+        if (mReader != null) {
+            mReader.close();
+            mReader = null;
+        }
+        mCameraId = null;
+    }
+}
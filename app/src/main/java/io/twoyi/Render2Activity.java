@@ -18,12 +18,18 @@
 
 package io.twoyi;
 
+import android.Manifest;
 import android.app.Activity;
+import android.content.pm.PackageInfo;
+import android.content.pm.PackageManager;
 import android.os.Bundle;
 import android.os.SystemClock;
+import android.os.VibrationEffect;
+import android.os.Vibrator;
 import android.util.DisplayMetrics;
 import android.util.Log;
 import android.view.Display;
+import android.view.InputDevice;
 import android.view.KeyEvent;
 import android.view.MotionEvent;
 import android.view.Surface;
@@ -51,10 +57,12 @@ import io.twoyi.utils.RomManager;
  * @author weishu
  * @date 2021/10/20.
  */
-public class Render2Activity extends Activity implements View.OnTouchListener {
+public class Render2Activity extends Activity implements View.OnTouchListener, Renderer.Callback {
 
     private static final String TAG = "Render2Activity";
 
+    private static final int REQUEST_CAMERA = 1;
+
     private SurfaceView mSurfaceView;
 
     private ViewGroup mRootView;
@@ -65,6 +73,10 @@ public class Render2Activity extends Activity implements View.OnTouchListener {
 
     private final AtomicBoolean mIsExtracting = new AtomicBoolean(false);
 
+    private CameraForwarder mCameraForwarder;
+    // {cameraId, width, height} of a forward waiting for the CAMERA permission
+    private volatile int[] mPendingCameraForward;
+
     private final SurfaceHolder.Callback mSurfaceCallback = new SurfaceHolder.Callback() {
         @Override
         public void surfaceCreated(@NonNull SurfaceHolder holder) {
@@ -132,7 +144,27 @@ public class Render2Activity extends Activity implements View.OnTouchListener {
         UITips.checkForAndroid12(this, this::bootSystem);
 
         mSurfaceView.setOnTouchListener(this);
+        // hover and scroll of a mouse don't come as touches
+        mSurfaceView.setOnGenericMotionListener((v, event) -> {
+            if (!event.isFromSource(InputDevice.SOURCE_MOUSE)) {
+                return false;
+            }
+            Renderer.handleTouch(event);
+            return true;
+        });
+
+        mCameraForwarder = new CameraForwarder(this);
 
+        Renderer.setCallback(this);
+    }
+
+    @Override
+    protected void onDestroy() {
+        super.onDestroy();
+        Renderer.setCallback(null);
+        if (mCameraForwarder != null) {
+            mCameraForwarder.release();
+        }
     }
 
     @Override
@@ -231,6 +263,24 @@ public class Render2Activity extends Activity implements View.OnTouchListener {
         }, "waiting-boot").start();
     }
 
+    @Override
+    protected void onResume() {
+        super.onResume();
+        Renderer.resume();
+    }
+
+    @Override
+    protected void onPause() {
+        super.onPause();
+        Renderer.pause();
+    }
+
+    @Override
+    public void onTrimMemory(int level) {
+        super.onTrimMemory(level);
+        Renderer.onTrimMemory(level);
+    }
+
     @Override
     public void onWindowFocusChanged(boolean hasFocus) {
         super.onWindowFocusChanged(hasFocus);
@@ -264,6 +314,105 @@ public class Render2Activity extends Activity implements View.OnTouchListener {
         Renderer.sendKeycode(KeyEvent.KEYCODE_HOME);
     }
 
+    @Override
+    public void onGuestVibrate(long durationMs) {
+        Vibrator vibrator = getSystemService(Vibrator.class);
+        if (vibrator == null) {
+            return;
+        }
+        if (durationMs <= 0) {
+            vibrator.cancel();
+        } else {
+            vibrator.vibrate(VibrationEffect.createOneShot(durationMs, VibrationEffect.DEFAULT_AMPLITUDE));
+        }
+    }
+
+    @Override
+    public void onGuestVibrateWaveform(long[] timings, int[] amplitudes) {
+        Vibrator vibrator = getSystemService(Vibrator.class);
+        if (vibrator == null) {
+            return;
+        }
+        vibrator.vibrate(VibrationEffect.createWaveform(timings, amplitudes, -1));
+    }
+
+    @Override
+    public void onRenderError(int code, String message) {
+        Log.e(TAG, "render error " + code + ": " + message);
+        runOnUiThread(() -> Toast.makeText(getApplicationContext(),
+                getString(R.string.render_error, code, message), Toast.LENGTH_LONG).show());
+    }
+
+    @Override
+    public void onGuestStarted(int status) {
+        Log.i(TAG, "guest start status: " + status);
+        if (status == Renderer.START_STARTED_WITHOUT_LOG) {
+            Log.w(TAG, "guest log.txt couldn't be created, its output is lost");
+        } else if (status == Renderer.START_FAILED) {
+            runOnUiThread(() -> Toast.makeText(getApplicationContext(), R.string.guest_start_failed, Toast.LENGTH_LONG).show());
+        }
+    }
+
+    @Override
+    public void onGuestLowMemory(int level) {
+        Log.w(TAG, "guest low memory, level: " + level);
+    }
+
+    @Override
+    public void onStartCameraForward(int cameraId, int width, int height) {
+        if (checkSelfPermission(Manifest.permission.CAMERA) == PackageManager.PERMISSION_GRANTED) {
+            mCameraForwarder.start(cameraId, width, height);
+            return;
+        }
+        mPendingCameraForward = new int[]{cameraId, width, height};
+        runOnUiThread(() -> requestPermissions(new String[]{Manifest.permission.CAMERA}, REQUEST_CAMERA));
+    }
+
+    @Override
+    public void onStopCameraForward(int cameraId) {
+        int[] pending = mPendingCameraForward;
+        if (pending != null && pending[0] == cameraId) {
+            mPendingCameraForward = null;
+        }
+        mCameraForwarder.stop(cameraId);
+    }
+
+    @Override
+    public void onRequestPermissionsResult(int requestCode, @NonNull String[] permissions, @NonNull int[] grantResults) {
+        super.onRequestPermissionsResult(requestCode, permissions, grantResults);
+        if (requestCode != REQUEST_CAMERA) {
+            return;
+        }
+        int[] pending = mPendingCameraForward;
+        mPendingCameraForward = null;
+        if (pending == null) {
+            return;
+        }
+        if (grantResults.length > 0 && grantResults[0] == PackageManager.PERMISSION_GRANTED) {
+            mCameraForwarder.start(pending[0], pending[1], pending[2]);
+        } else {
+            Log.w(TAG, "camera permission denied, guest camera stays dark");
+        }
+    }
+
+    @Override
+    public void onHostHome() {
+        runOnUiThread(() -> moveTaskToBack(true));
+    }
+
+    @Override
+    public String loadApkLabel(String apkPath) {
+        PackageManager pm = getPackageManager();
+        PackageInfo info = pm.getPackageArchiveInfo(apkPath, 0);
+        if (info == null || info.applicationInfo == null) {
+            return null;
+        }
+        // resources are only loaded from the source dir
+        info.applicationInfo.sourceDir = apkPath;
+        info.applicationInfo.publicSourceDir = apkPath;
+        return info.applicationInfo.loadLabel(pm).toString();
+    }
+
     private float getBestFps() {
         WindowManager windowManager = getWindowManager();
         Display defaultDisplay = windowManager.getDefaultDisplay();
@@ -18,6 +18,9 @@
 
 package io.twoyi;
 
+import android.content.res.Resources;
+import android.graphics.Bitmap;
+import android.graphics.BitmapFactory;
 import android.view.MotionEvent;
 import android.view.Surface;
 
@@ -31,13 +34,789 @@ public class Renderer {
         System.loadLibrary("twoyi");
     }
 
+    public static final int GESTURE_THREE_FINGER_SWIPE_UP = 1;
+    public static final int GESTURE_THREE_FINGER_SWIPE_DOWN = 1 << 1;
+
+    /**
+     * Events the native side reports back, called on native threads.
+     */
+    public interface Callback {
+        default void onGuestVibrate(long durationMs) {
+        }
+
+        /**
+         * Amplitudes as {@link android.os.VibrationEffect#createWaveform(long[], int[], int)} takes them:
+         * 0 is off and {@link android.os.VibrationEffect#DEFAULT_AMPLITUDE} the default.
+         */
+        default void onGuestVibrateWaveform(long[] timings, int[] amplitudes) {
+        }
+
+        default void onRenderError(int code, String message) {
+        }
+
+        default void onGuestLowMemory(int level) {
+        }
+
+        default void onHostGesture(int gesture) {
+        }
+
+        /**
+         * Open host camera {@code cameraId} at the supported size closest to width x height and
+         * feed its frames to {@link #pushFrameToGuest}, only if the CAMERA permission is granted.
+         */
+        default void onStartCameraForward(int cameraId, int width, int height) {
+        }
+
+        default void onStopCameraForward(int cameraId) {
+        }
+
+        /**
+         * @return the label of the apk at {@code apkPath}, or null; called from a background thread.
+         */
+        default String loadApkLabel(String apkPath) {
+            return null;
+        }
+
+        default void onDiagnosticsExported(String path, boolean success) {
+        }
+
+        default void onHostHome() {
+        }
+
+        /**
+         * Called from a background thread with the RECOVER_* action {@link #recoverGuest} took.
+         */
+        default void onGuestRecovered(int action) {
+        }
+
+        /**
+         * Called from a background thread with one of the START_* codes once the guest init
+         * started by {@link #init} or {@link #rebootGuest} was spawned or not.
+         */
+        default void onGuestStarted(int status) {
+        }
+
+        /**
+         * Called while {@link #extractRootfs} runs, whenever another percent of the archive is read.
+         */
+        default void onExtractProgress(int percent, long bytes) {
+        }
+    }
+
+    private static volatile Callback sCallback;
+
+    public static void setCallback(Callback callback) {
+        sCallback = callback;
+    }
+
+    public static final int START_STARTED = 0;
+    /** The guest runs, but log.txt couldn't be created so its output is lost. */
+    public static final int START_STARTED_WITHOUT_LOG = 1;
+    public static final int START_ALREADY_RUNNING = 2;
+    public static final int START_FAILED = 3;
+
     public static native void init(Surface surface, String loader, float xdpi, float ydpi, int fps);
 
     public static native void resetWindow(Surface surface, int top, int left, int width, int height);
 
     public static native void removeWindow(Surface surface);
 
+    public static native void pause();
+
+    public static native void resume();
+
+    public static native void invalidateGuestDisplay();
+
+    /**
+     * @param scale internal render resolution relative to the surface, in (0.25, 1.0]; other values are ignored.
+     */
+    public static native void setRenderScale(float scale);
+
     public static native void handleTouch(MotionEvent event);
 
     public static native void sendKeycode(int keycode);
+
+    /**
+     * Stops forwarding input to the guest while host UI covers it, fingers still down are released.
+     */
+    public static native void setInputEnabled(boolean enabled);
+
+    public static native String getInputQueueStats();
+
+    /**
+     * Replays the batched samples of a touchscreen MOVE, on by default.
+     */
+    public static native void setHistoricalTouchEnabled(boolean enabled);
+
+    /**
+     * Touches are mapped to x * xScale + xOffset, y * yScale + yOffset, the profile is kept across restarts.
+     */
+    public static native void setTouchCalibration(float xScale, float xOffset, float yScale, float yOffset);
+
+    /**
+     * @param gestures GESTURE_* flags the host handles itself, they are reported by {@link Callback#onHostGesture}.
+     */
+    public static native void setReservedGesture(int gestures);
+
+    /**
+     * @return [used, free, total] of the guest storage in bytes.
+     */
+    public static native long[] getGuestStorageInfo();
+
+    public static native long getGuestAppDataSize(String packageName);
+
+    public static native void sendBiometricResult(boolean success);
+
+    public static native String getGuestBuildInfo();
+
+    /**
+     * Takes effect on the next guest boot, call it before {@link #init} to apply it right away.
+     */
+    public static native boolean setGuestModel(String model, String manufacturer);
+
+    /**
+     * @param ms screen-off timeout of the guest, 0 means never.
+     */
+    public static native void setGuestScreenTimeout(int ms);
+
+    /**
+     * Hides the guest status and navigation bars. Blocks, call off the UI thread.
+     * @return false if the guest hasn't booted or refused the setting
+     */
+    public static native boolean setGuestImmersive(boolean immersive);
+
+    /**
+     * @return whether the guest bars are hidden, false if the guest hasn't booted. Blocks, call off the UI thread.
+     */
+    public static native boolean getGuestImmersive();
+
+    /**
+     * Stops the guest and wipes its data partition, the system image is kept.
+     */
+    public static native boolean wipeGuestData();
+
+    /**
+     * Stops the guest and wipes the whole rootfs, it is extracted again on the next boot.
+     */
+    public static native boolean wipeGuestFull();
+
+    /**
+     * @return GL vendor, renderer, version and extensions, or a string starting with "error:".
+     */
+    public static native String getGlInfo();
+
+    /**
+     * @return CPU usage of the guest per core in percent, estimated from its share of all busy
+     * time since the kernel doesn't account processes per core; empty until the first sample is taken.
+     */
+    public static native float[] getGuestCpuUsage();
+
+    /**
+     * Forwarded from {@link android.content.ComponentCallbacks2#onTrimMemory}.
+     */
+    public static native void onTrimMemory(int level);
+
+    /**
+     * Also writes native log lines as JSON to events.jsonl, logcat output is unchanged.
+     */
+    public static native void setJsonLogEnabled(boolean enabled);
+
+    public static native void setHapticsEnabled(boolean enabled);
+
+    /**
+     * Feeds an RGBA frame to the guest camera HAL, rows may be padded.
+     */
+    public static native void pushFrameToGuest(byte[] rgba, int width, int height);
+
+    public static native void startCameraForward(int cameraId);
+
+    public static native void stopCameraForward(int cameraId);
+
+    /**
+     * @return the most likely cause of the last boot failure, empty if log.txt shows nothing known
+     */
+    public static native String getLastBootError();
+
+    public static final int COMPOSITION_GPU = 0;
+    public static final int COMPOSITION_CPU = 1;
+
+    /**
+     * Switches the guest between GL and software composition, applied on the next guest boot.
+     */
+    public static native void setGuestComposition(int mode);
+
+    /**
+     * Fixes the guest density in dpi, within [120, 640], instead of following the host screen; 0 resets.
+     */
+    public static native void setGuestDensity(int dpi);
+
+    /**
+     * @return installed guest packages, empty if the guest hasn't booted. Blocks, call off the UI thread.
+     */
+    public static native String[] listGuestPackages();
+
+    /**
+     * @return the display name of a guest package, the package name if it can't be loaded.
+     */
+    public static native String getPackageLabel(String packageName);
+
+    /**
+     * Opens the launcher activity of a guest app. Blocks, call off the UI thread.
+     */
+    public static native boolean launchGuestApp(String packageName);
+
+    public static final int UNINSTALL_SUCCESS = 0;
+    public static final int UNINSTALL_FAILED = 1;
+    public static final int UNINSTALL_NOT_INSTALLED = 2;
+    public static final int UNINSTALL_SYSTEM_APP = 3;
+    public static final int UNINSTALL_GUEST_NOT_READY = 4;
+
+    /**
+     * Removes a guest app, the reason of a failure is in the native log. Blocks, call off the UI thread.
+     *
+     * @return one of the UNINSTALL_* codes
+     */
+    public static native int uninstallGuestApp(String packageName);
+
+    /**
+     * Reads a file by its absolute path in the guest, up to 4 MiB.
+     *
+     * @return the content, or null if it doesn't exist, is too big or isn't a regular file
+     * @throws SecurityException if the file can't be accessed
+     */
+    public static native byte[] readGuestFile(String path);
+
+    /**
+     * Replaces a file by its absolute path in the guest, with up to 4 MiB.
+     *
+     * @throws SecurityException if the file can't be accessed
+     */
+    public static native boolean writeGuestFile(String path, byte[] data);
+
+    /**
+     * Lowers the frame rate while the guest screen is static, input restores it immediately.
+     */
+    public static native void setIdleMode(boolean enabled);
+
+    /**
+     * Creates the guest input devices without the renderer, {@link #init} does it as well.
+     */
+    public static native void inputInit(int width, int height);
+
+    public static native void inputShutdown();
+
+    /**
+     * Reads a setting of the guest, namespace is one of "system", "secure" or "global".
+     * Blocks, call off the UI thread.
+     *
+     * @return the value, or null if it isn't set or the guest isn't booted
+     */
+    public static native String getGuestSetting(String namespace, String key);
+
+    public static native boolean setGuestSetting(String namespace, String key, String value);
+
+    /**
+     * Sets the guest media volume, 0..100. Blocks, call off the UI thread.
+     * @return false if the guest hasn't booted or the volume couldn't be set
+     */
+    public static native boolean setGuestVolume(int percent);
+
+    /**
+     * @return the guest media volume 0..100, or -1 if the guest isn't booted. Blocks, call off the UI thread.
+     */
+    public static native int getGuestVolume();
+
+    /**
+     * Forwards a host key, with {@link android.view.KeyEvent#getScanCode} for keys the
+     * key code table doesn't cover.
+     */
+    public static native void sendKeyEventWithScancode(int keycode, int scancode, boolean down);
+
+    /**
+     * Zips logs, guest info and stats to {@code path} in the background,
+     * {@link Callback#onDiagnosticsExported} reports the result.
+     */
+    public static native boolean exportDiagnostics(String path);
+
+    /**
+     * Keeps a running guest app from being picked by the low memory killers, false if it isn't running.
+     */
+    public static native boolean setGuestAppResident(String packageName, boolean resident);
+
+    /**
+     * @return the refresh rate the guest display runs at, or -1 if the guest isn't booted. Blocks, call off the UI thread.
+     */
+    public static native float getGuestRefreshRate();
+
+    /**
+     * Shares one GL context between all sub-windows to save GPU memory, takes effect on the next start.
+     */
+    public static native void setContextSharing(boolean enabled);
+
+    /**
+     * @return the nice value the render thread got, Integer.MIN_VALUE until the renderer started
+     */
+    public static native int getRenderThreadPriority();
+
+    public static final int MOUNT_SUCCESS = 0;
+    public static final int MOUNT_GUEST_NOT_RUNNING = 1;
+    public static final int MOUNT_INVALID_PATH = 2;
+    public static final int MOUNT_NAMESPACE_DENIED = 3;
+    public static final int MOUNT_DENIED = 4;
+    public static final int MOUNT_FAILED = 5;
+
+    /**
+     * Bind mounts a host directory into the running guest. Mounting requires root on the host,
+     * without it this returns MOUNT_NAMESPACE_DENIED or MOUNT_DENIED.
+     *
+     * @return one of the MOUNT_* codes
+     */
+    public static native int mountIntoRunningGuest(String hostPath, String guestPath);
+
+    /**
+     * @return one of the MOUNT_* codes, like {@link #mountIntoRunningGuest} it requires root
+     */
+    public static native int unmountFromRunningGuest(String guestPath);
+
+    /**
+     * Wakelocks held by guest apps as a JSON array of {level, tag, uid, package}.
+     */
+    public static native String getGuestWakelocks();
+
+    /**
+     * Denies the apps holding {@code tag} their wakelocks until the app is back in the foreground
+     * or the guest boots again, their background processes are killed to drop the held lock.
+     */
+    public static native boolean releaseGuestWakelock(String tag);
+
+    /**
+     * Toggles the guest's pointer location overlay to check the touch mapping.
+     */
+    public static native void setGuestPointerDebug(boolean enabled);
+
+    /**
+     * Composites sub-windows on up to {@code threads} threads, clamped to the cores.
+     * Ignored while GL context sharing is on.
+     */
+    public static native void setCompositorThreads(int threads);
+
+    /**
+     * Pre-flight checks of the input sockets, the rootfs and free storage, as a JSON report
+     * with the result of every check.
+     */
+    public static native String runSelfTest();
+
+    /**
+     * Sets the guest global HTTP proxy, {@code port} 0 clears it.
+     */
+    public static native boolean setGuestProxy(String host, int port);
+
+    /**
+     * Streams rendered frames at up to {@code fps} to a client of a unix socket at {@code path},
+     * frames are dropped while the client is slow. Each frame is a 24 byte little endian header
+     * (u32 magic "TWYF", u32 width, u32 height, u32 stride, u64 monotonic timestamp in us)
+     * followed by the RGBA pixels. Returns a handle for {@link #closeFramePipe}, -1 on failure.
+     */
+    public static native int openFramePipe(String path, int fps);
+
+    public static native void closeFramePipe(int handle);
+
+    /**
+     * Sets the guest hostname and its device and Bluetooth name.
+     */
+    public static native boolean setGuestHostname(String name);
+
+    public static final int HOME_FORWARD = 0;
+    public static final int HOME_HOST = 1;
+    public static final int HOME_EXIT_GUEST = 2;
+
+    /**
+     * What a guest HOME from {@link #sendKeyEventWithScancode} (a keyboard HOME key or the Meta shortcut) does:
+     * {@link #HOME_FORWARD} sends it to the guest, {@link #HOME_HOST} calls
+     * {@link Callback#onHostHome()}, {@link #HOME_EXIT_GUEST} stops the guest. Android never
+     * delivers the host's own HOME button or gesture to the app, so it isn't affected.
+     */
+    public static native void setHomeKeyBehavior(int behavior);
+
+    public static final int TRIM_LIGHT = 0;
+    public static final int TRIM_MODERATE = 1;
+    public static final int TRIM_AGGRESSIVE = 2;
+
+    /**
+     * Asks every guest app to release memory, from a running-moderate trim ({@link #TRIM_LIGHT})
+     * up to a complete one ({@link #TRIM_AGGRESSIVE}). Guest caches can't be dropped without root.
+     */
+    public static native void trimGuestMemory(int level);
+
+    public static final int RECOVER_NONE = 0;
+    public static final int RECOVER_FORCE_STOPPED = 1;
+    public static final int RECOVER_RESTARTED_SYSTEM_SERVER = 2;
+    public static final int RECOVER_FAILED = 3;
+
+    /**
+     * Force-stops a guest app that doesn't respond, or restarts the guest system_server
+     * as a last resort. Returns at once, the RECOVER_* action taken is reported to
+     * {@link Callback#onGuestRecovered}.
+     */
+    public static native void recoverGuest();
+
+    /**
+     * Scales guest text, {@code scale} has to be within 0.5..2.0.
+     */
+    public static native void setGuestFontScale(float scale);
+
+    /**
+     * {@code [x, y, width, height]} of what changed in the last frame, in guest display pixels
+     * with the origin at the top left. The whole display if the driver doesn't track damage,
+     * all zero while the renderer isn't running.
+     */
+    public static native int[] getLastDirtyRegion();
+
+    /**
+     * Archives the data of a guest app to {@code path} as a tar.gz, the app must not run.
+     */
+    public static native boolean backupGuestApp(String packageName, String path);
+
+    /**
+     * Replaces the data of an installed guest app with a backup, the app must not run.
+     */
+    public static native boolean restoreGuestApp(String packageName, String path);
+
+    /**
+     * Turns a touch held still into a right click at the touch, the hold time is
+     * {@code long_press_ms} in input.conf. Delays the start of every touch until it moves.
+     */
+    public static native void setRightClickOnLongPress(boolean enabled);
+
+    /**
+     * GLES version, Vulkan support and version, max texture size and native fence sync
+     * support as JSON, or null if no GL context could be created.
+     */
+    public static native String getRendererCapabilities();
+
+    /**
+     * Guest users as {@code "id:name"}, empty if the guest isn't ready.
+     */
+    public static native String[] listGuestUsers();
+
+    /**
+     * Fails while another user switch or creation is running.
+     */
+    public static native boolean switchGuestUser(int userId);
+
+    /**
+     * @return the id of the new guest user, -1 on failure.
+     */
+    public static native int createGuestUser(String name);
+
+    public static final int NFC_TECH_NFC_A = 1;
+    public static final int NFC_TECH_NFC_B = 2;
+    public static final int NFC_TECH_NFC_F = 3;
+    public static final int NFC_TECH_NFC_V = 4;
+    public static final int NFC_TECH_ISO_DEP = 5;
+    public static final int NFC_TECH_MIFARE_CLASSIC = 6;
+    public static final int NFC_TECH_MIFARE_ULTRALIGHT = 7;
+
+    /**
+     * Forwards a tag the host read to the guest NFC HAL, as
+     * {@code [technology][id length][id][NDEF message]} with one of the {@code NFC_TECH_*}
+     * constants. Ignored unless {@code nfc_forwarding} is set in container.conf.
+     */
+    public static native void sendNfcTag(byte[] tag);
+
+    /**
+     * Collects touch moves for up to {@code intervalMs} into one write to the guest,
+     * downs and ups are never delayed. 0 writes every event right away.
+     */
+    public static native void setInputFlushInterval(int intervalMs);
+
+    /**
+     * False if the renderer fell back to a software rasterizer or hasn't created its context yet.
+     */
+    public static native boolean isHardwareAccelerated();
+
+    /**
+     * Keeps the guest screen on while twoyi is in the foreground.
+     */
+    public static native void setKeepGuestAwake(boolean enabled);
+
+    /**
+     * Sets the guest window, transition and animator duration scales, 0 turns animations off.
+     */
+    public static native void setGuestAnimationScale(float scale);
+
+    /**
+     * Extra arguments for the guest init from the next start on, an empty array clears them.
+     * The whole array is ignored if an argument is empty, too long or has control characters.
+     */
+    public static native void setGuestInitArgs(String[] args);
+
+    public static final int COLOR_MODE_SRGB = 0;
+    public static final int COLOR_MODE_WIDE_GAMUT = 1;
+    public static final int COLOR_MODE_HDR = 2;
+
+    /**
+     * Sets the color space of the guest display, falling back to sRGB where the host doesn't
+     * support it. Screenshots, recordings and frame pipes stay RGBA8888: Display P3 pixels
+     * in wide gamut, HDR highlights clipped.
+     */
+    public static native void setGuestColorMode(int mode);
+
+    /**
+     * @return the axis maxes the guest touch device was created with, as
+     * {@code [x, y, pressure, touch major]}, null before the input system started.
+     */
+    public static native int[] getInputDeviceRanges();
+
+    public static final int REBOOT_NORMAL = 0;
+    public static final int REBOOT_RECOVERY = 1;
+    public static final int REBOOT_BOOTLOADER = 2;
+
+    /**
+     * Restarts the guest in the background, booting it into recovery or the bootloader if the guest
+     * image supports it. The start is reported to {@link Callback#onGuestStarted}.
+     */
+    public static native void rebootGuest(int mode);
+
+    /**
+     * @return the guest activities, one {@code t<task> <component>} per line with the topmost
+     * first, empty if the guest isn't ready. Cached for a second.
+     */
+    public static native String getGuestActivityStack();
+
+    /**
+     * @return the topmost line of {@link #getGuestActivityStack()}, empty if there is none.
+     */
+    public static native String getGuestTopActivity();
+
+    public static final int DPAD_UP = 0;
+    public static final int DPAD_DOWN = 1;
+    public static final int DPAD_LEFT = 2;
+    public static final int DPAD_RIGHT = 3;
+    public static final int DPAD_CENTER = 4;
+
+    /**
+     * Presses and releases one of the {@code DPAD_*} keys in the guest.
+     */
+    public static native void sendDpad(int direction);
+
+    /**
+     * Presses or releases a {@code DPAD_*} key, the guest repeats it until it is released.
+     */
+    public static native void sendDpadKey(int direction, boolean down);
+
+    /**
+     * @return paths of the logs saved when the guest init exited on its own with an error,
+     * newest first. {@code crash_logs_kept} in container.conf limits them, 5 by default.
+     */
+    public static native String[] listCrashLogs();
+
+    /**
+     * Scales guest mouse movement, within [0.1, 5]. {@code pointer_accel_exponent} in input.conf,
+     * within [1, 2], moves the pointer further on quick strokes.
+     */
+    public static native void setPointerAcceleration(float sensitivity);
+
+    /**
+     * Makes guest apps see airplane mode on or off, host connectivity is untouched. A guest
+     * that refuses the airplane mode broadcast from the shell only has the setting changed,
+     * running apps notice it the next time they read it.
+     * @return false if the guest isn't ready or the setting couldn't be written
+     */
+    public static native boolean setGuestAirplaneMode(boolean enabled);
+
+    /**
+     * Turns the guest wifi on or off, as guest apps see it.
+     * @return false if the guest isn't ready
+     */
+    public static native boolean setGuestWifiEnabled(boolean enabled);
+
+    /**
+     * Shows the image at {@code path} instead of a black screen until the guest booted,
+     * an empty path turns it off. Black is shown if the image can't be decoded.
+     */
+    public static native void setBootSplash(String path);
+
+    /**
+     * The guest shares the host kernel and its SELinux, so this is the host mode and the app
+     * can't change it. Blocks, call off the UI thread.
+     *
+     * @return "Enforcing", "Permissive" or "Disabled", empty if the guest isn't ready.
+     */
+    public static native String getGuestSelinuxMode();
+
+    /**
+     * @return the latest SELinux denials guest services logged, oldest first. Denials of the
+     * kernel go to the host audit log and aren't included. Blocks, call off the UI thread.
+     */
+    public static native String[] getRecentSelinuxDenials();
+
+    /**
+     * Maps ESC to BACK, Super to HOME and Alt+Tab to the recents in the guest, other keys
+     * are forwarded as they are. Off by default.
+     */
+    public static native void setKeyboardShortcuts(boolean enabled);
+
+    /**
+     * Extracts a .tar or .tar.gz rootfs into {@code dest}, reporting to {@link Callback#onExtractProgress}.
+     * A partial extraction is redone, a finished one of the same archive is skipped. Blocks,
+     * call off the UI thread.
+     */
+    public static native boolean extractRootfs(String archive, String dest);
+
+    /**
+     * On an HDR surface, tonemaps the SDR guest output into the HDR range instead of switching
+     * the surface back to sRGB, which is the default and keeps colors from looking washed out.
+     */
+    public static native void setSdrToHdrTonemap(boolean enabled);
+
+    public static final int CPU_LIMIT_SUCCESS = 0;
+    public static final int CPU_LIMIT_INVALID_ARGS = 1;
+    public static final int CPU_LIMIT_GUEST_NOT_RUNNING = 2;
+    public static final int CPU_LIMIT_APP_NOT_RUNNING = 3;
+    public static final int CPU_LIMIT_CGROUP_UNAVAILABLE = 4;
+    public static final int CPU_LIMIT_FAILED = 5;
+
+    /**
+     * Caps a running guest app at {@code percent} of one core, 0 lifts the cap. Processes the app
+     * starts later are capped as well. The guest cpu cgroups are the host's, which the app can't
+     * write without root on the host, so expect CPU_LIMIT_CGROUP_UNAVAILABLE there.
+     * Blocks, call off the UI thread.
+     *
+     * @return one of the CPU_LIMIT_* codes
+     */
+    public static native int setGuestAppCpuLimit(String packageName, int percent);
+
+    /**
+     * Syncs the guest storage and lets the guest exit on its own, killing it after {@code timeoutMs}
+     * (at most 30 s). Blocks, call off the UI thread.
+     * @return false if the guest had to be killed
+     */
+    public static native boolean stopContainerGraceful(int timeoutMs);
+
+    // called from native
+
+    static void onGuestVibrate(long durationMs) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onGuestVibrate(durationMs);
+        }
+    }
+
+    static void onGuestVibrateWaveform(long[] timings, int[] amplitudes) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onGuestVibrateWaveform(timings, amplitudes);
+        }
+    }
+
+    static void onRenderError(int code, String message) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onRenderError(code, message);
+        }
+    }
+
+    static void onGuestLowMemory(int level) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onGuestLowMemory(level);
+        }
+    }
+
+    static void onHostGesture(int gesture) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onHostGesture(gesture);
+        }
+    }
+
+    static void onStartCameraForward(int cameraId, int width, int height) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onStartCameraForward(cameraId, width, height);
+        }
+    }
+
+    static void onStopCameraForward(int cameraId) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onStopCameraForward(cameraId);
+        }
+    }
+
+    static String loadApkLabel(String apkPath) {
+        Callback callback = sCallback;
+        return callback != null ? callback.loadApkLabel(apkPath) : null;
+    }
+
+    static void onDiagnosticsExported(String path, boolean success) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onDiagnosticsExported(path, success);
+        }
+    }
+
+    static void onHostHome() {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onHostHome();
+        }
+    }
+
+    static void onGuestRecovered(int action) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onGuestRecovered(action);
+        }
+    }
+
+    static void onGuestStarted(int status) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onGuestStarted(status);
+        }
+    }
+
+    static void onExtractProgress(int percent, long bytes) {
+        Callback callback = sCallback;
+        if (callback != null) {
+            callback.onExtractProgress(percent, bytes);
+        }
+    }
+
+    static float hostFontScale() {
+        return Resources.getSystem().getConfiguration().fontScale;
+    }
+
+    // the splash is uploaded as is, keep it within a texture the size of a large screen
+    private static final int MAX_SPLASH_SIZE = 2048;
+
+    /**
+     * @return {@code [width, height, argb...]} of the image at {@code path}, null if it can't be decoded.
+     */
+    static int[] decodeSplash(String path) {
+        BitmapFactory.Options options = new BitmapFactory.Options();
+        options.inJustDecodeBounds = true;
+        BitmapFactory.decodeFile(path, options);
+        options.inJustDecodeBounds = false;
+        options.inSampleSize = 1;
+        while (options.outWidth / options.inSampleSize > MAX_SPLASH_SIZE
+                || options.outHeight / options.inSampleSize > MAX_SPLASH_SIZE) {
+            options.inSampleSize *= 2;
+        }
+
+        Bitmap bitmap = BitmapFactory.decodeFile(path, options);
+        if (bitmap == null) {
+            return null;
+        }
+        int width = bitmap.getWidth();
+        int height = bitmap.getHeight();
+        int[] pixels = new int[2 + width * height];
+        pixels[0] = width;
+        pixels[1] = height;
+        bitmap.getPixels(pixels, 2, width, 0, 0, width, height);
+        bitmap.recycle();
+        return pixels;
+    }
 }